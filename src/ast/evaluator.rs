@@ -1,29 +1,560 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    mem,
+    rc::Rc,
+};
 
 use crate::ast::{
-    lexer::TextSpan, ASTBinaryExpression, ASTBinaryOperatorKind, ASTExpression, ASTLetStatement,
-    ASTNumberExpression, ASTParenthesizedExpression, ASTVariableExpression, ASTVisitor,
+    lexer::{TextSpan, Token},
+    scopes::Scopes,
+    type_checker::TypeChecker,
+    types::Type,
+    ASTAssignmentExpression, ASTBinaryExpression, ASTBinaryOperator, ASTBinaryOperatorKind,
+    ASTBlockStatement, ASTBooleanExpression, ASTBreakStatement, ASTCallExpression,
+    ASTCastExpression, ASTContinueStatement, ASTExpression, ASTExpressionId, ASTFloatExpression,
+    ASTFunctionDeclaration, ASTIfStatement, ASTLetStatement, ASTNumberExpression,
+    ASTParenthesizedExpression, ASTReturnStatement, ASTStatement, ASTStringExpression,
+    ASTUnaryExpression, ASTUnaryOperator, ASTUnaryOperatorKind, ASTVariableExpression, ASTVisitor,
+    ASTWhileStatement, Ast,
 };
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Str(String),
+}
+
+impl Value {
+    pub fn ty(&self) -> Type {
+        match self {
+            Value::Integer(_) => Type::Integer,
+            Value::Float(_) => Type::Float,
+            Value::Boolean(_) => Type::Boolean,
+            Value::Str(_) => Type::String,
+        }
+    }
+
+    /// The type of the value followed by the value itself, like `<int> (3)`, for messages
+    /// about values of the wrong type. Strings are quoted.
+    pub fn describe(&self) -> String {
+        match self {
+            Value::Str(value) => format!("<{}> ({:?})", self.ty(), value),
+            value => format!("<{}> ({})", self.ty(), value),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Integer(value) => write!(f, "{}", value),
+            // Floats always show a fraction or an exponent, so that they read as floats.
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Str(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    IntegerOverflow {
+        operator: String,
+    },
+    InvalidOperands {
+        operator: String,
+        operands: Vec<Value>,
+    },
+    /// A float that is not a number was cast to an int, which has no value for it.
+    NaNCast,
+    NonBooleanCondition {
+        found: Value,
+    },
+    /// A value of unknown type was not of the type its destination requires.
+    TypeMismatch {
+        expected: Type,
+        found: Value,
+    },
+    /// An expression that produces no value was used where one is needed.
+    MissingValue,
+    UndefinedVariable {
+        name: String,
+    },
+    UndefinedFunction {
+        name: String,
+    },
+    StackOverflow {
+        function: String,
+    },
+    /// The tree still contains a node the parser could not make sense of.
+    InvalidSyntax,
+}
+
+impl Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeErrorKind::DivisionByZero => write!(f, "Division by zero"),
+            RuntimeErrorKind::IntegerOverflow { operator } => write!(
+                f,
+                "Integer overflow: the result of '{}' does not fit in <int>",
+                operator
+            ),
+            RuntimeErrorKind::InvalidOperands { operator, operands } => {
+                let operands: Vec<String> = operands.iter().map(Value::describe).collect();
+                write!(
+                    f,
+                    "Operator '{}' cannot be applied to {}",
+                    operator,
+                    operands.join(" and ")
+                )
+            }
+            RuntimeErrorKind::NaNCast => write!(f, "Cannot convert NaN to <int>"),
+            RuntimeErrorKind::NonBooleanCondition { found } => {
+                write!(
+                    f,
+                    "Expected a boolean condition, found {}",
+                    found.describe()
+                )
+            }
+            RuntimeErrorKind::TypeMismatch { expected, found } => {
+                write!(
+                    f,
+                    "Expected a value of type <{}>, found {}",
+                    expected,
+                    found.describe()
+                )
+            }
+            RuntimeErrorKind::MissingValue => write!(f, "Expression does not produce a value"),
+            RuntimeErrorKind::UndefinedVariable { name } => {
+                write!(f, "Variable '{}' is not defined", name)
+            }
+            RuntimeErrorKind::UndefinedFunction { name } => {
+                write!(f, "Function '{}' is not defined", name)
+            }
+            RuntimeErrorKind::StackOverflow { function } => {
+                write!(f, "Stack overflow while calling '{}'", function)
+            }
+            RuntimeErrorKind::InvalidSyntax => write!(f, "Cannot evaluate invalid syntax"),
+        }
+    }
+}
+
+/// An error that stops evaluation, with the calls that were active when it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: TextSpan,
+    /// Innermost call last.
+    pub stack_trace: Vec<CallFrame>,
+}
+
+/// A pending jump out of the statements currently being evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlFlow {
+    Break,
+    Continue,
+    /// The returned value, if any, is held in `last_value`.
+    Return,
+    /// Nothing else is evaluated once an error happened.
+    Error(RuntimeError),
+}
+
+/// How integer operations that overflow `i64` behave.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArithmeticMode {
+    /// Overflow is a runtime error.
+    Checked,
+    /// Results wrap around in two's complement.
+    Wrapping,
+}
+
+/// Calls nested deeper than this abort evaluation instead of overflowing the host stack.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallFrame {
+    pub function: String,
+    pub call_site: TextSpan,
+}
+
+/// A declared function, with the globals its body can see.
+#[derive(Clone)]
+struct Function {
+    declaration: Rc<ASTFunctionDeclaration>,
+    globals: Rc<HashMap<String, usize>>,
+}
+
+#[derive(Clone)]
 pub struct ASTEvaluator {
-    pub last_value: Option<i64>,
-    pub variables: HashMap<String, i64>,
+    pub last_value: Option<Value>,
+    /// The local variables. Globals live in `globals` instead.
+    pub variables: Scopes<Value>,
+    /// The value of every global declared so far. Declaring a name again gives it a new slot,
+    /// so that functions declared before keep using the one they saw.
+    globals: Vec<Value>,
+    /// The slot of each global visible to the code being evaluated.
+    global_slots: Rc<HashMap<String, usize>>,
+    functions: HashMap<String, Function>,
+    /// See `TypeChecker::runtime_checks`.
+    runtime_checks: HashMap<ASTExpressionId, Type>,
+    pub call_stack: Vec<CallFrame>,
+    arithmetic: ArithmeticMode,
+    control_flow: Option<ControlFlow>,
 }
 
 impl ASTEvaluator {
-    pub fn new() -> Self {
+    pub fn new(arithmetic: ArithmeticMode) -> Self {
         Self {
             last_value: None,
-            variables: HashMap::new(),
+            variables: Scopes::new(),
+            globals: Vec::new(),
+            global_slots: Rc::new(HashMap::new()),
+            functions: HashMap::new(),
+            runtime_checks: HashMap::new(),
+            call_stack: Vec::new(),
+            arithmetic,
+            control_flow: None,
+        }
+    }
+
+    /// Evaluates every statement of `ast`, which was checked by `types`, and returns the value
+    /// of the last one.
+    pub fn evaluate(
+        &mut self,
+        ast: &Ast,
+        types: &TypeChecker,
+    ) -> Result<Option<Value>, RuntimeError> {
+        self.runtime_checks.clone_from(&types.runtime_checks);
+        self.last_value = None;
+        ast.visit(self);
+        match self.control_flow.take() {
+            Some(ControlFlow::Error(error)) => Err(error),
+            _ => Ok(self.last_value.clone()),
+        }
+    }
+
+    /// Every global visible at the top level, sorted by name.
+    pub fn globals(&self) -> Vec<(&String, &Value)> {
+        let mut globals: Vec<_> = self
+            .global_slots
+            .iter()
+            .map(|(name, slot)| (name, &self.globals[*slot]))
+            .collect();
+        globals.sort_by(|(left, _), (right, _)| left.cmp(right));
+        globals
+    }
+
+    fn variable(&self, name: &str) -> Option<&Value> {
+        self.variables
+            .lookup(name)
+            .or_else(|| Some(&self.globals[*self.global_slots.get(name)?]))
+    }
+
+    fn variable_mut(&mut self, name: &str) -> Option<&mut Value> {
+        if self.variables.lookup(name).is_some() {
+            return self.variables.lookup_mut(name);
+        }
+        let slot = *self.global_slots.get(name)?;
+        self.globals.get_mut(slot)
+    }
+
+    fn fail(&mut self, kind: RuntimeErrorKind, span: TextSpan) {
+        self.control_flow = Some(ControlFlow::Error(RuntimeError {
+            kind,
+            span,
+            stack_trace: self.call_stack.clone(),
+        }));
+    }
+
+    /// Evaluates `expression` to a value. Returns `None` if evaluation has to stop instead,
+    /// either because of a jump or because of an error.
+    fn evaluate_expression(&mut self, expression: &ASTExpression) -> Option<Value> {
+        self.visit_expression(expression);
+        if self.control_flow.is_some() {
+            return None;
+        }
+        if self.last_value.is_none() {
+            self.fail(RuntimeErrorKind::MissingValue, expression.span());
+        }
+        self.last_value.clone()
+    }
+
+    fn evaluate_condition(&mut self, condition: &ASTExpression) -> Option<bool> {
+        match self.evaluate_expression(condition)? {
+            Value::Boolean(value) => Some(value),
+            found => {
+                self.fail(
+                    RuntimeErrorKind::NonBooleanCondition { found },
+                    condition.span(),
+                );
+                None
+            }
+        }
+    }
+
+    fn evaluate_unary_operator(
+        &mut self,
+        operator: &ASTUnaryOperator,
+        operand: Value,
+    ) -> Option<Value> {
+        let symbol = &operator.token.span.literal;
+        match apply_unary_operator(operator.kind, symbol, operand, self.arithmetic) {
+            Ok(value) => Some(value),
+            Err(kind) => {
+                self.fail(kind, operator.token.span.clone());
+                None
+            }
+        }
+    }
+
+    fn evaluate_binary_operator(
+        &mut self,
+        operator: &ASTBinaryOperator,
+        left: Value,
+        right: Value,
+    ) -> Option<Value> {
+        let symbol = &operator.token.span.literal;
+        match apply_binary_operator(operator.kind, symbol, left, right, self.arithmetic) {
+            Ok(value) => Some(value),
+            Err(kind) => {
+                self.fail(kind, operator.token.span.clone());
+                None
+            }
         }
     }
 }
 
+/// Applies a unary operator written as `symbol`. Every backend goes through this function,
+/// so that they all agree on results and errors.
+pub fn apply_unary_operator(
+    operator: ASTUnaryOperatorKind,
+    symbol: &str,
+    operand: Value,
+    arithmetic: ArithmeticMode,
+) -> Result<Value, RuntimeErrorKind> {
+    match (operator, operand) {
+        (ASTUnaryOperatorKind::Minus, Value::Integer(operand)) => integer_result(
+            symbol,
+            operand.checked_neg(),
+            operand.wrapping_neg(),
+            arithmetic,
+        ),
+        (ASTUnaryOperatorKind::Minus, Value::Float(operand)) => Ok(Value::Float(-operand)),
+        (ASTUnaryOperatorKind::LogicalNot, Value::Boolean(operand)) => Ok(Value::Boolean(!operand)),
+        (ASTUnaryOperatorKind::BitwiseNot, Value::Integer(operand)) => Ok(Value::Integer(!operand)),
+        (_, operand) => Err(RuntimeErrorKind::InvalidOperands {
+            operator: symbol.to_string(),
+            operands: vec![operand],
+        }),
+    }
+}
+
+/// Applies a binary operator written as `symbol`, see `apply_unary_operator`.
+pub fn apply_binary_operator(
+    operator: ASTBinaryOperatorKind,
+    symbol: &str,
+    left: Value,
+    right: Value,
+    arithmetic: ArithmeticMode,
+) -> Result<Value, RuntimeErrorKind> {
+    let value = match (operator, &left, &right) {
+        (ASTBinaryOperatorKind::Plus, Value::Integer(left), Value::Integer(right)) => {
+            return integer_result(
+                symbol,
+                left.checked_add(*right),
+                left.wrapping_add(*right),
+                arithmetic,
+            )
+        }
+        (ASTBinaryOperatorKind::Plus, Value::Str(left), Value::Str(right)) => {
+            Value::Str(format!("{}{}", left, right))
+        }
+        (ASTBinaryOperatorKind::Minus, Value::Integer(left), Value::Integer(right)) => {
+            return integer_result(
+                symbol,
+                left.checked_sub(*right),
+                left.wrapping_sub(*right),
+                arithmetic,
+            )
+        }
+        (ASTBinaryOperatorKind::Multiply, Value::Integer(left), Value::Integer(right)) => {
+            return integer_result(
+                symbol,
+                left.checked_mul(*right),
+                left.wrapping_mul(*right),
+                arithmetic,
+            )
+        }
+        (ASTBinaryOperatorKind::Divide, Value::Integer(left), Value::Integer(right)) => {
+            if *right == 0 {
+                return Err(RuntimeErrorKind::DivisionByZero);
+            }
+            return integer_result(
+                symbol,
+                left.checked_div(*right),
+                left.wrapping_div(*right),
+                arithmetic,
+            );
+        }
+        (_, Value::Float(left), Value::Float(right)) => {
+            return float_result(operator, *left, *right).ok_or_else(|| {
+                RuntimeErrorKind::InvalidOperands {
+                    operator: symbol.to_string(),
+                    operands: vec![Value::Float(*left), Value::Float(*right)],
+                }
+            })
+        }
+        (ASTBinaryOperatorKind::Equals, Value::Integer(left), Value::Integer(right)) => {
+            Value::Boolean(left == right)
+        }
+        (ASTBinaryOperatorKind::Equals, Value::Boolean(left), Value::Boolean(right)) => {
+            Value::Boolean(left == right)
+        }
+        (ASTBinaryOperatorKind::Equals, Value::Str(left), Value::Str(right)) => {
+            Value::Boolean(left == right)
+        }
+        (ASTBinaryOperatorKind::NotEquals, Value::Integer(left), Value::Integer(right)) => {
+            Value::Boolean(left != right)
+        }
+        (ASTBinaryOperatorKind::NotEquals, Value::Boolean(left), Value::Boolean(right)) => {
+            Value::Boolean(left != right)
+        }
+        (ASTBinaryOperatorKind::NotEquals, Value::Str(left), Value::Str(right)) => {
+            Value::Boolean(left != right)
+        }
+        (ASTBinaryOperatorKind::LessThan, Value::Integer(left), Value::Integer(right)) => {
+            Value::Boolean(left < right)
+        }
+        (ASTBinaryOperatorKind::LessThanOrEqual, Value::Integer(left), Value::Integer(right)) => {
+            Value::Boolean(left <= right)
+        }
+        (ASTBinaryOperatorKind::GreaterThan, Value::Integer(left), Value::Integer(right)) => {
+            Value::Boolean(left > right)
+        }
+        (
+            ASTBinaryOperatorKind::GreaterThanOrEqual,
+            Value::Integer(left),
+            Value::Integer(right),
+        ) => Value::Boolean(left >= right),
+        (ASTBinaryOperatorKind::LogicalAnd, Value::Boolean(left), Value::Boolean(right)) => {
+            Value::Boolean(*left && *right)
+        }
+        (ASTBinaryOperatorKind::LogicalOr, Value::Boolean(left), Value::Boolean(right)) => {
+            Value::Boolean(*left || *right)
+        }
+        _ => {
+            return Err(RuntimeErrorKind::InvalidOperands {
+                operator: symbol.to_string(),
+                operands: vec![left, right],
+            })
+        }
+    };
+    Ok(value)
+}
+
+/// Applies an arithmetic or comparison operator to two floats. Float arithmetic follows IEEE
+/// 754: it never fails, dividing by zero gives an infinity or NaN.
+fn float_result(operator: ASTBinaryOperatorKind, left: f64, right: f64) -> Option<Value> {
+    let value = match operator {
+        ASTBinaryOperatorKind::Plus => Value::Float(left + right),
+        ASTBinaryOperatorKind::Minus => Value::Float(left - right),
+        ASTBinaryOperatorKind::Multiply => Value::Float(left * right),
+        ASTBinaryOperatorKind::Divide => Value::Float(left / right),
+        ASTBinaryOperatorKind::Equals => Value::Boolean(left == right),
+        ASTBinaryOperatorKind::NotEquals => Value::Boolean(left != right),
+        ASTBinaryOperatorKind::LessThan => Value::Boolean(left < right),
+        ASTBinaryOperatorKind::LessThanOrEqual => Value::Boolean(left <= right),
+        ASTBinaryOperatorKind::GreaterThan => Value::Boolean(left > right),
+        ASTBinaryOperatorKind::GreaterThanOrEqual => Value::Boolean(left >= right),
+        ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr => return None,
+    };
+    Some(value)
+}
+
+/// Converts `value` to `target` for an `as` cast. A float converts to the integer it
+/// truncates to; one that does not fit, or NaN, overflows.
+pub fn apply_cast(
+    value: Value,
+    target: Type,
+    arithmetic: ArithmeticMode,
+) -> Result<Value, RuntimeErrorKind> {
+    match (value, target) {
+        (Value::Integer(value), Type::Float) => Ok(Value::Float(value as f64)),
+        (Value::Float(value), Type::Integer) if value.is_nan() => Err(RuntimeErrorKind::NaNCast),
+        (Value::Float(value), Type::Integer) => {
+            // `i64::MAX as f64` rounds up to 2^63, which is already out of range.
+            let fits = value >= i64::MIN as f64 && value < i64::MAX as f64;
+            let checked = fits.then_some(value as i64);
+            integer_result("as", checked, value as i64, arithmetic)
+        }
+        (value @ Value::Integer(_), Type::Integer) | (value @ Value::Float(_), Type::Float) => {
+            Ok(value)
+        }
+        (value, _) => Err(RuntimeErrorKind::InvalidOperands {
+            operator: "as".to_string(),
+            operands: vec![value],
+        }),
+    }
+}
+
+/// Picks the result of an integer operation according to the arithmetic mode.
+/// `checked` is `None` when the operation overflowed.
+fn integer_result(
+    symbol: &str,
+    checked: Option<i64>,
+    wrapped: i64,
+    arithmetic: ArithmeticMode,
+) -> Result<Value, RuntimeErrorKind> {
+    match (checked, arithmetic) {
+        (Some(value), _) => Ok(Value::Integer(value)),
+        (None, ArithmeticMode::Wrapping) => Ok(Value::Integer(wrapped)),
+        (None, ArithmeticMode::Checked) => Err(RuntimeErrorKind::IntegerOverflow {
+            operator: symbol.to_string(),
+        }),
+    }
+}
+
 impl ASTVisitor for ASTEvaluator {
+    fn visit_expression(&mut self, expression: &ASTExpression) {
+        self.do_visit_expression(expression);
+        let Some(expected) = self.runtime_checks.get(&expression.id).copied() else {
+            return;
+        };
+        if self.control_flow.is_some() {
+            return;
+        }
+        match &self.last_value {
+            None => self.fail(RuntimeErrorKind::MissingValue, expression.span()),
+            Some(found) if found.ty() != expected => {
+                let found = found.clone();
+                self.fail(
+                    RuntimeErrorKind::TypeMismatch { expected, found },
+                    expression.span(),
+                );
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn visit_statement(&mut self, statement: &ASTStatement) {
+        if !matches!(self.control_flow, Some(ControlFlow::Error(_))) {
+            self.do_visit_statement(statement);
+        }
+    }
+
     fn visit_variable_expression(&mut self, expr: &ASTVariableExpression) {
-        let name = expr.token.span.literal.clone();
-        let value = self.variables.get(&name).unwrap();
-        self.last_value = Some(*value);
+        let name = &expr.token.span.literal;
+        match self.variable(name) {
+            Some(value) => self.last_value = Some(value.clone()),
+            None => self.fail(
+                RuntimeErrorKind::UndefinedVariable { name: name.clone() },
+                expr.token.span.clone(),
+            ),
+        }
     }
 
     fn visit_parenthesized_expression(&mut self, expr: &ASTParenthesizedExpression) {
@@ -31,31 +562,224 @@ impl ASTVisitor for ASTEvaluator {
     }
 
     fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
-        self.visit_expression(&let_statement.initializer);
+        let Some(value) = self.evaluate_expression(&let_statement.initializer) else {
+            return;
+        };
         let name = let_statement.identifier.span.literal.clone();
-        let value = self.last_value.unwrap();
-        self.variables.insert(name, value);
+        if self.variables.is_global() {
+            Rc::make_mut(&mut self.global_slots).insert(name, self.globals.len());
+            self.globals.push(value);
+        } else {
+            self.variables.declare(name, value);
+        }
+    }
+
+    fn visit_block_statement(&mut self, block: &ASTBlockStatement) {
+        self.last_value = None;
+        self.variables.enter_scope();
+        for statement in &block.statements {
+            self.visit_statement(statement);
+            if self.control_flow.is_some() {
+                break;
+            }
+        }
+        self.variables.exit_scope();
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        loop {
+            match self.evaluate_condition(&while_statement.condition) {
+                Some(true) => {}
+                Some(false) => break,
+                None => return,
+            }
+
+            self.visit_block_statement(&while_statement.body);
+            match self.control_flow {
+                Some(ControlFlow::Break) => {
+                    self.control_flow = None;
+                    break;
+                }
+                Some(ControlFlow::Continue) => self.control_flow = None,
+                Some(ControlFlow::Return | ControlFlow::Error(_)) => return,
+                None => {}
+            }
+        }
+        self.last_value = None;
+    }
+
+    fn visit_break_statement(&mut self, break_statement: &ASTBreakStatement) {
+        self.control_flow = Some(ControlFlow::Break);
+    }
+
+    fn visit_continue_statement(&mut self, continue_statement: &ASTContinueStatement) {
+        self.control_flow = Some(ControlFlow::Continue);
+    }
+
+    fn visit_function_declaration(&mut self, function: &Rc<ASTFunctionDeclaration>) {
+        self.functions.insert(
+            function.identifier.span.literal.clone(),
+            Function {
+                declaration: Rc::clone(function),
+                globals: Rc::clone(&self.global_slots),
+            },
+        );
+        self.last_value = None;
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        match &return_statement.return_value {
+            Some(return_value) => {
+                self.visit_expression(return_value);
+                if self.control_flow.is_some() {
+                    return;
+                }
+            }
+            None => self.last_value = None,
+        }
+        self.control_flow = Some(ControlFlow::Return);
+    }
+
+    fn visit_call_expression(&mut self, call: &ASTCallExpression) {
+        let mut arguments = Vec::with_capacity(call.arguments.len());
+        for argument in &call.arguments {
+            let Some(argument) = self.evaluate_expression(argument) else {
+                return;
+            };
+            arguments.push(argument);
+        }
+
+        let name = &call.identifier.span.literal;
+        let Some(function) = self.functions.get(name).cloned() else {
+            self.fail(
+                RuntimeErrorKind::UndefinedFunction { name: name.clone() },
+                call.identifier.span.clone(),
+            );
+            return;
+        };
+        if self.call_stack.len() >= MAX_CALL_DEPTH {
+            self.fail(
+                RuntimeErrorKind::StackOverflow {
+                    function: name.clone(),
+                },
+                call.span(),
+            );
+            return;
+        }
+        self.call_stack.push(CallFrame {
+            function: name.clone(),
+            call_site: call.span(),
+        });
+        let caller_globals = mem::replace(&mut self.global_slots, function.globals);
+        self.variables.enter_frame();
+        let declaration = function.declaration;
+        for (parameter, argument) in declaration.parameters.iter().zip(arguments) {
+            self.variables
+                .declare(parameter.identifier.span.literal.clone(), argument);
+        }
+        self.visit_block_statement(&declaration.body);
+        self.variables.exit_frame();
+        self.global_slots = caller_globals;
+        self.call_stack.pop();
+        if self.control_flow == Some(ControlFlow::Return) {
+            self.control_flow = None;
+        }
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        match self.evaluate_condition(&if_statement.condition) {
+            Some(true) => self.visit_block_statement(&if_statement.then_branch),
+            Some(false) => match &if_statement.else_branch {
+                Some(else_branch) => self.visit_statement(&else_branch.else_statement),
+                None => self.last_value = None,
+            },
+            None => {}
+        }
     }
 
     fn visit_number_expression(&mut self, number: &ASTNumberExpression) {
-        self.last_value = Some(number.number);
+        self.last_value = Some(Value::Integer(number.number));
+    }
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
+        self.last_value = Some(Value::Boolean(boolean.value));
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        self.last_value = Some(Value::Str(string.value.clone()));
+    }
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        self.last_value = Some(Value::Float(float.number));
+    }
+
+    fn visit_cast_expression(&mut self, cast: &ASTCastExpression) {
+        let Some(value) = self.evaluate_expression(&cast.expression) else {
+            return;
+        };
+        let target = Type::from_name(&cast.type_name.span.literal).unwrap_or(Type::Unknown);
+        self.last_value = match apply_cast(value, target, self.arithmetic) {
+            Ok(value) => Some(value),
+            Err(kind) => {
+                self.fail(kind, cast.as_keyword.span.clone());
+                None
+            }
+        };
     }
 
     fn visit_binary_expression(&mut self, expr: &ASTBinaryExpression) {
-        self.visit_expression(&expr.left);
-        let left = self.last_value.unwrap();
-        self.visit_expression(&expr.right);
-        let right = self.last_value.unwrap();
+        let Some(left) = self.evaluate_expression(&expr.left) else {
+            return;
+        };
+
+        // `&&` and `||` only evaluate their right operand when the left one does not decide the result.
+        match (&expr.operator.kind, &left) {
+            (ASTBinaryOperatorKind::LogicalAnd, Value::Boolean(false))
+            | (ASTBinaryOperatorKind::LogicalOr, Value::Boolean(true)) => {
+                self.last_value = Some(left);
+                return;
+            }
+            _ => {}
+        }
+
+        let Some(right) = self.evaluate_expression(&expr.right) else {
+            return;
+        };
+        self.last_value = self.evaluate_binary_operator(&expr.operator, left, right);
+    }
+
+    fn visit_unary_expression(&mut self, expr: &ASTUnaryExpression) {
+        let Some(operand) = self.evaluate_expression(&expr.operand) else {
+            return;
+        };
+        self.last_value = self.evaluate_unary_operator(&expr.operator, operand);
+    }
 
-        match expr.operator.kind {
-            ASTBinaryOperatorKind::Plus => self.last_value = Some(left + right),
-            ASTBinaryOperatorKind::Minus => self.last_value = Some(left - right),
-            ASTBinaryOperatorKind::Multiply => self.last_value = Some(left * right),
-            ASTBinaryOperatorKind::Divide => self.last_value = Some(left / right),
+    fn visit_assignment_expression(&mut self, assignment: &ASTAssignmentExpression) {
+        let Some(mut value) = self.evaluate_expression(&assignment.value) else {
+            return;
+        };
+        let name = &assignment.identifier.span.literal;
+        let Some(current) = self.variable(name).cloned() else {
+            self.fail(
+                RuntimeErrorKind::UndefinedVariable { name: name.clone() },
+                assignment.identifier.span.clone(),
+            );
+            return;
+        };
+        if let Some(operator) = assignment.operator.binary_operator() {
+            value = match self.evaluate_binary_operator(&operator, current, value) {
+                Some(value) => value,
+                None => return,
+            };
+        }
+        if let Some(variable) = self.variable_mut(name) {
+            *variable = value.clone();
         }
+        self.last_value = Some(value);
     }
 
     fn visit_error(&mut self, span: &TextSpan) {
-        todo!()
+        self.fail(RuntimeErrorKind::InvalidSyntax, span.clone());
     }
 }
@@ -1,8 +1,13 @@
 use std::fmt::{Display, Formatter};
 
+use crate::diagnostics::DiagnosticsBagCell;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenKind {
     Number(i64),
+    Float(f64),
+    /// A string literal, holding its value with the escape sequences resolved.
+    String(String),
     Plus,
     Minus,
     Asterisk,
@@ -12,16 +17,63 @@ pub enum TokenKind {
     EOF,
     Bad,
     Whitespace,
+    /// Ends a statement, unless what comes before it needs to be continued.
+    Newline,
+    Semicolon,
+    /// A `//` line comment or a `/* */` block comment.
+    Comment,
+    /// A `///` comment, which documents the `let` or `fn` that follows it.
+    DocComment,
     // First variables
     Let,
     Identifier,
     Equals,
+    // Booleans and comparisons
+    True,
+    False,
+    EqualsEquals,
+    BangEquals,
+    LessThan,
+    LessThanEquals,
+    GreaterThan,
+    GreaterThanEquals,
+    AmpersandAmpersand,
+    PipePipe,
+    // Unary operators
+    Bang,
+    Tilde,
+    // Blocks
+    OpenBrace,
+    CloseBrace,
+    // Conditionals
+    If,
+    Else,
+    // Loops
+    While,
+    Break,
+    Continue,
+    As,
+    // Compound assignment
+    PlusEquals,
+    MinusEquals,
+    AsteriskEquals,
+    SlashEquals,
+    Mut,
+    // Functions
+    Fn,
+    Return,
+    Comma,
+    // Type annotations
+    Colon,
+    Arrow,
 }
 
 impl Display for TokenKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             TokenKind::Number(_) => write!(f, "Number"),
+            TokenKind::Float(_) => write!(f, "Float"),
+            TokenKind::String(_) => write!(f, "String"),
             TokenKind::Plus => write!(f, "+"),
             TokenKind::Minus => write!(f, "-"),
             TokenKind::Asterisk => write!(f, "*"),
@@ -31,9 +83,43 @@ impl Display for TokenKind {
             TokenKind::EOF => write!(f, "EOF"),
             TokenKind::Bad => write!(f, "Bad"),
             TokenKind::Whitespace => write!(f, "Whitespace"),
+            TokenKind::Newline => write!(f, "newline"),
+            TokenKind::Semicolon => write!(f, ";"),
+            TokenKind::Comment => write!(f, "Comment"),
+            TokenKind::DocComment => write!(f, "DocComment"),
             TokenKind::Let => write!(f, "let"),
             TokenKind::Identifier => write!(f, "Identifier"),
             TokenKind::Equals => write!(f, "="),
+            TokenKind::True => write!(f, "true"),
+            TokenKind::False => write!(f, "false"),
+            TokenKind::EqualsEquals => write!(f, "=="),
+            TokenKind::BangEquals => write!(f, "!="),
+            TokenKind::LessThan => write!(f, "<"),
+            TokenKind::LessThanEquals => write!(f, "<="),
+            TokenKind::GreaterThan => write!(f, ">"),
+            TokenKind::GreaterThanEquals => write!(f, ">="),
+            TokenKind::AmpersandAmpersand => write!(f, "&&"),
+            TokenKind::PipePipe => write!(f, "||"),
+            TokenKind::Bang => write!(f, "!"),
+            TokenKind::Tilde => write!(f, "~"),
+            TokenKind::OpenBrace => write!(f, "{{"),
+            TokenKind::CloseBrace => write!(f, "}}"),
+            TokenKind::If => write!(f, "if"),
+            TokenKind::Else => write!(f, "else"),
+            TokenKind::While => write!(f, "while"),
+            TokenKind::Break => write!(f, "break"),
+            TokenKind::Continue => write!(f, "continue"),
+            TokenKind::As => write!(f, "as"),
+            TokenKind::PlusEquals => write!(f, "+="),
+            TokenKind::MinusEquals => write!(f, "-="),
+            TokenKind::AsteriskEquals => write!(f, "*="),
+            TokenKind::SlashEquals => write!(f, "/="),
+            TokenKind::Mut => write!(f, "mut"),
+            TokenKind::Fn => write!(f, "fn"),
+            TokenKind::Return => write!(f, "return"),
+            TokenKind::Comma => write!(f, ","),
+            TokenKind::Colon => write!(f, ":"),
+            TokenKind::Arrow => write!(f, "->"),
         }
     }
 }
@@ -57,6 +143,25 @@ impl TextSpan {
     pub fn length(&self) -> usize {
         self.end - self.start
     }
+
+    /// Builds the smallest span covering all of `spans`. Gaps between them are filled with spaces.
+    pub fn combine(mut spans: Vec<TextSpan>) -> TextSpan {
+        spans.sort_by_key(|span| span.start);
+        let start = spans.first().unwrap().start;
+        let mut end = start;
+        let mut literal = String::new();
+        for span in &spans {
+            if span.start > end {
+                literal.push_str(&" ".repeat(span.start - end));
+            }
+            if span.end > end {
+                let overlap = end.saturating_sub(span.start);
+                literal.push_str(span.literal.get(overlap..).unwrap_or(""));
+                end = span.end;
+            }
+        }
+        TextSpan::new(start, end, literal)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -71,21 +176,33 @@ impl Token {
     }
 }
 
+/// Splits the input into tokens. Positions are byte offsets into the input.
 pub struct Lexer<'a> {
     input: &'a str,
     current_pos: usize,
+    diagnostics_bag: DiagnosticsBagCell,
 }
 
 impl<'a> Lexer<'a> {
-    pub fn new(input: &'a str) -> Self {
+    pub fn new(input: &'a str, diagnostics_bag: DiagnosticsBagCell) -> Self {
+        Self::starting_at(input, 0, diagnostics_bag)
+    }
+
+    /// Lexes `input` from byte `start` on. The spans stay relative to the whole input.
+    pub fn starting_at(input: &'a str, start: usize, diagnostics_bag: DiagnosticsBagCell) -> Self {
         Self {
             input,
-            current_pos: 0,
+            current_pos: start,
+            diagnostics_bag,
         }
     }
 
     fn current_char(&self) -> Option<char> {
-        self.input.chars().nth(self.current_pos)
+        self.input.get(self.current_pos..)?.chars().next()
+    }
+
+    fn next_char(&self) -> Option<char> {
+        self.input.get(self.current_pos..)?.chars().nth(1)
     }
 
     fn is_identifier_start(c: &char) -> bool {
@@ -106,10 +223,11 @@ impl<'a> Lexer<'a> {
 
         if self.current_pos == self.input.len() {
             let eof_char: char = '\0';
+            let position = self.current_pos;
             self.current_pos += 1;
             return Some(Token::new(
                 TokenKind::EOF,
-                TextSpan::new(0, 0, eof_char.to_string()),
+                TextSpan::new(position, position, eof_char.to_string()),
             ));
         }
 
@@ -119,8 +237,12 @@ impl<'a> Lexer<'a> {
             let mut kind = TokenKind::Bad;
 
             if Self::is_number_start(&c) {
-                let number: i64 = self.consume_number();
-                kind = TokenKind::Number(number);
+                kind = self.consume_number();
+            } else if c == '"' {
+                kind = TokenKind::String(self.consume_string());
+            } else if c == '\n' {
+                self.consume();
+                kind = TokenKind::Newline;
             } else if Self::is_whitespace(&c) {
                 self.consume();
                 kind = TokenKind::Whitespace;
@@ -128,6 +250,17 @@ impl<'a> Lexer<'a> {
                 let identifier = self.consume_identifier();
                 kind = match identifier.as_str() {
                     "let" => TokenKind::Let,
+                    "mut" => TokenKind::Mut,
+                    "fn" => TokenKind::Fn,
+                    "return" => TokenKind::Return,
+                    "true" => TokenKind::True,
+                    "false" => TokenKind::False,
+                    "if" => TokenKind::If,
+                    "else" => TokenKind::Else,
+                    "while" => TokenKind::While,
+                    "break" => TokenKind::Break,
+                    "continue" => TokenKind::Continue,
+                    "as" => TokenKind::As,
                     "=" => TokenKind::Equals,
                     _ => TokenKind::Identifier,
                 }
@@ -147,7 +280,7 @@ impl<'a> Lexer<'a> {
             return None;
         }
         let c = self.current_char();
-        self.current_pos += 1;
+        self.current_pos += c.map_or(1, char::len_utf8);
 
         c
     }
@@ -165,30 +298,297 @@ impl<'a> Lexer<'a> {
         identifier
     }
 
-    fn consume_number(&mut self) -> i64 {
-        let mut number: i64 = 0;
-        while let Some(c) = self.current_char() {
-            if c.is_digit(10) {
-                self.consume().unwrap();
-                number = number * 10 + c.to_digit(10).unwrap() as i64;
-            } else {
-                break;
+    /// Consumes an integer, or a float if a fraction or an exponent follows the digits.
+    fn consume_number(&mut self) -> TokenKind {
+        let start = self.current_pos;
+        self.consume_digits();
+        let mut is_float = false;
+        if self.current_char() == Some('.') && self.next_char().is_some_and(|c| c.is_digit(10)) {
+            self.consume();
+            self.consume_digits();
+            is_float = true;
+        }
+        if matches!(self.current_char(), Some('e' | 'E')) && self.has_exponent_digits() {
+            self.consume();
+            if matches!(self.current_char(), Some('+' | '-')) {
+                self.consume();
             }
+            self.consume_digits();
+            is_float = true;
+        }
+        let literal = &self.input[start..self.current_pos];
+        if is_float {
+            let number: f64 = literal.parse().unwrap();
+            if number.is_infinite() {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_float_literal_too_large(self.span_from(start));
+                return TokenKind::Float(0.0);
+            }
+            return TokenKind::Float(number);
+        }
+        match literal.parse() {
+            Ok(number) => TokenKind::Number(number),
+            // The magnitude of `i64::MIN` only fits once negated. The parser folds it into a
+            // unary minus and reports it anywhere else.
+            Err(_) if literal.parse::<u64>() == Ok(i64::MIN.unsigned_abs()) => {
+                TokenKind::Number(i64::MIN)
+            }
+            Err(_) => {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_integer_literal_too_large(self.span_from(start));
+                TokenKind::Number(0)
+            }
+        }
+    }
+
+    fn consume_digits(&mut self) {
+        while self.current_char().is_some_and(|c| c.is_digit(10)) {
+            self.consume();
+        }
+    }
+
+    /// Whether the `e` or `E` at the current position starts an exponent, that is, whether
+    /// digits follow it, with an optional sign in between.
+    fn has_exponent_digits(&self) -> bool {
+        let mut rest = self.input[self.current_pos + 1..].chars();
+        match rest.next() {
+            Some('+' | '-') => rest.next().is_some_and(|c| c.is_digit(10)),
+            c => c.is_some_and(|c| c.is_digit(10)),
         }
-        number
     }
 
     fn consume_punctuation(&mut self) -> TokenKind {
         let c = self.consume().unwrap();
         match c {
-            '+' => TokenKind::Plus,
-            '-' => TokenKind::Minus,
-            '/' => TokenKind::Slash,
-            '*' => TokenKind::Asterisk,
+            '+' => {
+                self.lex_potential_double_char_operator('=', TokenKind::Plus, TokenKind::PlusEquals)
+            }
+            '-' => match self.current_char() {
+                Some('>') => {
+                    self.consume();
+                    TokenKind::Arrow
+                }
+                _ => self.lex_potential_double_char_operator(
+                    '=',
+                    TokenKind::Minus,
+                    TokenKind::MinusEquals,
+                ),
+            },
+            '/' => match self.current_char() {
+                Some('/') => self.consume_line_comment(),
+                Some('*') => self.consume_block_comment(),
+                _ => self.lex_potential_double_char_operator(
+                    '=',
+                    TokenKind::Slash,
+                    TokenKind::SlashEquals,
+                ),
+            },
+            '*' => self.lex_potential_double_char_operator(
+                '=',
+                TokenKind::Asterisk,
+                TokenKind::AsteriskEquals,
+            ),
             '(' => TokenKind::LeftParen,
             ')' => TokenKind::RightParen,
-            '=' => TokenKind::Equals,
+            '=' => self.lex_potential_double_char_operator(
+                '=',
+                TokenKind::Equals,
+                TokenKind::EqualsEquals,
+            ),
+            '!' => {
+                self.lex_potential_double_char_operator('=', TokenKind::Bang, TokenKind::BangEquals)
+            }
+            '<' => self.lex_potential_double_char_operator(
+                '=',
+                TokenKind::LessThan,
+                TokenKind::LessThanEquals,
+            ),
+            '>' => self.lex_potential_double_char_operator(
+                '=',
+                TokenKind::GreaterThan,
+                TokenKind::GreaterThanEquals,
+            ),
+            '&' => self.lex_potential_double_char_operator(
+                '&',
+                TokenKind::Bad,
+                TokenKind::AmpersandAmpersand,
+            ),
+            '|' => {
+                self.lex_potential_double_char_operator('|', TokenKind::Bad, TokenKind::PipePipe)
+            }
+            '~' => TokenKind::Tilde,
+            ',' => TokenKind::Comma,
+            ';' => TokenKind::Semicolon,
+            ':' => TokenKind::Colon,
+            '{' => TokenKind::OpenBrace,
+            '}' => TokenKind::CloseBrace,
             _ => TokenKind::Bad,
         }
     }
+
+    /// Consumes a string literal and returns its value. A string ends at the end of its line
+    /// at the latest, so that a missing `"` does not swallow the rest of the input.
+    fn consume_string(&mut self) -> String {
+        let start = self.current_pos;
+        self.consume();
+        let mut value = String::new();
+        loop {
+            match self.current_char() {
+                Some('"') => {
+                    self.consume();
+                    break;
+                }
+                Some('\\') => {
+                    if let Some(c) = self.consume_escape() {
+                        value.push(c);
+                    }
+                }
+                None | Some('\n') => {
+                    let opening = TextSpan::new(start, start + 1, "\"".to_string());
+                    self.diagnostics_bag
+                        .borrow_mut()
+                        .report_unterminated_string(opening);
+                    break;
+                }
+                Some(c) => {
+                    self.consume();
+                    value.push(c);
+                }
+            }
+        }
+        value
+    }
+
+    /// Consumes an escape sequence, reporting it if it is invalid.
+    fn consume_escape(&mut self) -> Option<char> {
+        let start = self.current_pos;
+        self.consume();
+        let c = match self.current_char() {
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('"') => Some('"'),
+            Some('\\') => Some('\\'),
+            Some('u') => {
+                self.consume();
+                return self.consume_unicode_escape(start);
+            }
+            _ => None,
+        };
+        // A line break is left to end the string.
+        if self.current_char().is_some_and(|c| c != '\n') {
+            self.consume();
+        }
+        if c.is_none() {
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_invalid_escape(self.span_from(start));
+        }
+        c
+    }
+
+    /// Consumes the `{...}` of a `\u{...}` escape that starts at `start`.
+    fn consume_unicode_escape(&mut self, start: usize) -> Option<char> {
+        let mut c = None;
+        if self.current_char() == Some('{') {
+            self.consume();
+            let digits_start = self.current_pos;
+            while self
+                .current_char()
+                .is_some_and(|c| !matches!(c, '}' | '"' | '\n'))
+            {
+                self.consume();
+            }
+            let digits = &self.input[digits_start..self.current_pos];
+            let valid = (1..=6).contains(&digits.len())
+                && digits.chars().all(|digit| digit.is_ascii_hexdigit());
+            if self.current_char() == Some('}') {
+                self.consume();
+                if valid {
+                    c = u32::from_str_radix(digits, 16)
+                        .ok()
+                        .and_then(char::from_u32);
+                }
+            }
+        }
+        if c.is_none() {
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_invalid_escape(self.span_from(start));
+        }
+        c
+    }
+
+    fn span_from(&self, start: usize) -> TextSpan {
+        TextSpan::new(
+            start,
+            self.current_pos,
+            self.input[start..self.current_pos].to_string(),
+        )
+    }
+
+    /// Consumes the rest of a comment whose first `/` is consumed. `////` starts a plain
+    /// comment, like in Rust.
+    fn consume_line_comment(&mut self) -> TokenKind {
+        self.consume();
+        let kind = if self.current_char() == Some('/') && self.next_char() != Some('/') {
+            TokenKind::DocComment
+        } else {
+            TokenKind::Comment
+        };
+        while let Some(c) = self.current_char() {
+            if c == '\n' {
+                break;
+            }
+            self.consume();
+        }
+        kind
+    }
+
+    /// Consumes the rest of a block comment whose first `/` is consumed. Block comments nest.
+    fn consume_block_comment(&mut self) -> TokenKind {
+        let start = self.current_pos - 1;
+        self.consume();
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.current_char(), self.next_char()) {
+                (None, _) => {
+                    let opening = TextSpan::new(start, start + 2, "/*".to_string());
+                    self.diagnostics_bag
+                        .borrow_mut()
+                        .report_unterminated_block_comment(opening);
+                    break;
+                }
+                (Some('/'), Some('*')) => {
+                    self.consume();
+                    self.consume();
+                    depth += 1;
+                }
+                (Some('*'), Some('/')) => {
+                    self.consume();
+                    self.consume();
+                    depth -= 1;
+                }
+                _ => {
+                    self.consume();
+                }
+            }
+        }
+        TokenKind::Comment
+    }
+
+    fn lex_potential_double_char_operator(
+        &mut self,
+        expected: char,
+        one_char_kind: TokenKind,
+        double_char_kind: TokenKind,
+    ) -> TokenKind {
+        if self.current_char() == Some(expected) {
+            self.consume();
+            double_char_kind
+        } else {
+            one_char_kind
+        }
+    }
 }
@@ -0,0 +1,299 @@
+use std::rc::Rc;
+
+use crate::ast::{
+    lexer::TextSpan, scopes::Scopes, ASTAssignmentExpression, ASTBinaryExpression,
+    ASTBinaryOperatorKind, ASTBlockStatement, ASTBooleanExpression, ASTCallExpression,
+    ASTExpression, ASTExpressionKind, ASTFloatExpression, ASTFunctionDeclaration, ASTIfStatement,
+    ASTLetStatement, ASTNumberExpression, ASTStatement, ASTStatementKind, ASTStringExpression,
+    ASTVariableExpression, ASTVisitor, ASTWhileStatement, Ast,
+};
+use crate::diagnostics::DiagnosticsBagCell;
+
+struct Binding {
+    name: String,
+    declaration: TextSpan,
+    global: bool,
+    read: bool,
+    /// The last value stored in the binding that has not been read yet, with the region of
+    /// code that stored it.
+    pending_write: Option<(TextSpan, usize)>,
+}
+
+/// Warns about `let` bindings that are never read, values that are overwritten before being
+/// read and expression statements whose value is discarded.
+///
+/// Runs on checked trees only. Overwrites are only reported within straight-line code: a
+/// region ends wherever control flow may branch or a function may run.
+pub struct Linter {
+    bindings: Vec<Binding>,
+    variables: Scopes<usize>,
+    region: usize,
+    in_function: bool,
+    /// Whether the value of the next visited block is used.
+    block_value_used: bool,
+    diagnostics_bag: DiagnosticsBagCell,
+}
+
+impl Linter {
+    pub fn new(diagnostics_bag: DiagnosticsBagCell) -> Self {
+        Self {
+            bindings: Vec::new(),
+            variables: Scopes::new(),
+            region: 0,
+            in_function: false,
+            block_value_used: false,
+            diagnostics_bag,
+        }
+    }
+
+    pub fn lint(&mut self, ast: &Ast) {
+        // The value of the last top-level statement is the result of the program.
+        self.visit_statements(&ast.statements, true);
+        let globals: Vec<usize> = self
+            .variables
+            .visible()
+            .into_iter()
+            .map(|(_, binding)| *binding)
+            .collect();
+        for binding in globals {
+            self.check_read(binding);
+        }
+    }
+
+    fn visit_statements(&mut self, statements: &[ASTStatement], value_used: bool) {
+        for (index, statement) in statements.iter().enumerate() {
+            let used = value_used && index + 1 == statements.len();
+            match &statement.kind {
+                ASTStatementKind::Expression(expression) => {
+                    if !used && Self::is_discardable(expression) {
+                        self.diagnostics_bag
+                            .borrow_mut()
+                            .report_unused_value(expression.span());
+                    }
+                    self.visit_expression(expression);
+                }
+                ASTStatementKind::LetStatement(let_statement) => {
+                    self.visit_let_statement(let_statement);
+                    if used {
+                        let name = &let_statement.identifier.span.literal;
+                        if let Some(binding) = self.variables.lookup(name).copied() {
+                            self.read(binding);
+                        }
+                    }
+                }
+                ASTStatementKind::Block(block) => {
+                    self.block_value_used = used;
+                    self.visit_block_statement(block);
+                }
+                ASTStatementKind::If(if_statement) => {
+                    self.visit_if(if_statement, used);
+                }
+                _ => self.visit_statement(statement),
+            }
+        }
+    }
+
+    fn visit_if(&mut self, if_statement: &ASTIfStatement, value_used: bool) {
+        self.visit_expression(&if_statement.condition);
+        self.block_value_used = value_used;
+        self.visit_block_statement(&if_statement.then_branch);
+        if let Some(else_branch) = &if_statement.else_branch {
+            let else_statement = std::slice::from_ref(&*else_branch.else_statement);
+            self.visit_statements(else_statement, value_used);
+        }
+        self.region += 1;
+    }
+
+    /// Expressions that are only evaluated for their value. Calls and assignments have effects.
+    fn is_discardable(expression: &ASTExpression) -> bool {
+        match &expression.kind {
+            ASTExpressionKind::Number(_)
+            | ASTExpressionKind::Boolean(_)
+            | ASTExpressionKind::Float(_)
+            | ASTExpressionKind::String(_)
+            | ASTExpressionKind::Variable(_)
+            | ASTExpressionKind::Unary(_)
+            | ASTExpressionKind::Binary(_) => true,
+            ASTExpressionKind::Parenthesized(parenthesized) => {
+                Self::is_discardable(&parenthesized.expression)
+            }
+            ASTExpressionKind::Cast(cast) => Self::is_discardable(&cast.expression),
+            ASTExpressionKind::If(_)
+            | ASTExpressionKind::Assignment(_)
+            | ASTExpressionKind::Call(_)
+            | ASTExpressionKind::Error(_) => false,
+        }
+    }
+
+    fn declare(&mut self, name: &str, declaration: TextSpan, read: bool) {
+        let global = self.variables.is_global();
+        self.bindings.push(Binding {
+            name: name.to_string(),
+            declaration: declaration.clone(),
+            global,
+            read,
+            pending_write: Some((declaration, self.region)),
+        });
+        self.variables
+            .declare(name.to_string(), self.bindings.len() - 1);
+    }
+
+    /// Functions only read and write globals when they are called, which is accounted for at
+    /// each call instead.
+    fn is_global_in_function(&self, binding: usize) -> bool {
+        self.in_function && self.bindings[binding].global
+    }
+
+    fn read(&mut self, binding: usize) {
+        if !self.is_global_in_function(binding) {
+            self.bindings[binding].pending_write = None;
+        }
+        self.bindings[binding].read = true;
+    }
+
+    /// Records that `binding` is written at `span`, reporting the previous value if nothing
+    /// read it.
+    fn write(&mut self, binding: usize, span: TextSpan) {
+        if self.is_global_in_function(binding) {
+            return;
+        }
+        if let Some((previous, region)) = self.bindings[binding].pending_write.take() {
+            if region == self.region {
+                let name = self.bindings[binding].name.clone();
+                self.diagnostics_bag.borrow_mut().report_overwritten_value(
+                    &name,
+                    previous,
+                    span.clone(),
+                );
+            }
+        }
+        self.bindings[binding].pending_write = Some((span, self.region));
+    }
+
+    fn check_read(&mut self, binding: usize) {
+        let binding_ref = &self.bindings[binding];
+        if !binding_ref.read {
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_unused_variable(&binding_ref.name, binding_ref.declaration.clone());
+        }
+    }
+}
+
+impl ASTVisitor for Linter {
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        self.visit_expression(&let_statement.initializer);
+        let name = &let_statement.identifier.span.literal;
+        let declaration = let_statement.identifier.span.clone();
+        // A binding shadowed in its own scope can never be read again, unless a function that
+        // sees it was called since it was written.
+        if let Some(previous) = self.variables.lookup(name).copied() {
+            let binding = &self.bindings[previous];
+            if self.variables.is_declared_locally(name)
+                && !binding.read
+                && binding.pending_write.is_some()
+            {
+                self.bindings[previous].read = true;
+                let previous = self.bindings[previous].declaration.clone();
+                self.diagnostics_bag.borrow_mut().report_overwritten_value(
+                    name,
+                    previous,
+                    declaration.clone(),
+                );
+            }
+        }
+        self.declare(name, declaration, false);
+    }
+
+    fn visit_block_statement(&mut self, block: &ASTBlockStatement) {
+        let value_used = std::mem::replace(&mut self.block_value_used, false);
+        self.region += 1;
+        self.variables.enter_scope();
+        self.visit_statements(&block.statements, value_used);
+        for (_, binding) in self.variables.exit_scope() {
+            self.check_read(binding);
+        }
+        self.region += 1;
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        self.visit_if(if_statement, true);
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        // The condition runs again after the body, so it starts a region of its own.
+        self.region += 1;
+        self.visit_expression(&while_statement.condition);
+        self.visit_block_statement(&while_statement.body);
+    }
+
+    fn visit_function_declaration(&mut self, function: &Rc<ASTFunctionDeclaration>) {
+        let in_function = std::mem::replace(&mut self.in_function, true);
+        self.variables.enter_frame();
+        for parameter in &function.parameters {
+            let span = parameter.identifier.span.clone();
+            self.declare(&parameter.identifier.span.literal, span, true);
+        }
+        // Functions return the value of their body.
+        self.block_value_used = true;
+        self.visit_block_statement(&function.body);
+        self.variables.exit_frame();
+        self.in_function = in_function;
+    }
+
+    fn visit_variable_expression(&mut self, variable: &ASTVariableExpression) {
+        if let Some(binding) = self.variables.lookup(&variable.token.span.literal).copied() {
+            self.read(binding);
+        }
+    }
+
+    fn visit_assignment_expression(&mut self, assignment: &ASTAssignmentExpression) {
+        self.visit_expression(&assignment.value);
+        let name = &assignment.identifier.span.literal;
+        let Some(binding) = self.variables.lookup(name).copied() else {
+            return;
+        };
+        if assignment.operator.binary_operator().is_some() {
+            self.read(binding);
+        }
+        let span = TextSpan::combine(vec![
+            assignment.identifier.span.clone(),
+            assignment.value.span(),
+        ]);
+        self.write(binding, span);
+    }
+
+    fn visit_binary_expression(&mut self, binary: &ASTBinaryExpression) {
+        self.visit_expression(&binary.left);
+        if matches!(
+            binary.operator.kind,
+            ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr
+        ) {
+            // The right operand may not run.
+            self.region += 1;
+        }
+        self.visit_expression(&binary.right);
+    }
+
+    fn visit_call_expression(&mut self, call: &ASTCallExpression) {
+        for argument in &call.arguments {
+            self.visit_expression(argument);
+        }
+        // The function may read any global.
+        for binding in &mut self.bindings {
+            if binding.global {
+                binding.pending_write = None;
+            }
+        }
+    }
+
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {}
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {}
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {}
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {}
+
+    fn visit_error(&mut self, span: &TextSpan) {}
+}
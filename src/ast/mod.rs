@@ -1,9 +1,20 @@
+use std::{
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
 use lexer::{TextSpan, Token};
 use termion::color::{self, Fg, Reset};
 
 pub mod evaluator;
 pub mod lexer;
+pub mod linter;
+pub mod optimizer;
 pub mod parser;
+pub mod scopes;
+pub mod symbol_checker;
+pub mod type_checker;
+pub mod types;
 
 pub struct Ast {
     pub statements: Vec<ASTStatement>,
@@ -40,6 +51,19 @@ pub trait ASTVisitor {
             ASTStatementKind::LetStatement(let_statement) => {
                 self.visit_let_statement(let_statement)
             }
+            ASTStatementKind::Block(block) => self.visit_block_statement(block),
+            ASTStatementKind::If(if_statement) => self.visit_if_statement(if_statement),
+            ASTStatementKind::While(while_statement) => self.visit_while_statement(while_statement),
+            ASTStatementKind::Break(break_statement) => self.visit_break_statement(break_statement),
+            ASTStatementKind::Continue(continue_statement) => {
+                self.visit_continue_statement(continue_statement)
+            }
+            ASTStatementKind::FunctionDeclaration(function) => {
+                self.visit_function_declaration(function)
+            }
+            ASTStatementKind::Return(return_statement) => {
+                self.visit_return_statement(return_statement)
+            }
         }
     }
 
@@ -49,15 +73,59 @@ pub trait ASTVisitor {
 
     fn visit_let_statement(&mut self, let_statement: &ASTLetStatement);
 
+    fn visit_block_statement(&mut self, block: &ASTBlockStatement) {
+        for statement in &block.statements {
+            self.visit_statement(statement);
+        }
+    }
+
+    /// Visits both `if` statements and `if` expressions.
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        self.visit_expression(&if_statement.condition);
+        self.visit_block_statement(&if_statement.then_branch);
+        if let Some(else_branch) = &if_statement.else_branch {
+            self.visit_statement(&else_branch.else_statement);
+        }
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        self.visit_expression(&while_statement.condition);
+        self.visit_block_statement(&while_statement.body);
+    }
+
+    fn visit_break_statement(&mut self, break_statement: &ASTBreakStatement) {}
+
+    fn visit_continue_statement(&mut self, continue_statement: &ASTContinueStatement) {}
+
+    fn visit_function_declaration(&mut self, function: &Rc<ASTFunctionDeclaration>) {
+        self.visit_block_statement(&function.body);
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        if let Some(return_value) = &return_statement.return_value {
+            self.visit_expression(return_value);
+        }
+    }
+
     fn do_visit_expression(&mut self, expression: &ASTExpression) {
         match &expression.kind {
             ASTExpressionKind::Number(number) => self.visit_number_expression(number),
+            ASTExpressionKind::Float(float) => self.visit_float_expression(float),
+            ASTExpressionKind::Boolean(boolean) => self.visit_boolean_expression(boolean),
+            ASTExpressionKind::String(string) => self.visit_string_expression(string),
             ASTExpressionKind::Binary(binary) => self.visit_binary_expression(binary),
+            ASTExpressionKind::Unary(unary) => self.visit_unary_expression(unary),
             ASTExpressionKind::Parenthesized(parenthesized) => {
                 self.visit_parenthesized_expression(parenthesized)
             }
             ASTExpressionKind::Error(span) => self.visit_error(span),
             ASTExpressionKind::Variable(expr) => self.visit_variable_expression(expr),
+            ASTExpressionKind::If(if_expression) => self.visit_if_statement(if_expression),
+            ASTExpressionKind::Assignment(assignment) => {
+                self.visit_assignment_expression(assignment)
+            }
+            ASTExpressionKind::Call(call) => self.visit_call_expression(call),
+            ASTExpressionKind::Cast(cast) => self.visit_cast_expression(cast),
         }
     }
 
@@ -69,15 +137,39 @@ pub trait ASTVisitor {
 
     fn visit_number_expression(&mut self, number: &ASTNumberExpression);
 
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression);
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression);
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression);
+
     fn visit_binary_expression(&mut self, binary: &ASTBinaryExpression) {
         self.visit_expression(&binary.left);
         self.visit_expression(&binary.right);
     }
 
+    fn visit_unary_expression(&mut self, unary: &ASTUnaryExpression) {
+        self.visit_expression(&unary.operand);
+    }
+
+    fn visit_assignment_expression(&mut self, assignment: &ASTAssignmentExpression) {
+        self.visit_expression(&assignment.value);
+    }
+
+    fn visit_call_expression(&mut self, call: &ASTCallExpression) {
+        for argument in &call.arguments {
+            self.visit_expression(argument);
+        }
+    }
+
     fn visit_parenthesized_expression(&mut self, parenthesized: &ASTParenthesizedExpression) {
         self.visit_expression(&parenthesized.expression);
     }
 
+    fn visit_cast_expression(&mut self, cast: &ASTCastExpression) {
+        self.visit_expression(&cast.expression);
+    }
+
     fn visit_error(&mut self, span: &TextSpan);
 }
 
@@ -90,9 +182,12 @@ const LEVEL_INDENT: usize = 2;
 
 impl ASTPrinter {
     const NUMBER_COLOR: color::Yellow = color::Yellow;
+    const BOOLEAN_COLOR: color::Magenta = color::Magenta;
     const TEXT_COLOR: color::LightWhite = color::LightWhite;
     const KEYWORD_COLOR: color::Blue = color::Blue;
     const VARIABLE_COLOR: color::Green = color::Green;
+    const STRING_COLOR: color::Cyan = color::Cyan;
+    const COMMENT_COLOR: color::LightBlack = color::LightBlack;
     fn add_whitespace(&mut self) {
         self.result.push_str(" ")
     }
@@ -101,6 +196,51 @@ impl ASTPrinter {
         self.result.push_str("\n")
     }
 
+    /// Writes `value` as a string literal that lexes back to it.
+    fn quote(value: &str) -> String {
+        let mut literal = String::from('"');
+        for c in value.chars() {
+            match c {
+                '\n' => literal.push_str("\\n"),
+                '\t' => literal.push_str("\\t"),
+                '"' => literal.push_str("\\\""),
+                '\\' => literal.push_str("\\\\"),
+                c if c.is_control() => literal.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                c => literal.push(c),
+            }
+        }
+        literal.push('"');
+        literal
+    }
+
+    fn print_doc_comment(&mut self, doc_comment: &Option<ASTDocComment>) {
+        let Some(doc_comment) = doc_comment else {
+            return;
+        };
+        for line in &doc_comment.lines {
+            self.result.push_str(&format!(
+                "{}{}\n{}",
+                Self::COMMENT_COLOR.fg_str(),
+                line.span.literal.trim_end(),
+                " ".repeat(self.indent)
+            ));
+        }
+    }
+
+    fn print_type_annotation(&mut self, type_annotation: &ASTTypeAnnotation) {
+        self.result.push_str(&format!(
+            "{}{}",
+            Self::TEXT_COLOR.fg_str(),
+            type_annotation.separator.span.literal
+        ));
+        self.add_whitespace();
+        self.result.push_str(&format!(
+            "{}{}",
+            Self::KEYWORD_COLOR.fg_str(),
+            type_annotation.type_name.span.literal
+        ));
+    }
+
     pub fn new() -> Self {
         Self {
             indent: 0,
@@ -119,15 +259,24 @@ impl ASTVisitor for ASTPrinter {
     }
 
     fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        self.print_doc_comment(&let_statement.doc_comment);
         self.result
             .push_str(&format!("{}let", Self::KEYWORD_COLOR.fg_str()));
         self.add_whitespace();
+        if let_statement.is_mutable() {
+            self.result
+                .push_str(&format!("{}mut", Self::KEYWORD_COLOR.fg_str()));
+            self.add_whitespace();
+        }
 
         self.result.push_str(&format!(
             "{}{}",
             Self::TEXT_COLOR.fg_str(),
             let_statement.identifier.span.literal
         ));
+        if let Some(type_annotation) = &let_statement.type_annotation {
+            self.print_type_annotation(type_annotation);
+        }
 
         self.add_whitespace();
         self.result
@@ -141,11 +290,159 @@ impl ASTVisitor for ASTPrinter {
         self.result.push_str(&format!("{}\n", Fg(Reset)));
     }
 
+    fn visit_block_statement(&mut self, block: &ASTBlockStatement) {
+        self.result
+            .push_str(&format!("{}{{\n", Self::TEXT_COLOR.fg_str()));
+        self.indent += LEVEL_INDENT;
+        for statement in &block.statements {
+            self.result.push_str(&" ".repeat(self.indent));
+            self.visit_statement(statement);
+        }
+        self.indent -= LEVEL_INDENT;
+        self.result.push_str(&format!(
+            "{}{}}}",
+            " ".repeat(self.indent),
+            Self::TEXT_COLOR.fg_str()
+        ));
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        self.result
+            .push_str(&format!("{}if", Self::KEYWORD_COLOR.fg_str()));
+        self.add_whitespace();
+        self.visit_expression(&if_statement.condition);
+        self.add_whitespace();
+        self.visit_block_statement(&if_statement.then_branch);
+        if let Some(else_branch) = &if_statement.else_branch {
+            self.add_whitespace();
+            self.result
+                .push_str(&format!("{}else", Self::KEYWORD_COLOR.fg_str()));
+            self.add_whitespace();
+            self.do_visit_statement(&else_branch.else_statement);
+        }
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        self.result
+            .push_str(&format!("{}while", Self::KEYWORD_COLOR.fg_str()));
+        self.add_whitespace();
+        self.visit_expression(&while_statement.condition);
+        self.add_whitespace();
+        self.visit_block_statement(&while_statement.body);
+    }
+
+    fn visit_break_statement(&mut self, break_statement: &ASTBreakStatement) {
+        self.result
+            .push_str(&format!("{}break", Self::KEYWORD_COLOR.fg_str()));
+    }
+
+    fn visit_continue_statement(&mut self, continue_statement: &ASTContinueStatement) {
+        self.result
+            .push_str(&format!("{}continue", Self::KEYWORD_COLOR.fg_str()));
+    }
+
+    fn visit_function_declaration(&mut self, function: &Rc<ASTFunctionDeclaration>) {
+        self.print_doc_comment(&function.doc_comment);
+        self.result
+            .push_str(&format!("{}fn", Self::KEYWORD_COLOR.fg_str()));
+        self.add_whitespace();
+        self.result.push_str(&format!(
+            "{}{}(",
+            Self::TEXT_COLOR.fg_str(),
+            function.identifier.span.literal
+        ));
+        for (index, parameter) in function.parameters.iter().enumerate() {
+            if index > 0 {
+                self.result.push_str(", ");
+            }
+            self.result.push_str(&format!(
+                "{}{}{}",
+                Self::VARIABLE_COLOR.fg_str(),
+                parameter.identifier.span.literal,
+                Self::TEXT_COLOR.fg_str()
+            ));
+            if let Some(type_annotation) = &parameter.type_annotation {
+                self.print_type_annotation(type_annotation);
+            }
+        }
+        self.result.push(')');
+        if let Some(return_type) = &function.return_type {
+            self.add_whitespace();
+            self.print_type_annotation(return_type);
+        }
+        self.add_whitespace();
+        self.visit_block_statement(&function.body);
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        self.result
+            .push_str(&format!("{}return", Self::KEYWORD_COLOR.fg_str()));
+        if let Some(return_value) = &return_statement.return_value {
+            self.add_whitespace();
+            self.visit_expression(return_value);
+        }
+    }
+
+    fn visit_call_expression(&mut self, call: &ASTCallExpression) {
+        self.result.push_str(&format!(
+            "{}{}{}(",
+            Self::TEXT_COLOR.fg_str(),
+            call.identifier.span.literal,
+            Self::TEXT_COLOR.fg_str()
+        ));
+        for (index, argument) in call.arguments.iter().enumerate() {
+            if index > 0 {
+                self.result
+                    .push_str(&format!("{}, ", Self::TEXT_COLOR.fg_str()));
+            }
+            self.visit_expression(argument);
+        }
+        self.result
+            .push_str(&format!("{})", Self::TEXT_COLOR.fg_str()));
+    }
+
     fn visit_number_expression(&mut self, number: &ASTNumberExpression) {
         self.result
             .push_str(&format!("{}{}", Self::NUMBER_COLOR.fg_str(), number.number));
     }
 
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        self.result.push_str(&format!(
+            "{}{:?}",
+            Self::NUMBER_COLOR.fg_str(),
+            float.number
+        ));
+    }
+
+    fn visit_cast_expression(&mut self, cast: &ASTCastExpression) {
+        self.visit_expression(&cast.expression);
+        self.add_whitespace();
+        self.result
+            .push_str(&format!("{}as", Self::KEYWORD_COLOR.fg_str()));
+        self.add_whitespace();
+        self.result.push_str(&format!(
+            "{}{}",
+            Self::KEYWORD_COLOR.fg_str(),
+            cast.type_name.span.literal
+        ));
+    }
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
+        self.result.push_str(&format!(
+            "{}{}",
+            Self::BOOLEAN_COLOR.fg_str(),
+            boolean.value
+        ));
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        self.result.push_str(&format!(
+            "{}{}",
+            Self::STRING_COLOR.fg_str(),
+            Self::quote(&string.value)
+        ));
+    }
+
     fn visit_error(&mut self, span: &TextSpan) {
         self.result
             .push_str(&format!("{}{}", Self::TEXT_COLOR.fg_str(), span.literal));
@@ -164,6 +461,31 @@ impl ASTVisitor for ASTPrinter {
         self.visit_expression(&binary.right);
     }
 
+    fn visit_unary_expression(&mut self, unary: &ASTUnaryExpression) {
+        self.result.push_str(&format!(
+            "{}{}",
+            Self::TEXT_COLOR.fg_str(),
+            unary.operator.token.span.literal
+        ));
+        self.visit_expression(&unary.operand);
+    }
+
+    fn visit_assignment_expression(&mut self, assignment: &ASTAssignmentExpression) {
+        self.result.push_str(&format!(
+            "{}{}",
+            Self::VARIABLE_COLOR.fg_str(),
+            assignment.identifier.span.literal
+        ));
+        self.add_whitespace();
+        self.result.push_str(&format!(
+            "{}{}",
+            Self::TEXT_COLOR.fg_str(),
+            assignment.operator.token.span.literal
+        ));
+        self.add_whitespace();
+        self.visit_expression(&assignment.value);
+    }
+
     fn visit_parenthesized_expression(&mut self, parenthesized: &ASTParenthesizedExpression) {
         self.result
             .push_str(&format!("{}{}", Self::TEXT_COLOR.fg_str(), "("));
@@ -183,15 +505,119 @@ impl ASTPrinter {
 pub enum ASTStatementKind {
     Expression(ASTExpression),
     LetStatement(ASTLetStatement),
+    Block(ASTBlockStatement),
+    If(ASTIfStatement),
+    While(ASTWhileStatement),
+    Break(ASTBreakStatement),
+    Continue(ASTContinueStatement),
+    FunctionDeclaration(Rc<ASTFunctionDeclaration>),
+    Return(ASTReturnStatement),
+}
+
+/// The `///` lines right before a `let` or `fn`.
+pub struct ASTDocComment {
+    pub lines: Vec<Token>,
+}
+
+impl ASTDocComment {
+    pub fn span(&self) -> TextSpan {
+        TextSpan::combine(self.lines.iter().map(|line| line.span.clone()).collect())
+    }
 }
 
 pub struct ASTLetStatement {
+    pub doc_comment: Option<ASTDocComment>,
+    pub mut_keyword: Option<Token>,
     pub identifier: Token,
+    pub type_annotation: Option<ASTTypeAnnotation>,
     pub initializer: ASTExpression,
 }
 
+impl ASTLetStatement {
+    pub fn is_mutable(&self) -> bool {
+        self.mut_keyword.is_some()
+    }
+}
+
+pub struct ASTBlockStatement {
+    pub open_brace: Token,
+    pub statements: Vec<ASTStatement>,
+    pub close_brace: Token,
+}
+
+impl ASTBlockStatement {
+    pub fn span(&self) -> TextSpan {
+        TextSpan::combine(vec![
+            self.open_brace.span.clone(),
+            self.close_brace.span.clone(),
+        ])
+    }
+}
+
+pub struct ASTIfStatement {
+    pub if_keyword: Token,
+    pub condition: ASTExpression,
+    pub then_branch: ASTBlockStatement,
+    pub else_branch: Option<ASTElseBranch>,
+}
+
+impl ASTIfStatement {
+    pub fn span(&self) -> TextSpan {
+        let end = match &self.else_branch {
+            Some(else_branch) => else_branch.else_statement.span(),
+            None => self.then_branch.span(),
+        };
+        TextSpan::combine(vec![self.if_keyword.span.clone(), end])
+    }
+}
+
+/// The `else` part of an if statement: either a block or, for `else if`, another if statement.
+pub struct ASTElseBranch {
+    pub else_keyword: Token,
+    pub else_statement: Box<ASTStatement>,
+}
+
+pub struct ASTWhileStatement {
+    pub while_keyword: Token,
+    pub condition: ASTExpression,
+    pub body: ASTBlockStatement,
+}
+
+pub struct ASTBreakStatement {
+    pub break_keyword: Token,
+}
+
+pub struct ASTContinueStatement {
+    pub continue_keyword: Token,
+}
+
+/// `: type` after a binding, or `-> type` after a parameter list.
+pub struct ASTTypeAnnotation {
+    pub separator: Token,
+    pub type_name: Token,
+}
+
+pub struct ASTFunctionParameter {
+    pub identifier: Token,
+    pub type_annotation: Option<ASTTypeAnnotation>,
+}
+
+pub struct ASTFunctionDeclaration {
+    pub doc_comment: Option<ASTDocComment>,
+    pub fn_keyword: Token,
+    pub identifier: Token,
+    pub parameters: Vec<ASTFunctionParameter>,
+    pub return_type: Option<ASTTypeAnnotation>,
+    pub body: ASTBlockStatement,
+}
+
+pub struct ASTReturnStatement {
+    pub return_keyword: Token,
+    pub return_value: Option<ASTExpression>,
+}
+
 pub struct ASTStatement {
-    kind: ASTStatementKind,
+    pub kind: ASTStatementKind,
 }
 
 impl ASTStatement {
@@ -199,13 +625,93 @@ impl ASTStatement {
         ASTStatement { kind }
     }
 
+    pub fn span(&self) -> TextSpan {
+        match &self.kind {
+            ASTStatementKind::Expression(expr) => expr.span(),
+            ASTStatementKind::LetStatement(let_statement) => TextSpan::combine(vec![
+                let_statement.identifier.span.clone(),
+                let_statement.initializer.span(),
+            ]),
+            ASTStatementKind::Block(block) => block.span(),
+            ASTStatementKind::If(if_statement) => if_statement.span(),
+            ASTStatementKind::While(while_statement) => TextSpan::combine(vec![
+                while_statement.while_keyword.span.clone(),
+                while_statement.body.span(),
+            ]),
+            ASTStatementKind::Break(break_statement) => break_statement.break_keyword.span.clone(),
+            ASTStatementKind::Continue(continue_statement) => {
+                continue_statement.continue_keyword.span.clone()
+            }
+            ASTStatementKind::FunctionDeclaration(function) => {
+                TextSpan::combine(vec![function.fn_keyword.span.clone(), function.body.span()])
+            }
+            ASTStatementKind::Return(return_statement) => {
+                let mut spans = vec![return_statement.return_keyword.span.clone()];
+                if let Some(return_value) = &return_statement.return_value {
+                    spans.push(return_value.span());
+                }
+                TextSpan::combine(spans)
+            }
+        }
+    }
+
     pub fn expression(expr: ASTExpression) -> Self {
         ASTStatement::new(ASTStatementKind::Expression(expr))
     }
 
-    pub fn let_statement(identifier: Token, initializer: ASTExpression) -> Self {
+    pub fn block(block: ASTBlockStatement) -> Self {
+        ASTStatement::new(ASTStatementKind::Block(block))
+    }
+
+    pub fn if_statement(if_statement: ASTIfStatement) -> Self {
+        ASTStatement::new(ASTStatementKind::If(if_statement))
+    }
+
+    pub fn while_statement(
+        while_keyword: Token,
+        condition: ASTExpression,
+        body: ASTBlockStatement,
+    ) -> Self {
+        ASTStatement::new(ASTStatementKind::While(ASTWhileStatement {
+            while_keyword,
+            condition,
+            body,
+        }))
+    }
+
+    pub fn break_statement(break_keyword: Token) -> Self {
+        ASTStatement::new(ASTStatementKind::Break(ASTBreakStatement { break_keyword }))
+    }
+
+    pub fn function_declaration(function: ASTFunctionDeclaration) -> Self {
+        ASTStatement::new(ASTStatementKind::FunctionDeclaration(Rc::new(function)))
+    }
+
+    pub fn return_statement(return_keyword: Token, return_value: Option<ASTExpression>) -> Self {
+        ASTStatement::new(ASTStatementKind::Return(ASTReturnStatement {
+            return_keyword,
+            return_value,
+        }))
+    }
+
+    pub fn continue_statement(continue_keyword: Token) -> Self {
+        ASTStatement::new(ASTStatementKind::Continue(ASTContinueStatement {
+            continue_keyword,
+        }))
+    }
+
+    pub fn let_statement(
+        doc_comment: Option<ASTDocComment>,
+        mut_keyword: Option<Token>,
+        identifier: Token,
+        type_annotation: Option<ASTTypeAnnotation>,
+        initializer: ASTExpression,
+    ) -> Self {
         ASTStatement::new(ASTStatementKind::LetStatement(ASTLetStatement {
+            doc_comment,
+            mut_keyword,
             identifier,
+            type_annotation,
             initializer,
         }))
     }
@@ -214,9 +720,17 @@ impl ASTStatement {
 //Expression
 pub enum ASTExpressionKind {
     Number(ASTNumberExpression),
+    Float(ASTFloatExpression),
+    Boolean(ASTBooleanExpression),
+    String(ASTStringExpression),
     Binary(ASTBinaryExpression),
+    Unary(ASTUnaryExpression),
     Parenthesized(ASTParenthesizedExpression),
     Variable(ASTVariableExpression),
+    If(Box<ASTIfStatement>),
+    Assignment(ASTAssignmentExpression),
+    Call(ASTCallExpression),
+    Cast(ASTCastExpression),
     Error(TextSpan),
 }
 
@@ -230,25 +744,73 @@ impl ASTVariableExpression {
     }
 }
 
+pub struct ASTCallExpression {
+    pub identifier: Token,
+    pub arguments: Vec<ASTExpression>,
+    pub close_paren: Token,
+}
+
+impl ASTCallExpression {
+    pub fn span(&self) -> TextSpan {
+        TextSpan::combine(vec![
+            self.identifier.span.clone(),
+            self.close_paren.span.clone(),
+        ])
+    }
+}
+
 pub struct ASTNumberExpression {
-    number: i64,
+    pub number: i64,
+    pub token: Token,
+}
+
+pub struct ASTFloatExpression {
+    pub number: f64,
+    pub token: Token,
+}
+
+/// `expression as type_name`, which converts between `int` and `float`.
+pub struct ASTCastExpression {
+    pub expression: Box<ASTExpression>,
+    pub as_keyword: Token,
+    pub type_name: Token,
+}
+
+pub struct ASTBooleanExpression {
+    pub value: bool,
+    pub token: Token,
+}
+
+pub struct ASTStringExpression {
+    pub value: String,
+    pub token: Token,
 }
 
 pub struct ASTParenthesizedExpression {
-    expression: Box<ASTExpression>,
+    pub open_paren: Token,
+    pub expression: Box<ASTExpression>,
+    pub close_paren: Token,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ASTBinaryOperatorKind {
     Plus,
     Minus,
     Multiply,
     Divide,
+    Equals,
+    NotEquals,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    LogicalAnd,
+    LogicalOr,
 }
 
 pub struct ASTBinaryOperator {
-    kind: ASTBinaryOperatorKind,
-    token: lexer::Token,
+    pub kind: ASTBinaryOperatorKind,
+    pub token: lexer::Token,
 }
 
 impl ASTBinaryOperator {
@@ -258,29 +820,172 @@ impl ASTBinaryOperator {
 
     pub fn precedence(&self) -> u8 {
         match self.kind {
-            ASTBinaryOperatorKind::Plus | ASTBinaryOperatorKind::Minus => 1,
-            ASTBinaryOperatorKind::Multiply | ASTBinaryOperatorKind::Divide => 2,
+            ASTBinaryOperatorKind::LogicalOr => 1,
+            ASTBinaryOperatorKind::LogicalAnd => 2,
+            ASTBinaryOperatorKind::Equals | ASTBinaryOperatorKind::NotEquals => 3,
+            ASTBinaryOperatorKind::LessThan
+            | ASTBinaryOperatorKind::LessThanOrEqual
+            | ASTBinaryOperatorKind::GreaterThan
+            | ASTBinaryOperatorKind::GreaterThanOrEqual => 4,
+            ASTBinaryOperatorKind::Plus | ASTBinaryOperatorKind::Minus => 5,
+            ASTBinaryOperatorKind::Multiply | ASTBinaryOperatorKind::Divide => 6,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ASTUnaryOperatorKind {
+    Minus,
+    LogicalNot,
+    BitwiseNot,
+}
+
+pub struct ASTUnaryOperator {
+    pub kind: ASTUnaryOperatorKind,
+    pub token: lexer::Token,
+}
+
+impl ASTUnaryOperator {
+    pub fn new(kind: ASTUnaryOperatorKind, token: lexer::Token) -> Self {
+        ASTUnaryOperator { kind, token }
+    }
+}
+
+pub struct ASTUnaryExpression {
+    pub operator: ASTUnaryOperator,
+    pub operand: Box<ASTExpression>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ASTAssignmentOperatorKind {
+    Assign,
+    PlusAssign,
+    MinusAssign,
+    MultiplyAssign,
+    DivideAssign,
+}
+
+pub struct ASTAssignmentOperator {
+    pub kind: ASTAssignmentOperatorKind,
+    pub token: lexer::Token,
+}
+
+impl ASTAssignmentOperator {
+    pub fn new(kind: ASTAssignmentOperatorKind, token: lexer::Token) -> Self {
+        ASTAssignmentOperator { kind, token }
+    }
+
+    /// The binary operator a compound assignment applies, or `None` for plain `=`.
+    pub fn binary_operator(&self) -> Option<ASTBinaryOperator> {
+        let kind = match self.kind {
+            ASTAssignmentOperatorKind::Assign => return None,
+            ASTAssignmentOperatorKind::PlusAssign => ASTBinaryOperatorKind::Plus,
+            ASTAssignmentOperatorKind::MinusAssign => ASTBinaryOperatorKind::Minus,
+            ASTAssignmentOperatorKind::MultiplyAssign => ASTBinaryOperatorKind::Multiply,
+            ASTAssignmentOperatorKind::DivideAssign => ASTBinaryOperatorKind::Divide,
+        };
+        Some(ASTBinaryOperator::new(kind, self.token.clone()))
+    }
+}
+
+pub struct ASTAssignmentExpression {
+    pub identifier: Token,
+    pub operator: ASTAssignmentOperator,
+    pub value: Box<ASTExpression>,
+}
+
 pub struct ASTBinaryExpression {
-    operator: ASTBinaryOperator,
-    left: Box<ASTExpression>,
-    right: Box<ASTExpression>,
+    pub operator: ASTBinaryOperator,
+    pub left: Box<ASTExpression>,
+    pub right: Box<ASTExpression>,
 }
 
+/// Identifies an expression node, so that passes can attach data to it in side tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ASTExpressionId(usize);
+
+static NEXT_EXPRESSION_ID: AtomicUsize = AtomicUsize::new(0);
+
 pub struct ASTExpression {
-    kind: ASTExpressionKind,
+    pub id: ASTExpressionId,
+    pub kind: ASTExpressionKind,
 }
 
 impl ASTExpression {
     pub fn new(kind: ASTExpressionKind) -> Self {
-        ASTExpression { kind }
+        let id = ASTExpressionId(NEXT_EXPRESSION_ID.fetch_add(1, Ordering::Relaxed));
+        ASTExpression { id, kind }
+    }
+
+    pub fn span(&self) -> TextSpan {
+        match &self.kind {
+            ASTExpressionKind::Number(number) => number.token.span.clone(),
+            ASTExpressionKind::Float(float) => float.token.span.clone(),
+            ASTExpressionKind::Boolean(boolean) => boolean.token.span.clone(),
+            ASTExpressionKind::String(string) => string.token.span.clone(),
+            ASTExpressionKind::Binary(binary) => {
+                TextSpan::combine(vec![binary.left.span(), binary.right.span()])
+            }
+            ASTExpressionKind::Unary(unary) => TextSpan::combine(vec![
+                unary.operator.token.span.clone(),
+                unary.operand.span(),
+            ]),
+            ASTExpressionKind::Parenthesized(parenthesized) => TextSpan::combine(vec![
+                parenthesized.open_paren.span.clone(),
+                parenthesized.close_paren.span.clone(),
+            ]),
+            ASTExpressionKind::Variable(variable) => variable.token.span.clone(),
+            ASTExpressionKind::If(if_expression) => if_expression.span(),
+            ASTExpressionKind::Assignment(assignment) => TextSpan::combine(vec![
+                assignment.identifier.span.clone(),
+                assignment.value.span(),
+            ]),
+            ASTExpressionKind::Call(call) => call.span(),
+            ASTExpressionKind::Cast(cast) => {
+                TextSpan::combine(vec![cast.expression.span(), cast.type_name.span.clone()])
+            }
+            ASTExpressionKind::Error(span) => span.clone(),
+        }
+    }
+
+    pub fn number(number: i64, token: Token) -> Self {
+        ASTExpression::new(ASTExpressionKind::Number(ASTNumberExpression {
+            number,
+            token,
+        }))
+    }
+
+    pub fn float(number: f64, token: Token) -> Self {
+        ASTExpression::new(ASTExpressionKind::Float(ASTFloatExpression {
+            number,
+            token,
+        }))
+    }
+
+    pub fn cast(expression: ASTExpression, as_keyword: Token, type_name: Token) -> Self {
+        ASTExpression::new(ASTExpressionKind::Cast(ASTCastExpression {
+            expression: Box::new(expression),
+            as_keyword,
+            type_name,
+        }))
+    }
+
+    pub fn if_expression(if_expression: ASTIfStatement) -> Self {
+        ASTExpression::new(ASTExpressionKind::If(Box::new(if_expression)))
     }
 
-    pub fn number(number: i64) -> Self {
-        ASTExpression::new(ASTExpressionKind::Number(ASTNumberExpression { number }))
+    pub fn boolean(value: bool, token: Token) -> Self {
+        ASTExpression::new(ASTExpressionKind::Boolean(ASTBooleanExpression {
+            value,
+            token,
+        }))
+    }
+
+    pub fn string(value: String, token: Token) -> Self {
+        ASTExpression::new(ASTExpressionKind::String(ASTStringExpression {
+            value,
+            token,
+        }))
     }
 
     pub fn error(span: TextSpan) -> Self {
@@ -295,14 +1000,43 @@ impl ASTExpression {
         }))
     }
 
+    pub fn unary(operator: ASTUnaryOperator, operand: ASTExpression) -> Self {
+        ASTExpression::new(ASTExpressionKind::Unary(ASTUnaryExpression {
+            operator,
+            operand: Box::new(operand),
+        }))
+    }
+
+    pub fn assignment(
+        identifier: Token,
+        operator: ASTAssignmentOperator,
+        value: ASTExpression,
+    ) -> Self {
+        ASTExpression::new(ASTExpressionKind::Assignment(ASTAssignmentExpression {
+            identifier,
+            operator,
+            value: Box::new(value),
+        }))
+    }
+
+    pub fn call(identifier: Token, arguments: Vec<ASTExpression>, close_paren: Token) -> Self {
+        ASTExpression::new(ASTExpressionKind::Call(ASTCallExpression {
+            identifier,
+            arguments,
+            close_paren,
+        }))
+    }
+
     pub fn identifier(token: Token) -> Self {
         ASTExpression::new(ASTExpressionKind::Variable(ASTVariableExpression { token }))
     }
 
-    pub fn parenthesized(expression: ASTExpression) -> Self {
+    pub fn parenthesized(open_paren: Token, expression: ASTExpression, close_paren: Token) -> Self {
         ASTExpression::new(ASTExpressionKind::Parenthesized(
             ASTParenthesizedExpression {
+                open_paren,
                 expression: Box::new(expression),
+                close_paren,
             },
         ))
     }
@@ -0,0 +1,306 @@
+use std::rc::Rc;
+
+use crate::ast::{
+    evaluator::{apply_binary_operator, apply_cast, apply_unary_operator, ArithmeticMode, Value},
+    lexer::{TextSpan, Token, TokenKind},
+    scopes::Scopes,
+    type_checker::TypeChecker,
+    types::Type,
+    ASTBinaryExpression, ASTBinaryOperatorKind, ASTBlockStatement, ASTBooleanExpression,
+    ASTExpression, ASTExpressionKind, ASTFloatExpression, ASTIfStatement, ASTNumberExpression,
+    ASTStatement, ASTStatementKind, ASTStringExpression, Ast,
+};
+
+/// What a binary expression simplifies to.
+enum Simplified {
+    Constant(Value),
+    Left,
+    Right,
+}
+
+/// Folds constant expressions, propagates the values of immutable `let` bindings and applies
+/// identities such as `x * 1` and `x + 0`.
+///
+/// Expressions that would fail at runtime, like `1 / 0` or an overflowing operation in checked
+/// arithmetic, are left as they are so that they still fail with the same diagnostic. Rewritten
+/// expressions keep their id, so the types found by the `TypeChecker` still apply to them.
+pub struct ASTOptimizer<'a> {
+    types: &'a TypeChecker,
+    arithmetic: ArithmeticMode,
+    /// The value of each variable in scope that is bound once to a constant.
+    constants: Scopes<Option<Value>>,
+}
+
+impl<'a> ASTOptimizer<'a> {
+    pub fn optimize(ast: &mut Ast, types: &'a TypeChecker, arithmetic: ArithmeticMode) {
+        let mut optimizer = Self {
+            types,
+            arithmetic,
+            constants: Scopes::new(),
+        };
+        for statement in &mut ast.statements {
+            optimizer.optimize_statement(statement);
+        }
+    }
+
+    fn optimize_statement(&mut self, statement: &mut ASTStatement) {
+        match &mut statement.kind {
+            ASTStatementKind::Expression(expression) => self.optimize_expression(expression),
+            ASTStatementKind::LetStatement(let_statement) => {
+                self.optimize_expression(&mut let_statement.initializer);
+                let value = match let_statement.is_mutable() {
+                    true => None,
+                    false => Self::constant(&let_statement.initializer),
+                };
+                self.constants
+                    .declare(let_statement.identifier.span.literal.clone(), value);
+            }
+            ASTStatementKind::Block(block) => self.optimize_block(block),
+            ASTStatementKind::If(if_statement) => self.optimize_if(if_statement),
+            ASTStatementKind::While(while_statement) => {
+                self.optimize_expression(&mut while_statement.condition);
+                self.optimize_block(&mut while_statement.body);
+            }
+            ASTStatementKind::Break(_) | ASTStatementKind::Continue(_) => {}
+            ASTStatementKind::FunctionDeclaration(function) => {
+                let function = Rc::get_mut(function)
+                    .expect("functions are only shared once evaluation starts");
+                // Functions only run once the globals declared before them are initialized.
+                self.constants.enter_frame();
+                for parameter in &function.parameters {
+                    self.constants
+                        .declare(parameter.identifier.span.literal.clone(), None);
+                }
+                self.optimize_block(&mut function.body);
+                self.constants.exit_frame();
+            }
+            ASTStatementKind::Return(return_statement) => {
+                if let Some(return_value) = &mut return_statement.return_value {
+                    self.optimize_expression(return_value);
+                }
+            }
+        }
+    }
+
+    fn optimize_block(&mut self, block: &mut ASTBlockStatement) {
+        self.constants.enter_scope();
+        for statement in &mut block.statements {
+            self.optimize_statement(statement);
+        }
+        self.constants.exit_scope();
+    }
+
+    fn optimize_if(&mut self, if_statement: &mut ASTIfStatement) {
+        self.optimize_expression(&mut if_statement.condition);
+        self.optimize_block(&mut if_statement.then_branch);
+        if let Some(else_branch) = &mut if_statement.else_branch {
+            self.optimize_statement(&mut else_branch.else_statement);
+        }
+    }
+
+    fn optimize_expression(&mut self, expression: &mut ASTExpression) {
+        let value = match &mut expression.kind {
+            ASTExpressionKind::Number(_)
+            | ASTExpressionKind::Float(_)
+            | ASTExpressionKind::Boolean(_)
+            | ASTExpressionKind::String(_)
+            | ASTExpressionKind::Error(_) => None,
+            ASTExpressionKind::Variable(variable) => self
+                .constants
+                .lookup(&variable.token.span.literal)
+                .cloned()
+                .flatten(),
+            ASTExpressionKind::Parenthesized(parenthesized) => {
+                self.optimize_expression(&mut parenthesized.expression);
+                Self::constant(&parenthesized.expression)
+            }
+            ASTExpressionKind::Cast(cast) => {
+                self.optimize_expression(&mut cast.expression);
+                let target = Type::from_name(&cast.type_name.span.literal);
+                Self::constant(&cast.expression)
+                    .zip(target)
+                    .and_then(|(value, target)| apply_cast(value, target, self.arithmetic).ok())
+            }
+            ASTExpressionKind::Unary(unary) => {
+                self.optimize_expression(&mut unary.operand);
+                Self::constant(&unary.operand).and_then(|operand| {
+                    let symbol = &unary.operator.token.span.literal;
+                    apply_unary_operator(unary.operator.kind, symbol, operand, self.arithmetic).ok()
+                })
+            }
+            ASTExpressionKind::Binary(binary) => {
+                self.optimize_expression(&mut binary.left);
+                self.optimize_expression(&mut binary.right);
+                match self.simplify(binary) {
+                    Some(Simplified::Constant(value)) => Some(value),
+                    Some(Simplified::Left) => {
+                        Self::replace_with_operand(expression, true);
+                        return;
+                    }
+                    Some(Simplified::Right) => {
+                        Self::replace_with_operand(expression, false);
+                        return;
+                    }
+                    None => None,
+                }
+            }
+            ASTExpressionKind::If(if_expression) => {
+                self.optimize_if(if_expression);
+                None
+            }
+            ASTExpressionKind::Assignment(assignment) => {
+                self.optimize_expression(&mut assignment.value);
+                None
+            }
+            ASTExpressionKind::Call(call) => {
+                for argument in &mut call.arguments {
+                    self.optimize_expression(argument);
+                }
+                None
+            }
+        };
+        if let Some(value) = value {
+            expression.kind = Self::literal(value, expression.span());
+        }
+    }
+
+    /// Finds what `binary` reduces to once its operands have been optimized, if anything.
+    fn simplify(&self, binary: &ASTBinaryExpression) -> Option<Simplified> {
+        use ASTBinaryOperatorKind::*;
+
+        let left = Self::constant(&binary.left);
+        let right = Self::constant(&binary.right);
+        if let (Some(left), Some(right)) = (&left, &right) {
+            let symbol = &binary.operator.token.span.literal;
+            return apply_binary_operator(
+                binary.operator.kind,
+                symbol,
+                left.clone(),
+                right.clone(),
+                self.arithmetic,
+            )
+            .ok()
+            .map(Simplified::Constant);
+        }
+        match (binary.operator.kind, left, right) {
+            // The right operand is never evaluated.
+            (LogicalAnd, Some(Value::Boolean(false)), _) => {
+                Some(Simplified::Constant(Value::Boolean(false)))
+            }
+            (LogicalOr, Some(Value::Boolean(true)), _) => {
+                Some(Simplified::Constant(Value::Boolean(true)))
+            }
+            (Multiply, Some(Value::Integer(0)), _) if self.is_pure(&binary.right) => {
+                Some(Simplified::Constant(Value::Integer(0)))
+            }
+            (Multiply, _, Some(Value::Integer(0))) if self.is_pure(&binary.left) => {
+                Some(Simplified::Constant(Value::Integer(0)))
+            }
+            (Plus, Some(Value::Integer(0)), _) | (Multiply, Some(Value::Integer(1)), _)
+                if self.keeps_value(&binary.right, Type::Integer) =>
+            {
+                Some(Simplified::Right)
+            }
+            (LogicalAnd, Some(Value::Boolean(true)), _)
+            | (LogicalOr, Some(Value::Boolean(false)), _)
+                if self.keeps_value(&binary.right, Type::Boolean) =>
+            {
+                Some(Simplified::Right)
+            }
+            (Plus | Minus, _, Some(Value::Integer(0)))
+            | (Multiply | Divide, _, Some(Value::Integer(1)))
+                if self.keeps_value(&binary.left, Type::Integer) =>
+            {
+                Some(Simplified::Left)
+            }
+            (LogicalAnd, _, Some(Value::Boolean(true)))
+            | (LogicalOr, _, Some(Value::Boolean(false)))
+                if self.keeps_value(&binary.left, Type::Boolean) =>
+            {
+                Some(Simplified::Left)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether `operand` can stand in for an operation that would just return it. Its type has
+    /// to be known, or the operation could fail on a value of another type, and it must always
+    /// produce a value, or using it where one is needed could fail.
+    fn keeps_value(&self, operand: &ASTExpression, expected: Type) -> bool {
+        self.types.type_of(operand) == expected && Self::always_has_value(operand)
+    }
+
+    fn always_has_value(expression: &ASTExpression) -> bool {
+        match &expression.kind {
+            ASTExpressionKind::Parenthesized(parenthesized) => {
+                Self::always_has_value(&parenthesized.expression)
+            }
+            ASTExpressionKind::Call(_) | ASTExpressionKind::If(_) | ASTExpressionKind::Error(_) => {
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether evaluating `expression` can be skipped: it cannot fail and has no effects.
+    fn is_pure(&self, expression: &ASTExpression) -> bool {
+        match &expression.kind {
+            ASTExpressionKind::Number(_) | ASTExpressionKind::Boolean(_) => true,
+            ASTExpressionKind::Variable(_) => self.types.type_of(expression) == Type::Integer,
+            ASTExpressionKind::Parenthesized(parenthesized) => {
+                self.is_pure(&parenthesized.expression)
+            }
+            _ => false,
+        }
+    }
+
+    /// Replaces a binary expression with its left or right operand.
+    fn replace_with_operand(expression: &mut ASTExpression, left: bool) {
+        let span = expression.span();
+        let kind = std::mem::replace(&mut expression.kind, ASTExpressionKind::Error(span));
+        let ASTExpressionKind::Binary(binary) = kind else {
+            unreachable!("only binary expressions are simplified");
+        };
+        *expression = if left { *binary.left } else { *binary.right };
+    }
+
+    fn constant(expression: &ASTExpression) -> Option<Value> {
+        match &expression.kind {
+            ASTExpressionKind::Number(number) => Some(Value::Integer(number.number)),
+            ASTExpressionKind::Float(float) => Some(Value::Float(float.number)),
+            ASTExpressionKind::Boolean(boolean) => Some(Value::Boolean(boolean.value)),
+            ASTExpressionKind::String(string) => Some(Value::Str(string.value.clone())),
+            _ => None,
+        }
+    }
+
+    /// A literal for `value` that covers the source of the expression it replaces, so that
+    /// diagnostics pointing at the surrounding code keep their spans.
+    fn literal(value: Value, span: TextSpan) -> ASTExpressionKind {
+        match value {
+            Value::Integer(number) => ASTExpressionKind::Number(ASTNumberExpression {
+                number,
+                token: Token::new(TokenKind::Number(number), span),
+            }),
+            Value::Float(number) => ASTExpressionKind::Float(ASTFloatExpression {
+                number,
+                token: Token::new(TokenKind::Float(number), span),
+            }),
+            Value::Boolean(value) => {
+                let kind = if value {
+                    TokenKind::True
+                } else {
+                    TokenKind::False
+                };
+                ASTExpressionKind::Boolean(ASTBooleanExpression {
+                    value,
+                    token: Token::new(kind, span),
+                })
+            }
+            Value::Str(value) => ASTExpressionKind::String(ASTStringExpression {
+                token: Token::new(TokenKind::String(value.clone()), span),
+                value,
+            }),
+        }
+    }
+}
@@ -1,10 +1,17 @@
-use std::cell::Cell;
+use std::{cell::Cell, collections::HashMap};
 
-use crate::ast::lexer::{Lexer, Token, TokenKind};
-use crate::ast::{ASTExpression, ASTExpressionKind, ASTStatement, ASTStatementKind};
-use crate::diagnostics::DiagnosticsBagCell;
+use crate::ast::lexer::{Lexer, TextSpan, Token, TokenKind};
+use crate::ast::{
+    ASTBlockStatement, ASTDocComment, ASTElseBranch, ASTExpression, ASTExpressionKind,
+    ASTFunctionDeclaration, ASTFunctionParameter, ASTIfStatement, ASTStatement, ASTStatementKind,
+    ASTTypeAnnotation,
+};
+use crate::diagnostics::{DiagnosticBag, DiagnosticsBagCell};
 
-use super::{ASTBinaryOperator, ASTBinaryOperatorKind};
+use super::{
+    ASTAssignmentOperator, ASTAssignmentOperatorKind, ASTBinaryOperator, ASTBinaryOperatorKind,
+    ASTUnaryOperator, ASTUnaryOperatorKind,
+};
 
 pub struct Counter {
     value: Cell<usize>,
@@ -26,30 +33,257 @@ impl Counter {
     }
 }
 
+/// Parses tokens into statements.
+///
+/// A statement ends at a newline or `;`, or where its block or the input ends. A newline does
+/// not end a statement inside parentheses, after a token that needs an operand or more input,
+/// such as a binary operator, `=`, `,` or `{`, or before `else`. Comments are skipped, except
+/// that `///` doc comments are kept for the `let` or `fn` that follows them.
+///
+/// After a syntax error the parser is in panic mode: it keeps building the statement with
+/// `ASTExpressionKind::Error` placeholders but reports nothing more until it has skipped to the
+/// start of the next statement, so that each mistake is reported once.
 pub struct Parser {
     tokens: Vec<Token>,
+    /// The doc comments that come right before a token, by the index of that token.
+    doc_comments: HashMap<usize, Vec<Token>>,
     current: Counter,
+    panicking: Cell<bool>,
+    /// How many blocks enclose the current token. Only then can `}` end a statement.
+    block_depth: usize,
     diagnostics_bag: DiagnosticsBagCell,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>, diagnostics_bag: DiagnosticsBagCell) -> Self {
+        let (tokens, doc_comments) = Self::significant_tokens(tokens);
         Self {
-            tokens: tokens
-                .iter()
-                .filter(|token| token.kind != TokenKind::Whitespace)
-                .map(|token| token.clone())
-                .collect(),
+            tokens,
+            doc_comments,
             current: Counter::new(),
+            panicking: Cell::new(false),
+            block_depth: 0,
             diagnostics_bag,
         }
     }
 
+    /// Drops whitespace, comments and the newlines that do not end a statement, and sets the
+    /// doc comments aside.
+    ///
+    /// A keyword that can only start a statement always ends the line before it, even inside
+    /// parentheses, so that a missing `)` does not swallow the rest of the input.
+    fn significant_tokens(tokens: Vec<Token>) -> (Vec<Token>, HashMap<usize, Vec<Token>>) {
+        let mut significant: Vec<Token> = Vec::new();
+        let mut doc_comments = HashMap::new();
+        let mut doc_comment: Vec<Token> = Vec::new();
+        let mut newline: Option<Token> = None;
+        let mut paren_depth: usize = 0;
+        for token in tokens {
+            match token.kind {
+                TokenKind::Whitespace => continue,
+                TokenKind::Newline => {
+                    newline.get_or_insert(token);
+                    continue;
+                }
+                TokenKind::Comment => {
+                    // A block comment over several lines ends the line it starts on.
+                    if token.span.literal.contains('\n') {
+                        newline.get_or_insert(Token::new(TokenKind::Newline, token.span));
+                    }
+                    continue;
+                }
+                TokenKind::DocComment => {
+                    doc_comment.push(token);
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(newline) = newline.take() {
+                if Self::is_statement_keyword(&token.kind) {
+                    paren_depth = 0;
+                    significant.push(newline);
+                } else if paren_depth == 0
+                    && token.kind != TokenKind::Else
+                    && significant
+                        .last()
+                        .is_some_and(|previous| !Self::is_continued(&previous.kind))
+                {
+                    significant.push(newline);
+                }
+            }
+            match token.kind {
+                TokenKind::LeftParen => paren_depth += 1,
+                TokenKind::RightParen => paren_depth = paren_depth.saturating_sub(1),
+                _ => {}
+            }
+            if !doc_comment.is_empty() {
+                doc_comments.insert(significant.len(), std::mem::take(&mut doc_comment));
+            }
+            significant.push(token);
+        }
+        (significant, doc_comments)
+    }
+
+    fn is_statement_keyword(kind: &TokenKind) -> bool {
+        matches!(
+            kind,
+            TokenKind::Let
+                | TokenKind::Fn
+                | TokenKind::While
+                | TokenKind::Return
+                | TokenKind::Break
+                | TokenKind::Continue
+        )
+    }
+
+    /// Whether a newline after a token of `kind` continues the statement.
+    pub fn is_continued(kind: &TokenKind) -> bool {
+        matches!(
+            kind,
+            TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Asterisk
+                | TokenKind::Slash
+                | TokenKind::EqualsEquals
+                | TokenKind::BangEquals
+                | TokenKind::LessThan
+                | TokenKind::LessThanEquals
+                | TokenKind::GreaterThan
+                | TokenKind::GreaterThanEquals
+                | TokenKind::AmpersandAmpersand
+                | TokenKind::PipePipe
+                | TokenKind::Bang
+                | TokenKind::Tilde
+                | TokenKind::Equals
+                | TokenKind::PlusEquals
+                | TokenKind::MinusEquals
+                | TokenKind::AsteriskEquals
+                | TokenKind::SlashEquals
+                | TokenKind::Comma
+                | TokenKind::Colon
+                | TokenKind::Arrow
+                | TokenKind::OpenBrace
+                | TokenKind::Else
+                | TokenKind::As
+        )
+    }
+
     pub fn next_statement(&mut self) -> Option<ASTStatement> {
+        self.skip_terminators();
         if self.is_at_end() {
+            self.report_detached_doc_comments();
             return None;
         }
-        Some(self.parse_statement())
+        Some(self.parse_statement_and_recover())
+    }
+
+    /// Takes the doc comment of the current token.
+    fn take_doc_comment(&mut self) -> Option<ASTDocComment> {
+        let lines = self.doc_comments.remove(&self.current.get_value())?;
+        Some(ASTDocComment { lines })
+    }
+
+    /// Warns about the doc comments that no declaration took. After a syntax error they may
+    /// belong to a declaration that was skipped, so they are left alone.
+    fn report_detached_doc_comments(&mut self) {
+        let mut diagnostics_bag = self.diagnostics_bag.borrow_mut();
+        if diagnostics_bag.has_errors() {
+            return;
+        }
+        let mut detached: Vec<ASTDocComment> = self
+            .doc_comments
+            .drain()
+            .map(|(_, lines)| ASTDocComment { lines })
+            .collect();
+        detached.sort_by_key(|doc_comment| doc_comment.span().start);
+        for doc_comment in detached {
+            diagnostics_bag.report_detached_doc_comment(doc_comment.span());
+        }
+    }
+
+    fn skip_terminators(&self) {
+        while self.is_at_terminator() {
+            self.consume();
+        }
+    }
+
+    fn is_at_terminator(&self) -> bool {
+        matches!(
+            self.current().kind,
+            TokenKind::Newline | TokenKind::Semicolon
+        )
+    }
+
+    /// Parses a statement and its terminator, then skips what is left of the statement if it
+    /// had a syntax error.
+    fn parse_statement_and_recover(&mut self) -> ASTStatement {
+        let start = self.current.get_value();
+        let statement = self.parse_statement();
+        if !self.panicking.get() {
+            if !Self::ends_with_block(&statement) {
+                self.parse_terminator();
+            } else if self.is_at_terminator() {
+                self.consume();
+            }
+        }
+        if self.panicking.get() {
+            self.synchronize(start);
+        }
+        statement
+    }
+
+    fn parse_terminator(&self) {
+        if self.is_at_terminator() {
+            self.consume();
+        } else if !self.is_at_end() && !self.is_at_block_end() {
+            self.report(|bag| bag.report_missing_terminator(self.current()));
+        }
+    }
+
+    /// Whether `statement` ends with the closing brace of a block, which also ends the statement,
+    /// as in `if n < 2 { return n } return n - 1`.
+    fn ends_with_block(statement: &ASTStatement) -> bool {
+        matches!(
+            statement.kind,
+            ASTStatementKind::Block(_)
+                | ASTStatementKind::If(_)
+                | ASTStatementKind::While(_)
+                | ASTStatementKind::FunctionDeclaration(_)
+        )
+    }
+
+    fn is_at_block_end(&self) -> bool {
+        self.block_depth > 0 && self.current().kind == TokenKind::CloseBrace
+    }
+
+    /// Skips tokens up to the end of the statement or the start of the next one and leaves
+    /// panic mode. At least one token is skipped if nothing was consumed since `start`, so that
+    /// parsing always moves on.
+    fn synchronize(&self, start: usize) {
+        if self.current.get_value() == start && !self.is_at_end() {
+            self.consume();
+        }
+        while !self.is_at_statement_boundary() {
+            self.consume();
+        }
+        self.panicking.set(false);
+    }
+
+    /// Whether the current token can only end a statement or start a new one.
+    fn is_at_statement_boundary(&self) -> bool {
+        match &self.current().kind {
+            TokenKind::Newline | TokenKind::Semicolon | TokenKind::EOF => true,
+            TokenKind::CloseBrace => self.block_depth > 0,
+            kind => Self::is_statement_keyword(kind),
+        }
+    }
+
+    /// Reports a syntax error and enters panic mode, unless the current statement already has
+    /// one.
+    fn report(&self, report: impl FnOnce(&mut DiagnosticBag)) {
+        if !self.panicking.replace(true) {
+            report(&mut self.diagnostics_bag.borrow_mut());
+        }
     }
 
     fn is_at_end(&self) -> bool {
@@ -59,36 +293,235 @@ impl Parser {
     fn parse_statement(&mut self) -> ASTStatement {
         match self.current().kind {
             TokenKind::Let => self.parse_let_statement(),
+            TokenKind::OpenBrace => self.parse_block_statement(),
+            TokenKind::If => self.parse_if_statement(),
+            TokenKind::While => self.parse_while_statement(),
+            TokenKind::Fn => self.parse_function_declaration(),
+            TokenKind::Return => self.parse_return_statement(),
+            TokenKind::Break => {
+                let break_keyword = self.consume().clone();
+                ASTStatement::break_statement(break_keyword)
+            }
+            TokenKind::Continue => {
+                let continue_keyword = self.consume().clone();
+                ASTStatement::continue_statement(continue_keyword)
+            }
             _ => self.parse_expression_statement(),
         }
     }
 
+    fn parse_block_statement(&mut self) -> ASTStatement {
+        ASTStatement::block(self.parse_block())
+    }
+
+    fn parse_block(&mut self) -> ASTBlockStatement {
+        // The newline ends the header, so a brace on the next line would start a new statement.
+        // The intent is clear though, so the block is parsed anyway after the error.
+        if self.current().kind == TokenKind::Newline && self.peek(1).kind == TokenKind::OpenBrace {
+            if !self.panicking.get() {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_block_on_next_line(self.peek(1));
+            }
+            self.consume();
+        }
+        // Without an opening brace there is no telling where the block would end: it is left
+        // empty and the statement is skipped up to its end.
+        if self.current().kind != TokenKind::OpenBrace {
+            let token = self.current().clone();
+            self.report(|bag| bag.report_unexpected_token(&TokenKind::OpenBrace, &token));
+            return ASTBlockStatement {
+                open_brace: token.clone(),
+                statements: Vec::new(),
+                close_brace: token,
+            };
+        }
+        let open_brace = self.consume().clone();
+        let mut statements = Vec::new();
+        self.block_depth += 1;
+        self.skip_terminators();
+        while self.current().kind != TokenKind::CloseBrace && !self.is_at_end() {
+            statements.push(self.parse_statement_and_recover());
+            self.skip_terminators();
+        }
+        self.block_depth -= 1;
+        let close_brace = self.consume_and_check(TokenKind::CloseBrace).clone();
+        ASTBlockStatement {
+            open_brace,
+            statements,
+            close_brace,
+        }
+    }
+
+    fn parse_function_declaration(&mut self) -> ASTStatement {
+        let doc_comment = self.take_doc_comment();
+        let fn_keyword = self.consume_and_check(TokenKind::Fn).clone();
+        let identifier = self.consume_and_check(TokenKind::Identifier).clone();
+        self.consume_and_check(TokenKind::LeftParen);
+        let mut parameters = Vec::new();
+        while self.current().kind != TokenKind::RightParen && !self.is_at_end() {
+            let identifier = self.consume_and_check(TokenKind::Identifier).clone();
+            let type_annotation = self.parse_optional_type_annotation(TokenKind::Colon);
+            parameters.push(ASTFunctionParameter {
+                identifier,
+                type_annotation,
+            });
+            if self.current().kind != TokenKind::Comma {
+                break;
+            }
+            self.consume();
+        }
+        self.consume_and_check(TokenKind::RightParen);
+        let return_type = self.parse_optional_type_annotation(TokenKind::Arrow);
+        let body = self.parse_block();
+        ASTStatement::function_declaration(ASTFunctionDeclaration {
+            doc_comment,
+            fn_keyword,
+            identifier,
+            parameters,
+            return_type,
+            body,
+        })
+    }
+
+    fn parse_return_statement(&mut self) -> ASTStatement {
+        let return_keyword = self.consume_and_check(TokenKind::Return).clone();
+        let return_value = match self.current().kind {
+            TokenKind::CloseBrace | TokenKind::EOF | TokenKind::Newline | TokenKind::Semicolon => {
+                None
+            }
+            _ => Some(self.parse_expression()),
+        };
+        ASTStatement::return_statement(return_keyword, return_value)
+    }
+
+    fn parse_while_statement(&mut self) -> ASTStatement {
+        let while_keyword = self.consume_and_check(TokenKind::While).clone();
+        let condition = self.parse_expression();
+        let body = self.parse_block();
+        ASTStatement::while_statement(while_keyword, condition, body)
+    }
+
+    fn parse_if_statement(&mut self) -> ASTStatement {
+        ASTStatement::if_statement(self.parse_if())
+    }
+
+    fn parse_if_expression(&mut self) -> ASTExpression {
+        let if_expression = self.parse_if();
+        if if_expression.else_branch.is_none() {
+            self.report(|bag| bag.report_missing_else_branch(&if_expression.if_keyword));
+        }
+        ASTExpression::if_expression(if_expression)
+    }
+
+    fn parse_if(&mut self) -> ASTIfStatement {
+        let if_keyword = self.consume_and_check(TokenKind::If).clone();
+        let condition = self.parse_expression();
+        let then_branch = self.parse_block();
+        let else_branch = if self.current().kind == TokenKind::Else {
+            let else_keyword = self.consume().clone();
+            let else_statement = if self.current().kind == TokenKind::If {
+                self.parse_if_statement()
+            } else {
+                self.parse_block_statement()
+            };
+            Some(ASTElseBranch {
+                else_keyword,
+                else_statement: Box::new(else_statement),
+            })
+        } else {
+            None
+        };
+
+        ASTIfStatement {
+            if_keyword,
+            condition,
+            then_branch,
+            else_branch,
+        }
+    }
+
     fn parse_expression_statement(&mut self) -> ASTStatement {
         let expr: ASTExpression = self.parse_expression();
         ASTStatement::new(ASTStatementKind::Expression(expr))
     }
 
     fn parse_let_statement(&mut self) -> ASTStatement {
+        let doc_comment = self.take_doc_comment();
         self.consume_and_check(TokenKind::Let);
+        let mut_keyword = if self.current().kind == TokenKind::Mut {
+            Some(self.consume().clone())
+        } else {
+            None
+        };
         let identifier = self.consume_and_check(TokenKind::Identifier).clone();
+        let type_annotation = self.parse_optional_type_annotation(TokenKind::Colon);
         self.consume_and_check(TokenKind::Equals);
         let expression: ASTExpression = self.parse_expression();
-        ASTStatement::let_statement(identifier, expression)
+        ASTStatement::let_statement(
+            doc_comment,
+            mut_keyword,
+            identifier,
+            type_annotation,
+            expression,
+        )
+    }
+
+    fn parse_optional_type_annotation(
+        &mut self,
+        separator: TokenKind,
+    ) -> Option<ASTTypeAnnotation> {
+        if self.current().kind != separator {
+            return None;
+        }
+        let separator = self.consume().clone();
+        let type_name = self.consume_and_check(TokenKind::Identifier).clone();
+        Some(ASTTypeAnnotation {
+            separator,
+            type_name,
+        })
     }
 
     fn parse_expression(&mut self) -> ASTExpression {
-        return self.parse_binary_expression(0);
+        return self.parse_assignment_expression();
+    }
+
+    fn parse_assignment_expression(&mut self) -> ASTExpression {
+        if self.current().kind == TokenKind::Identifier {
+            if let Some(operator) = self.parse_assignment_operator() {
+                let identifier = self.consume().clone();
+                self.consume();
+                // Assignment is right-associative: `a = b = 1` assigns `b` first.
+                let value = self.parse_assignment_expression();
+                return ASTExpression::assignment(identifier, operator, value);
+            }
+        }
+        self.parse_binary_expression(0)
+    }
+
+    fn parse_assignment_operator(&mut self) -> Option<ASTAssignmentOperator> {
+        let token = self.peek(1);
+        let kind = match token.kind {
+            TokenKind::Equals => Some(ASTAssignmentOperatorKind::Assign),
+            TokenKind::PlusEquals => Some(ASTAssignmentOperatorKind::PlusAssign),
+            TokenKind::MinusEquals => Some(ASTAssignmentOperatorKind::MinusAssign),
+            TokenKind::AsteriskEquals => Some(ASTAssignmentOperatorKind::MultiplyAssign),
+            TokenKind::SlashEquals => Some(ASTAssignmentOperatorKind::DivideAssign),
+            _ => None,
+        };
+
+        kind.map(|kind| ASTAssignmentOperator::new(kind, token.clone()))
     }
 
     fn parse_binary_expression(&mut self, precedence: u8) -> ASTExpression {
-        let mut left = self.parse_primary_expression();
+        let mut left = self.parse_cast_expression();
 
         while let Some(operator) = self.parse_binary_operator() {
-            self.consume();
             let operator_precedence = operator.precedence();
-            if operator_precedence < precedence {
+            if operator_precedence <= precedence {
                 break;
             }
+            self.consume();
             let right = self.parse_binary_expression(operator_precedence);
             left = ASTExpression::binary(operator, left, right);
         }
@@ -96,6 +529,46 @@ impl Parser {
         left
     }
 
+    /// Casts bind tighter than binary operators and looser than unary ones.
+    fn parse_cast_expression(&mut self) -> ASTExpression {
+        let mut expression = self.parse_unary_expression();
+        while self.current().kind == TokenKind::As {
+            let as_keyword = self.consume().clone();
+            let type_name = self.consume_and_check(TokenKind::Identifier).clone();
+            expression = ASTExpression::cast(expression, as_keyword, type_name);
+        }
+        expression
+    }
+
+    fn parse_unary_expression(&mut self) -> ASTExpression {
+        if let Some(operator) = self.parse_unary_operator() {
+            self.consume();
+            // `-9223372036854775808` is the one literal whose magnitude does not fit on its own.
+            if operator.kind == ASTUnaryOperatorKind::Minus
+                && self.current().kind == TokenKind::Number(i64::MIN)
+            {
+                let number = self.consume();
+                let span = TextSpan::combine(vec![operator.token.span, number.span.clone()]);
+                return ASTExpression::number(i64::MIN, Token::new(number.kind.clone(), span));
+            }
+            let operand = self.parse_unary_expression();
+            return ASTExpression::unary(operator, operand);
+        }
+        self.parse_primary_expression()
+    }
+
+    fn parse_unary_operator(&mut self) -> Option<ASTUnaryOperator> {
+        let token = self.peek(0);
+        let kind = match token.kind {
+            TokenKind::Minus => Some(ASTUnaryOperatorKind::Minus),
+            TokenKind::Bang => Some(ASTUnaryOperatorKind::LogicalNot),
+            TokenKind::Tilde => Some(ASTUnaryOperatorKind::BitwiseNot),
+            _ => None,
+        };
+
+        kind.map(|kind| ASTUnaryOperator::new(kind, token.clone()))
+    }
+
     fn parse_binary_operator(&mut self) -> Option<ASTBinaryOperator> {
         let token = self.peek(0);
         let kind = match token.kind {
@@ -103,6 +576,14 @@ impl Parser {
             TokenKind::Minus => Some(ASTBinaryOperatorKind::Minus),
             TokenKind::Asterisk => Some(ASTBinaryOperatorKind::Multiply),
             TokenKind::Slash => Some(ASTBinaryOperatorKind::Divide),
+            TokenKind::EqualsEquals => Some(ASTBinaryOperatorKind::Equals),
+            TokenKind::BangEquals => Some(ASTBinaryOperatorKind::NotEquals),
+            TokenKind::LessThan => Some(ASTBinaryOperatorKind::LessThan),
+            TokenKind::LessThanEquals => Some(ASTBinaryOperatorKind::LessThanOrEqual),
+            TokenKind::GreaterThan => Some(ASTBinaryOperatorKind::GreaterThan),
+            TokenKind::GreaterThanEquals => Some(ASTBinaryOperatorKind::GreaterThanOrEqual),
+            TokenKind::AmpersandAmpersand => Some(ASTBinaryOperatorKind::LogicalAnd),
+            TokenKind::PipePipe => Some(ASTBinaryOperatorKind::LogicalOr),
             _ => None,
         };
 
@@ -110,24 +591,60 @@ impl Parser {
     }
 
     fn parse_primary_expression(&mut self) -> ASTExpression {
-        let token = self.consume();
+        if self.current().kind == TokenKind::If {
+            return self.parse_if_expression();
+        }
+
+        // The expression is missing: leave the token to end the statement.
+        if self.is_at_statement_boundary() {
+            let token = self.current().clone();
+            self.report(|bag| bag.report_expected_expression(&token));
+            return ASTExpression::error(token.span.clone());
+        }
+
+        let token = self.consume().clone();
         match token.kind {
-            TokenKind::Number(number) => ASTExpression::number(number),
+            TokenKind::Number(i64::MIN) => {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_integer_literal_too_large(token.span.clone());
+                ASTExpression::number(0, token)
+            }
+            TokenKind::Number(number) => ASTExpression::number(number, token),
+            TokenKind::Float(number) => ASTExpression::float(number, token),
+            TokenKind::String(ref value) => ASTExpression::string(value.clone(), token.clone()),
+            TokenKind::True => ASTExpression::boolean(true, token.clone()),
+            TokenKind::False => ASTExpression::boolean(false, token.clone()),
             TokenKind::LeftParen => {
                 let expr: ASTExpression = self.parse_expression();
-                self.consume_and_check(TokenKind::RightParen);
-                ASTExpression::parenthesized(expr)
+                let close_paren = self.consume_and_check(TokenKind::RightParen).clone();
+                ASTExpression::parenthesized(token, expr, close_paren)
+            }
+            TokenKind::Identifier if self.current().kind == TokenKind::LeftParen => {
+                self.parse_call_expression(token)
             }
             TokenKind::Identifier => ASTExpression::identifier(token.clone()),
             _ => {
-                self.diagnostics_bag
-                    .borrow_mut()
-                    .report_expected_expression(&token);
+                self.report(|bag| bag.report_expected_expression(&token));
                 ASTExpression::error(token.span.clone())
             }
         }
     }
 
+    fn parse_call_expression(&mut self, identifier: Token) -> ASTExpression {
+        self.consume_and_check(TokenKind::LeftParen);
+        let mut arguments = Vec::new();
+        while self.current().kind != TokenKind::RightParen && !self.is_at_end() {
+            arguments.push(self.parse_expression());
+            if self.current().kind != TokenKind::Comma {
+                break;
+            }
+            self.consume();
+        }
+        let close_paren = self.consume_and_check(TokenKind::RightParen).clone();
+        ASTExpression::call(identifier, arguments, close_paren)
+    }
+
     fn peek(&self, offset: isize) -> &Token {
         let mut index = (self.current.get_value() as isize + offset) as usize;
         if index >= self.tokens.len() {
@@ -145,13 +662,13 @@ impl Parser {
         self.peek(-1)
     }
 
+    /// Consumes the current token if it is of the expected kind. Otherwise reports it and
+    /// leaves it in place, as it most likely belongs to what follows the mistake.
     fn consume_and_check(&self, kind: TokenKind) -> &Token {
-        let token = self.consume();
-        if token.kind != kind {
-            self.diagnostics_bag
-                .borrow_mut()
-                .report_unexpected_token(&kind, &token);
+        if self.current().kind == kind {
+            return self.consume();
         }
-        token
+        self.report(|bag| bag.report_unexpected_token(&kind, self.current()));
+        self.current()
     }
 }
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+/// A chain of lexical scopes. The first scope is the global one and is never exited.
+///
+/// Function calls open a new frame: lookups inside a frame only see the frame's own scopes
+/// and the global scope, never the locals of the caller.
+#[derive(Clone)]
+pub struct Scopes<T> {
+    scopes: Vec<HashMap<String, T>>,
+    frames: Vec<usize>,
+}
+
+impl<T> Scopes<T> {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Drops the innermost scope and returns the bindings it held.
+    pub fn exit_scope(&mut self) -> HashMap<String, T> {
+        assert!(
+            self.scopes.len() > self.frame_start() + 1,
+            "cannot exit the outermost scope of a frame"
+        );
+        self.scopes.pop().unwrap()
+    }
+
+    pub fn enter_frame(&mut self) {
+        self.frames.push(self.scopes.len());
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn exit_frame(&mut self) {
+        let start = self.frames.pop().expect("no frame to exit");
+        self.scopes.truncate(start);
+    }
+
+    /// Whether declarations would currently land in the global scope.
+    pub fn is_global(&self) -> bool {
+        self.scopes.len() == 1
+    }
+
+    pub fn declare(&mut self, name: String, value: T) {
+        self.scopes.last_mut().unwrap().insert(name, value);
+    }
+
+    /// Whether `name` is declared in the innermost scope.
+    pub fn is_declared_locally(&self, name: &str) -> bool {
+        self.scopes.last().unwrap().contains_key(name)
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&T> {
+        let frame_start = self.frame_start();
+        self.scopes[frame_start..]
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.scopes[0].get(name))
+    }
+
+    pub fn lookup_mut(&mut self, name: &str) -> Option<&mut T> {
+        let frame_start = self.frame_start();
+        let index = (frame_start..self.scopes.len())
+            .rev()
+            .find(|index| self.scopes[*index].contains_key(name))
+            .unwrap_or(0);
+        self.scopes[index].get_mut(name)
+    }
+
+    /// Every binding currently visible, with inner bindings hiding the outer ones they shadow.
+    pub fn visible(&self) -> Vec<(&String, &T)> {
+        let mut visible: HashMap<&String, &T> = HashMap::new();
+        visible.extend(self.scopes[0].iter());
+        for scope in &self.scopes[self.frame_start()..] {
+            visible.extend(scope.iter());
+        }
+        let mut visible: Vec<_> = visible.into_iter().collect();
+        visible.sort_by(|(left, _), (right, _)| left.cmp(right));
+        visible
+    }
+
+    fn frame_start(&self) -> usize {
+        self.frames.last().copied().unwrap_or(0)
+    }
+}
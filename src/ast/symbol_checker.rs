@@ -0,0 +1,317 @@
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    rc::Rc,
+};
+
+use crate::ast::{
+    lexer::{TextSpan, Token},
+    scopes::Scopes,
+    types::Type,
+    ASTAssignmentExpression, ASTBinaryOperatorKind, ASTBlockStatement, ASTBooleanExpression,
+    ASTBreakStatement, ASTCallExpression, ASTContinueStatement, ASTExpression, ASTExpressionKind,
+    ASTFloatExpression, ASTFunctionDeclaration, ASTIfStatement, ASTLetStatement,
+    ASTNumberExpression, ASTParenthesizedExpression, ASTReturnStatement, ASTStatementKind,
+    ASTStringExpression, ASTUnaryOperatorKind, ASTVariableExpression, ASTVisitor,
+    ASTWhileStatement, Ast,
+};
+use crate::diagnostics::DiagnosticsBagCell;
+
+#[derive(Clone)]
+pub struct Symbol {
+    pub declaration: TextSpan,
+    pub mutable: bool,
+}
+
+#[derive(Clone)]
+pub struct FunctionSymbol {
+    pub declaration: TextSpan,
+    pub parameter_count: usize,
+}
+
+#[derive(Clone)]
+pub struct SymbolChecker {
+    symbols: Scopes<Symbol>,
+    functions: HashMap<String, FunctionSymbol>,
+    /// The functions called in the body of each function.
+    calls: HashMap<String, Vec<String>>,
+    /// The calls made by the top-level code of the tree being checked.
+    top_level_calls: Vec<Token>,
+    /// Names whose declaring scope has already ended in the function being checked, or in the
+    /// top-level code, and that were not declared again since. Used to explain failed lookups.
+    out_of_scope: HashMap<String, Symbol>,
+    loop_depth: usize,
+    /// The function whose body is being checked.
+    function: Option<String>,
+    diagnostics_bag: DiagnosticsBagCell,
+}
+
+impl SymbolChecker {
+    pub fn new(diagnostics_bag: DiagnosticsBagCell) -> Self {
+        Self {
+            symbols: Scopes::new(),
+            functions: HashMap::new(),
+            calls: HashMap::new(),
+            top_level_calls: Vec::new(),
+            out_of_scope: HashMap::new(),
+            loop_depth: 0,
+            function: None,
+            diagnostics_bag,
+        }
+    }
+
+    /// Resolves the names of `ast`. Functions are declared up front, so that they can call each
+    /// other in any order. Top-level code runs in order though, so it may only call functions
+    /// declared before the call, and that only call such functions in turn: the globals they
+    /// read are then initialized.
+    pub fn check(&mut self, ast: &Ast) {
+        for statement in &ast.statements {
+            if let ASTStatementKind::FunctionDeclaration(function) = &statement.kind {
+                self.declare_function(function);
+            }
+        }
+        ast.visit(self);
+        for call in mem::take(&mut self.top_level_calls) {
+            if let Some((function, declaration)) = self.declared_after(&call) {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_call_before_declaration(&call, &function, declaration);
+            }
+        }
+    }
+
+    fn declare_function(&mut self, function: &ASTFunctionDeclaration) {
+        let name = &function.identifier.span.literal;
+        if let Some(previous) = self.functions.get(name) {
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_duplicate_function(&function.identifier, previous.declaration.clone());
+            return;
+        }
+        self.functions.insert(
+            name.clone(),
+            FunctionSymbol {
+                declaration: function.identifier.span.clone(),
+                parameter_count: function.parameters.len(),
+            },
+        );
+    }
+
+    /// The first function that `call` may run, directly or through other calls, and that is
+    /// declared after it.
+    fn declared_after(&self, call: &Token) -> Option<(String, TextSpan)> {
+        let mut pending = vec![call.span.literal.clone()];
+        let mut visited = HashSet::new();
+        while let Some(name) = pending.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            let Some(function) = self.functions.get(&name) else {
+                continue;
+            };
+            if function.declaration.start > call.span.start {
+                return Some((name, function.declaration.clone()));
+            }
+            pending.extend(self.calls.get(&name).into_iter().flatten().cloned());
+        }
+        None
+    }
+
+    fn declare(&mut self, identifier: &Token, mutable: bool) {
+        let name = &identifier.span.literal;
+        self.out_of_scope.remove(name);
+        self.symbols.declare(
+            name.clone(),
+            Symbol {
+                declaration: identifier.span.clone(),
+                mutable,
+            },
+        );
+    }
+
+    fn check_declared(&mut self, identifier: &Token) {
+        if self.symbols.lookup(&identifier.span.literal).is_some() {
+            return;
+        }
+        let mut diagnostics_bag = self.diagnostics_bag.borrow_mut();
+        if self.out_of_scope.contains_key(&identifier.span.literal) {
+            diagnostics_bag.report_variable_out_of_scope(identifier);
+        } else {
+            diagnostics_bag.report_undeclared_variable(identifier);
+        }
+    }
+}
+
+impl ASTVisitor for SymbolChecker {
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        self.visit_expression(&let_statement.initializer);
+        self.declare(&let_statement.identifier, let_statement.is_mutable());
+    }
+
+    fn visit_block_statement(&mut self, block: &ASTBlockStatement) {
+        self.symbols.enter_scope();
+        // Functions are only allowed at the top level. Nested ones are still declared for the
+        // whole block, like top-level ones, unless their name is taken, so that calls to them
+        // report nothing more.
+        for statement in &block.statements {
+            if let ASTStatementKind::FunctionDeclaration(function) = &statement.kind {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_nested_function(&function.identifier);
+                if !self
+                    .functions
+                    .contains_key(&function.identifier.span.literal)
+                {
+                    self.declare_function(function);
+                }
+            }
+        }
+        for statement in &block.statements {
+            self.visit_statement(statement);
+        }
+        self.out_of_scope.extend(self.symbols.exit_scope());
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        self.visit_expression(&if_statement.condition);
+        self.visit_block_statement(&if_statement.then_branch);
+        if let Some(else_branch) = &if_statement.else_branch {
+            self.visit_statement(&else_branch.else_statement);
+        }
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        self.visit_expression(&while_statement.condition);
+        self.loop_depth += 1;
+        self.visit_block_statement(&while_statement.body);
+        self.loop_depth -= 1;
+    }
+
+    fn visit_break_statement(&mut self, break_statement: &ASTBreakStatement) {
+        if self.loop_depth == 0 {
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_outside_of_loop(&break_statement.break_keyword);
+        }
+    }
+
+    fn visit_continue_statement(&mut self, continue_statement: &ASTContinueStatement) {
+        if self.loop_depth == 0 {
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_outside_of_loop(&continue_statement.continue_keyword);
+        }
+    }
+
+    fn visit_function_declaration(&mut self, function: &Rc<ASTFunctionDeclaration>) {
+        // A nested function was already reported. Its body sees the variables around it, so
+        // that those it uses are not reported as undeclared on top of that.
+        let nested = !self.symbols.is_global();
+        let loop_depth = mem::replace(&mut self.loop_depth, 0);
+        let name = function.identifier.span.literal.clone();
+        let outer_function = mem::replace(&mut self.function, Some(name));
+        let mut outer_out_of_scope = HashMap::new();
+        if nested {
+            self.symbols.enter_scope();
+        } else {
+            self.symbols.enter_frame();
+            outer_out_of_scope = mem::take(&mut self.out_of_scope);
+        }
+        for parameter in &function.parameters {
+            let name = &parameter.identifier.span.literal;
+            if self.symbols.is_declared_locally(name) {
+                let previous = self.symbols.lookup(name).unwrap().declaration.clone();
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_duplicate_parameter(&parameter.identifier, previous);
+            }
+            self.declare(&parameter.identifier, false);
+        }
+        self.visit_block_statement(&function.body);
+        if nested {
+            self.out_of_scope.extend(self.symbols.exit_scope());
+        } else {
+            self.symbols.exit_frame();
+            self.out_of_scope = outer_out_of_scope;
+        }
+        self.function = outer_function;
+        self.loop_depth = loop_depth;
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        if self.function.is_none() {
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_return_outside_function(&return_statement.return_keyword);
+        }
+        if let Some(return_value) = &return_statement.return_value {
+            self.visit_expression(return_value);
+        }
+    }
+
+    fn visit_call_expression(&mut self, call: &ASTCallExpression) {
+        for argument in &call.arguments {
+            self.visit_expression(argument);
+        }
+        let name = &call.identifier.span.literal;
+        if self.functions.contains_key(name) {
+            match &self.function {
+                Some(function) => self
+                    .calls
+                    .entry(function.clone())
+                    .or_default()
+                    .push(name.clone()),
+                None => self.top_level_calls.push(call.identifier.clone()),
+            }
+        }
+        let mut diagnostics_bag = self.diagnostics_bag.borrow_mut();
+        match self.functions.get(name) {
+            None => diagnostics_bag.report_undeclared_function(&call.identifier),
+            Some(function) if function.parameter_count != call.arguments.len() => diagnostics_bag
+                .report_invalid_argument_count(
+                    &call.identifier,
+                    function.parameter_count,
+                    call.arguments.len(),
+                    call.span(),
+                ),
+            Some(_) => {}
+        }
+    }
+
+    fn visit_variable_expression(&mut self, variable: &ASTVariableExpression) {
+        self.check_declared(variable.identifier());
+    }
+
+    fn visit_assignment_expression(&mut self, assignment: &ASTAssignmentExpression) {
+        self.visit_expression(&assignment.value);
+        self.check_declared(&assignment.identifier);
+        if let Some(symbol) = self.symbols.lookup(&assignment.identifier.span.literal) {
+            if !symbol.mutable {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_assignment_to_immutable(
+                        &assignment.identifier,
+                        TextSpan::combine(vec![
+                            assignment.identifier.span.clone(),
+                            assignment.value.span(),
+                        ]),
+                        symbol.declaration.clone(),
+                    );
+            }
+        }
+    }
+
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {}
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {}
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {}
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {}
+
+    fn visit_parenthesized_expression(&mut self, parenthesized: &ASTParenthesizedExpression) {
+        self.visit_expression(&parenthesized.expression);
+    }
+
+    fn visit_error(&mut self, span: &TextSpan) {}
+}
@@ -0,0 +1,630 @@
+use std::{cell::RefCell, collections::HashMap, mem, rc::Rc};
+
+use crate::ast::{
+    lexer::{TextSpan, Token},
+    scopes::Scopes,
+    types::Type,
+    ASTAssignmentExpression, ASTBinaryExpression, ASTBinaryOperatorKind, ASTBlockStatement,
+    ASTBooleanExpression, ASTBreakStatement, ASTCallExpression, ASTCastExpression,
+    ASTContinueStatement, ASTExpression, ASTExpressionId, ASTExpressionKind, ASTFloatExpression,
+    ASTFunctionDeclaration, ASTIfStatement, ASTLetStatement, ASTNumberExpression,
+    ASTReturnStatement, ASTStatement, ASTStatementKind, ASTStringExpression, ASTTypeAnnotation,
+    ASTUnaryExpression, ASTUnaryOperatorKind, ASTVariableExpression, ASTVisitor, ASTWhileStatement,
+    Ast,
+};
+use crate::diagnostics::{DiagnosticBag, DiagnosticsBagCell};
+
+#[derive(Clone, PartialEq)]
+pub struct FunctionType {
+    pub parameters: Vec<Type>,
+    pub return_type: Type,
+}
+
+/// Infers the type of every expression and reports the ones that are used inconsistently.
+///
+/// Unannotated parameters and return types are inferred from the calls and the bodies of
+/// their functions, see `infer`. Where a value of unknown type flows into a variable,
+/// parameter or return value of a known type, its type is checked at runtime instead.
+///
+/// Runs after the `SymbolChecker`, so every name it looks up is known to be declared.
+#[derive(Clone)]
+pub struct TypeChecker {
+    /// The inferred type of each expression, for later passes to read.
+    pub expression_types: HashMap<ASTExpressionId, Type>,
+    /// The expressions of unknown type whose value must be checked at runtime, with the type
+    /// it must have.
+    pub runtime_checks: HashMap<ASTExpressionId, Type>,
+    variables: Scopes<Type>,
+    functions: HashMap<String, FunctionType>,
+    /// The functions of the tree being checked, whose unannotated types are being inferred.
+    inferring: HashMap<String, Rc<ASTFunctionDeclaration>>,
+    /// The function being checked, if any.
+    function: Option<String>,
+    /// The declared return type of the function being checked, if any.
+    return_type: Option<Type>,
+    /// The type of the last visited expression or statement, like `ASTEvaluator::last_value`.
+    last_type: Type,
+    diagnostics_bag: DiagnosticsBagCell,
+}
+
+impl TypeChecker {
+    pub fn new(diagnostics_bag: DiagnosticsBagCell) -> Self {
+        Self {
+            expression_types: HashMap::new(),
+            runtime_checks: HashMap::new(),
+            variables: Scopes::new(),
+            functions: HashMap::new(),
+            inferring: HashMap::new(),
+            function: None,
+            return_type: None,
+            last_type: Type::Unit,
+            diagnostics_bag,
+        }
+    }
+
+    /// Checks `ast`. The signatures of its functions are known up front, so that they can call
+    /// each other in any order.
+    pub fn check(&mut self, ast: &Ast) {
+        for statement in &ast.statements {
+            if let ASTStatementKind::FunctionDeclaration(function) = &statement.kind {
+                self.declare_function(function);
+                self.inferring.insert(
+                    function.identifier.span.literal.clone(),
+                    Rc::clone(function),
+                );
+            }
+        }
+        self.infer(ast);
+        ast.visit(self);
+        // Later inputs of the REPL must not change the types that were checked.
+        self.inferring.clear();
+    }
+
+    /// Infers the types that the functions of `ast` leave unannotated: a parameter has the
+    /// type of the arguments passed to it and a function returns the type of the values it
+    /// returns, or `Type::Unknown` if they differ. They start out as `Type::Never`, for no
+    /// value at all, and `ast` is checked again without reporting anything until they stop
+    /// changing. Those that are still `Type::Never` then become `Type::Unknown`.
+    fn infer(&mut self, ast: &Ast) {
+        if self.inferring.is_empty() {
+            return;
+        }
+        let diagnostics_bag = mem::replace(
+            &mut self.diagnostics_bag,
+            Rc::new(RefCell::new(DiagnosticBag::new())),
+        );
+        let variables = self.variables.clone();
+        let runtime_checks = self.runtime_checks.clone();
+        for unreached_is_unknown in [false, true] {
+            if unreached_is_unknown {
+                for name in self.inferring.keys() {
+                    let function = self.functions.get_mut(name).unwrap();
+                    let types = function.parameters.iter_mut();
+                    for ty in types.chain([&mut function.return_type]) {
+                        if *ty == Type::Never {
+                            *ty = Type::Unknown;
+                        }
+                    }
+                }
+            }
+            loop {
+                let functions = self.functions.clone();
+                ast.visit(self);
+                self.variables = variables.clone();
+                if self.functions == functions {
+                    break;
+                }
+            }
+        }
+        self.runtime_checks = runtime_checks;
+        self.diagnostics_bag = diagnostics_bag;
+    }
+
+    fn declare_function(&mut self, function: &ASTFunctionDeclaration) {
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|parameter| {
+                self.resolve_annotation(&parameter.type_annotation)
+                    .unwrap_or(Type::Never)
+            })
+            .collect();
+        let return_type = self
+            .resolve_annotation(&function.return_type)
+            .unwrap_or(Type::Never);
+        self.functions.insert(
+            function.identifier.span.literal.clone(),
+            FunctionType {
+                parameters,
+                return_type,
+            },
+        );
+    }
+
+    pub fn type_of(&self, expression: &ASTExpression) -> Type {
+        self.expression_types
+            .get(&expression.id)
+            .copied()
+            .unwrap_or(Type::Unknown)
+    }
+
+    /// The signature of the function `name`, with the types inferred for its unannotated
+    /// parameters and return type.
+    pub fn function_type(&self, name: &str) -> &FunctionType {
+        &self.functions[name]
+    }
+
+    fn check_expression(&mut self, expression: &ASTExpression) -> Type {
+        self.visit_expression(expression);
+        self.last_type
+    }
+
+    fn expect_type(&mut self, expected: Type, expression: &ASTExpression) {
+        let found = self.check_expression(expression);
+        if !found.is_assignable_to(&expected) {
+            self.diagnostics_bag.borrow_mut().report_type_mismatch(
+                &expected,
+                &found,
+                expression.span(),
+            );
+        }
+        self.check_at_runtime(expected, expression);
+    }
+
+    /// Records that the value of `expression` is checked at runtime if its type is unknown
+    /// but must be `expected`.
+    fn check_at_runtime(&mut self, expected: Type, expression: &ASTExpression) {
+        if self.type_of(expression) == Type::Unknown
+            && !matches!(expected, Type::Unknown | Type::Never)
+        {
+            self.runtime_checks.insert(expression.id, expected);
+        }
+    }
+
+    /// Records the runtime checks of the values that the last of `statements` may produce,
+    /// which are returned from a function that must return `expected`.
+    fn check_returned_at_runtime(&mut self, expected: Type, statements: &[ASTStatement]) {
+        let Some(statement) = statements.last() else {
+            return;
+        };
+        match &statement.kind {
+            ASTStatementKind::Expression(expression) => self.check_at_runtime(expected, expression),
+            ASTStatementKind::LetStatement(let_statement)
+                if let_statement.type_annotation.is_none() =>
+            {
+                self.check_at_runtime(expected, &let_statement.initializer)
+            }
+            ASTStatementKind::Block(block) => {
+                self.check_returned_at_runtime(expected, &block.statements)
+            }
+            ASTStatementKind::If(if_statement) => {
+                self.check_returned_at_runtime(expected, &if_statement.then_branch.statements);
+                if let Some(else_branch) = &if_statement.else_branch {
+                    let else_statement = std::slice::from_ref(&*else_branch.else_statement);
+                    self.check_returned_at_runtime(expected, else_statement);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether the value of `statements` can be more than `()`, even when they are typed as `()`
+    /// because the branches of an if statement at their end disagree or the else branch is missing.
+    fn may_produce_value(&self, statements: &[ASTStatement]) -> bool {
+        let Some(statement) = statements.last() else {
+            return false;
+        };
+        match &statement.kind {
+            ASTStatementKind::Expression(expression) => self.type_of(expression) != Type::Unit,
+            ASTStatementKind::LetStatement(let_statement) => {
+                self.type_of(&let_statement.initializer) != Type::Unit
+            }
+            ASTStatementKind::Block(block) => self.may_produce_value(&block.statements),
+            ASTStatementKind::If(if_statement) => {
+                self.may_produce_value(&if_statement.then_branch.statements)
+                    || if_statement
+                        .else_branch
+                        .as_ref()
+                        .is_some_and(|else_branch| {
+                            self.may_produce_value(std::slice::from_ref(
+                                &*else_branch.else_statement,
+                            ))
+                        })
+            }
+            _ => false,
+        }
+    }
+
+    /// Widens the inferred return type of the function being checked to cover `ty`.
+    fn infer_return_type(&mut self, ty: Type) {
+        let Some(name) = &self.function else {
+            return;
+        };
+        if self.inferring.contains_key(name) {
+            let function = self.functions.get_mut(name).unwrap();
+            function.return_type = function.return_type.join(ty);
+        }
+    }
+
+    fn check_condition(&mut self, condition: &ASTExpression) {
+        let condition_type = self.check_expression(condition);
+        if !condition_type.is_assignable_to(&Type::Boolean) {
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_non_boolean_condition(&condition_type, condition.span());
+        }
+    }
+
+    /// Both branches of an if expression must produce the same type.
+    fn check_if_expression(&mut self, if_expression: &ASTIfStatement) {
+        self.check_condition(&if_expression.condition);
+        self.visit_block_statement(&if_expression.then_branch);
+        let then_type = self.last_type;
+        let Some(else_branch) = &if_expression.else_branch else {
+            // Already reported by the parser.
+            self.last_type = Type::Unknown;
+            return;
+        };
+        self.visit_statement(&else_branch.else_statement);
+        let else_type = self.last_type;
+        self.last_type = match Self::join_branches(then_type, else_type) {
+            Some(ty) => ty,
+            None => {
+                self.diagnostics_bag.borrow_mut().report_type_mismatch(
+                    &then_type,
+                    &else_type,
+                    else_branch.else_statement.span(),
+                );
+                Type::Unknown
+            }
+        };
+    }
+
+    fn join_branches(then_type: Type, else_type: Type) -> Option<Type> {
+        else_type
+            .is_assignable_to(&then_type)
+            .then(|| then_type.join(else_type))
+    }
+
+    fn resolve_annotation(&self, type_annotation: &Option<ASTTypeAnnotation>) -> Option<Type> {
+        let type_annotation = type_annotation.as_ref()?;
+        let resolved = Type::from_name(&type_annotation.type_name.span.literal);
+        if resolved.is_none() {
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_undeclared_type(&type_annotation.type_name);
+        }
+        Some(resolved.unwrap_or(Type::Unknown))
+    }
+
+    /// The type `operator` produces for the given operands, or `None` if it does not apply to them.
+    fn binary_operator_type(
+        operator: &ASTBinaryOperatorKind,
+        left: Type,
+        right: Type,
+    ) -> Option<Type> {
+        if left == Type::Never || right == Type::Never {
+            return Some(Type::Never);
+        }
+        // Arithmetic and comparisons take two ints or two floats, never one of each.
+        let number = match (left, right) {
+            (Type::Float, _) | (_, Type::Float) => Type::Float,
+            (Type::Unknown, Type::Unknown) => Type::Unknown,
+            _ => Type::Integer,
+        };
+        let (operand, result) = match operator {
+            ASTBinaryOperatorKind::Plus if left == Type::String || right == Type::String => {
+                (Type::String, Type::String)
+            }
+            ASTBinaryOperatorKind::Plus
+            | ASTBinaryOperatorKind::Minus
+            | ASTBinaryOperatorKind::Multiply
+            | ASTBinaryOperatorKind::Divide => (number, number),
+            ASTBinaryOperatorKind::LessThan
+            | ASTBinaryOperatorKind::LessThanOrEqual
+            | ASTBinaryOperatorKind::GreaterThan
+            | ASTBinaryOperatorKind::GreaterThanOrEqual => (number, Type::Boolean),
+            ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr => {
+                (Type::Boolean, Type::Boolean)
+            }
+            ASTBinaryOperatorKind::Equals | ASTBinaryOperatorKind::NotEquals => {
+                let comparable = left != Type::Unit && right != Type::Unit;
+                return (comparable && left.is_assignable_to(&right)).then_some(Type::Boolean);
+            }
+        };
+        (left.is_assignable_to(&operand) && right.is_assignable_to(&operand)).then_some(result)
+    }
+
+    fn unary_operator_type(operator: &ASTUnaryOperatorKind, operand: Type) -> Option<Type> {
+        let expected = match operator {
+            _ if operand == Type::Never => return Some(Type::Never),
+            ASTUnaryOperatorKind::Minus if operand == Type::Unknown => return Some(Type::Unknown),
+            ASTUnaryOperatorKind::Minus if operand == Type::Float => Type::Float,
+            ASTUnaryOperatorKind::Minus | ASTUnaryOperatorKind::BitwiseNot => Type::Integer,
+            ASTUnaryOperatorKind::LogicalNot => Type::Boolean,
+        };
+        operand.is_assignable_to(&expected).then_some(expected)
+    }
+
+    fn report_invalid_operands(&self, operator: &Token, left: Type, right: Type) {
+        let mut diagnostics_bag = self.diagnostics_bag.borrow_mut();
+        if matches!(
+            (left, right),
+            (Type::Integer, Type::Float) | (Type::Float, Type::Integer)
+        ) {
+            diagnostics_bag.report_mixed_number_types(operator, &left, &right);
+        } else {
+            diagnostics_bag.report_invalid_binary_operator(operator, &left, &right);
+        }
+    }
+}
+
+impl ASTVisitor for TypeChecker {
+    fn visit_statement(&mut self, statement: &ASTStatement) {
+        self.last_type = Type::Unit;
+        self.do_visit_statement(statement);
+    }
+
+    fn visit_expression(&mut self, expression: &ASTExpression) {
+        match &expression.kind {
+            ASTExpressionKind::If(if_expression) => self.check_if_expression(if_expression),
+            _ => self.do_visit_expression(expression),
+        }
+        self.expression_types.insert(expression.id, self.last_type);
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        let declared = self.resolve_annotation(&let_statement.type_annotation);
+        let ty = match declared {
+            Some(declared) => {
+                self.expect_type(declared, &let_statement.initializer);
+                declared
+            }
+            None => self.check_expression(&let_statement.initializer),
+        };
+        self.variables
+            .declare(let_statement.identifier.span.literal.clone(), ty);
+        // Like in the evaluator, the value of a `let` is the value it binds.
+        self.last_type = ty;
+    }
+
+    fn visit_block_statement(&mut self, block: &ASTBlockStatement) {
+        self.variables.enter_scope();
+        self.last_type = Type::Unit;
+        for statement in &block.statements {
+            self.visit_statement(statement);
+        }
+        self.variables.exit_scope();
+    }
+
+    // Branches of an if statement may disagree; its value is then simply not usable.
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        self.check_condition(&if_statement.condition);
+        self.visit_block_statement(&if_statement.then_branch);
+        let then_type = self.last_type;
+        self.last_type = match &if_statement.else_branch {
+            Some(else_branch) => {
+                self.visit_statement(&else_branch.else_statement);
+                Self::join_branches(then_type, self.last_type).unwrap_or(Type::Unit)
+            }
+            None => Type::Unit,
+        };
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        self.check_condition(&while_statement.condition);
+        self.visit_block_statement(&while_statement.body);
+        self.last_type = Type::Unit;
+    }
+
+    fn visit_break_statement(&mut self, break_statement: &ASTBreakStatement) {
+        self.last_type = Type::Never;
+    }
+
+    fn visit_continue_statement(&mut self, continue_statement: &ASTContinueStatement) {
+        self.last_type = Type::Never;
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        let (found, span) = match &return_statement.return_value {
+            Some(return_value) => (self.check_expression(return_value), return_value.span()),
+            None => (Type::Unit, return_statement.return_keyword.span.clone()),
+        };
+        match self.return_type {
+            Some(expected) => {
+                if !found.is_assignable_to(&expected) {
+                    self.diagnostics_bag
+                        .borrow_mut()
+                        .report_return_type_mismatch(&expected, &found, span);
+                }
+                if let Some(return_value) = &return_statement.return_value {
+                    self.check_at_runtime(expected, return_value);
+                }
+            }
+            None => self.infer_return_type(found),
+        }
+        self.last_type = Type::Never;
+    }
+
+    fn visit_function_declaration(&mut self, function: &Rc<ASTFunctionDeclaration>) {
+        let FunctionType {
+            parameters,
+            return_type,
+        } = self.functions[&function.identifier.span.literal].clone();
+        let return_type = function.return_type.as_ref().map(|_| return_type);
+
+        let name = function.identifier.span.literal.clone();
+        let outer_function = mem::replace(&mut self.function, Some(name));
+        let outer_return_type = mem::replace(&mut self.return_type, return_type);
+        self.variables.enter_frame();
+        for (parameter, ty) in function.parameters.iter().zip(parameters) {
+            self.variables
+                .declare(parameter.identifier.span.literal.clone(), ty);
+        }
+        self.visit_block_statement(&function.body);
+        let body_type = self.last_type;
+        self.variables.exit_frame();
+
+        // The value of the body is returned implicitly when no `return` is reached.
+        match return_type {
+            Some(return_type) => {
+                if !body_type.is_assignable_to(&return_type) {
+                    self.diagnostics_bag
+                        .borrow_mut()
+                        .report_return_type_mismatch(
+                            &return_type,
+                            &body_type,
+                            function.body.close_brace.span.clone(),
+                        );
+                }
+                self.check_returned_at_runtime(return_type, &function.body.statements);
+            }
+            None if body_type == Type::Unit
+                && self.may_produce_value(&function.body.statements) =>
+            {
+                self.infer_return_type(Type::Unknown)
+            }
+            None => self.infer_return_type(body_type),
+        }
+        self.return_type = outer_return_type;
+        self.function = outer_function;
+        self.last_type = Type::Unit;
+    }
+
+    fn visit_call_expression(&mut self, call: &ASTCallExpression) {
+        let name = &call.identifier.span.literal;
+        let function = self.functions.get(name).cloned();
+        match function {
+            Some(function) => {
+                let inferring = self.inferring.get(name).cloned();
+                for (index, argument) in call
+                    .arguments
+                    .iter()
+                    .enumerate()
+                    .take(function.parameters.len())
+                {
+                    let inferred = inferring.as_ref().is_some_and(|declaration| {
+                        declaration.parameters[index].type_annotation.is_none()
+                    });
+                    if inferred {
+                        let found = self.check_expression(argument);
+                        let parameter =
+                            &mut self.functions.get_mut(name).unwrap().parameters[index];
+                        *parameter = parameter.join(found);
+                    } else {
+                        self.expect_type(function.parameters[index], argument);
+                    }
+                }
+                for argument in call.arguments.iter().skip(function.parameters.len()) {
+                    self.check_expression(argument);
+                }
+                self.last_type = function.return_type;
+            }
+            None => {
+                for argument in &call.arguments {
+                    self.check_expression(argument);
+                }
+                self.last_type = Type::Unknown;
+            }
+        }
+    }
+
+    fn visit_assignment_expression(&mut self, assignment: &ASTAssignmentExpression) {
+        let variable_type = self
+            .variables
+            .lookup(&assignment.identifier.span.literal)
+            .copied()
+            .unwrap_or(Type::Unknown);
+        let value_type = self.check_expression(&assignment.value);
+        match assignment.operator.binary_operator() {
+            Some(operator) => {
+                if Self::binary_operator_type(&operator.kind, variable_type, value_type).is_none() {
+                    self.report_invalid_operands(&operator.token, variable_type, value_type);
+                }
+            }
+            None => {
+                if !value_type.is_assignable_to(&variable_type) {
+                    self.diagnostics_bag.borrow_mut().report_type_mismatch(
+                        &variable_type,
+                        &value_type,
+                        assignment.value.span(),
+                    );
+                }
+                self.check_at_runtime(variable_type, &assignment.value);
+            }
+        }
+        self.last_type = variable_type;
+    }
+
+    fn visit_binary_expression(&mut self, binary: &ASTBinaryExpression) {
+        let left = self.check_expression(&binary.left);
+        let right = self.check_expression(&binary.right);
+        self.last_type = match Self::binary_operator_type(&binary.operator.kind, left, right) {
+            Some(ty) => ty,
+            None => {
+                self.report_invalid_operands(&binary.operator.token, left, right);
+                Type::Unknown
+            }
+        };
+    }
+
+    fn visit_cast_expression(&mut self, cast: &ASTCastExpression) {
+        let from = self.check_expression(&cast.expression);
+        let Some(to) = Type::from_name(&cast.type_name.span.literal) else {
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_undeclared_type(&cast.type_name);
+            self.last_type = Type::Unknown;
+            return;
+        };
+        let is_number = |ty: Type| matches!(ty, Type::Integer | Type::Float);
+        if !(is_number(from) || matches!(from, Type::Unknown | Type::Never)) || !is_number(to) {
+            let span = TextSpan::combine(vec![cast.expression.span(), cast.type_name.span.clone()]);
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_invalid_cast(&from, &to, span);
+        }
+        self.last_type = to;
+    }
+
+    fn visit_unary_expression(&mut self, unary: &ASTUnaryExpression) {
+        let operand = self.check_expression(&unary.operand);
+        self.last_type = match Self::unary_operator_type(&unary.operator.kind, operand) {
+            Some(ty) => ty,
+            None => {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_invalid_unary_operator(&unary.operator.token, &operand);
+                Type::Unknown
+            }
+        };
+    }
+
+    fn visit_variable_expression(&mut self, variable: &ASTVariableExpression) {
+        self.last_type = self
+            .variables
+            .lookup(&variable.identifier().span.literal)
+            .copied()
+            .unwrap_or(Type::Unknown);
+    }
+
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {
+        self.last_type = Type::Integer;
+    }
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        self.last_type = Type::Float;
+    }
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
+        self.last_type = Type::Boolean;
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        self.last_type = Type::String;
+    }
+
+    fn visit_error(&mut self, span: &TextSpan) {
+        self.last_type = Type::Unknown;
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Integer,
+    Float,
+    Boolean,
+    String,
+    /// The type of statements and blocks that produce no value.
+    Unit,
+    /// Used where the type cannot be known statically, e.g. parameters that are passed
+    /// values of different types. It is compatible with every other type, and values of this
+    /// type are checked at runtime where a value of a known type is expected.
+    Unknown,
+    /// The type of statements that never complete, like `return`, and of code that is never
+    /// reached. It fits wherever a value of any type is expected.
+    Never,
+}
+
+impl Type {
+    /// Resolves the name used in a type annotation.
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "int" => Some(Type::Integer),
+            "float" => Some(Type::Float),
+            "bool" => Some(Type::Boolean),
+            "string" => Some(Type::String),
+            _ => None,
+        }
+    }
+
+    pub fn is_assignable_to(&self, other: &Type) -> bool {
+        matches!(self, Type::Unknown | Type::Never)
+            || matches!(other, Type::Unknown | Type::Never)
+            || self == other
+    }
+
+    /// The type of a value that comes from either `self` or `other`.
+    pub fn join(self, other: Type) -> Type {
+        match (self, other) {
+            (Type::Never, ty) | (ty, Type::Never) => ty,
+            _ if self == other => self,
+            _ => Type::Unknown,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Integer => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Boolean => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Unit => write!(f, "()"),
+            Type::Unknown => write!(f, "unknown"),
+            Type::Never => write!(f, "!"),
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{
+    evaluator::Value, lexer::TextSpan, types::Type, ASTBinaryOperatorKind, ASTUnaryOperatorKind,
+};
+use crate::ir::{self, BlockId, InstructionKind, Terminator, ValueId};
+
+/// A single VM instruction.
+///
+/// IR values that are loaded again live in local slots. Instructions load their operands onto
+/// the stack, whose slots may be empty when a value such as the result of a call is absent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    /// Pushes a value from the constant pool.
+    Constant(u32),
+    Pop,
+    /// Pushes an empty slot.
+    Unit,
+    LoadLocal(u32),
+    StoreLocal(u32),
+    LoadGlobal(u32),
+    StoreGlobal(u32),
+    /// Fails if the top of the stack holds no value.
+    ExpectValue,
+    /// Fails if the top of the stack is not a value of the given type.
+    ExpectType(Type),
+    /// Converts the top of the stack for an `as` cast.
+    Cast(Type),
+    Unary(ASTUnaryOperatorKind),
+    Binary(ASTBinaryOperatorKind),
+    Jump(u32),
+    /// Pops a condition and jumps if it is false.
+    JumpIfFalse(u32),
+    /// Pops a value and jumps if it is the given boolean. Used by `&&` and `||`.
+    JumpIfBoolean(bool, u32),
+    /// Calls a function with the arguments on top of the stack.
+    Call(u32),
+    /// Leaves the function, moving the top of the stack onto the caller's stack.
+    Return,
+}
+
+/// Instructions, each paired with the span to blame when it fails.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Instruction>,
+    pub spans: Vec<Option<TextSpan>>,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    /// The arguments of a call are stored in the first locals.
+    pub parameter_count: usize,
+    pub local_count: usize,
+    pub chunk: Chunk,
+}
+
+#[derive(Debug)]
+pub struct Program {
+    pub constants: Vec<Value>,
+    /// The top-level code is the function at index `MAIN_FUNCTION`.
+    pub functions: Vec<Function>,
+    pub global_count: usize,
+}
+
+pub const MAIN_FUNCTION: usize = 0;
+
+/// Compiles the IR into bytecode. Values that are used share local slots when their lifetimes
+/// do not overlap, and parameters keep the first slots; constants are pushed where they are used
+/// instead.
+pub struct BytecodeCompiler {
+    constants: Vec<Value>,
+    chunk: Chunk,
+    /// Values that are pushed by a single instruction rather than loaded from their slot.
+    inlined: HashMap<ValueId, Instruction>,
+    /// The slot of each value that is loaded from one. Other values are popped once computed.
+    slots: Vec<Option<u32>>,
+    block_starts: Vec<u32>,
+    /// Jumps to patch once every block has been placed, with the block they go to.
+    jumps: Vec<(usize, BlockId)>,
+}
+
+impl BytecodeCompiler {
+    pub fn compile(program: &ir::Program) -> Program {
+        let mut compiler = Self {
+            constants: Vec::new(),
+            chunk: Chunk::default(),
+            inlined: HashMap::new(),
+            slots: Vec::new(),
+            block_starts: Vec::new(),
+            jumps: Vec::new(),
+        };
+        let functions = program
+            .functions
+            .iter()
+            .map(|function| compiler.compile_function(function))
+            .collect();
+        Program {
+            constants: compiler.constants,
+            functions,
+            global_count: program.globals.len(),
+        }
+    }
+
+    fn compile_function(&mut self, function: &ir::Function) -> Function {
+        self.inlined.clear();
+        self.block_starts.clear();
+        for instruction in function.blocks.iter().flat_map(|block| &block.instructions) {
+            self.inline(instruction);
+        }
+        let local_count = self.allocate_slots(function);
+        for (index, block) in function.blocks.iter().enumerate() {
+            self.block_starts.push(self.position());
+            for instruction in &block.instructions {
+                self.compile_instruction(instruction);
+            }
+            let next = BlockId(index + 1);
+            self.compile_terminator(function, BlockId(index), next, &block.terminator);
+        }
+        for (index, block) in std::mem::take(&mut self.jumps) {
+            self.patch_jump(index, self.block_starts[block.0]);
+        }
+        Function {
+            name: function.name.clone(),
+            parameter_count: function.parameters.len(),
+            local_count,
+            chunk: std::mem::take(&mut self.chunk),
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Option<TextSpan>) -> usize {
+        self.chunk.code.push(instruction);
+        self.chunk.spans.push(span);
+        self.chunk.code.len() - 1
+    }
+
+    fn position(&self) -> u32 {
+        self.chunk.code.len() as u32
+    }
+
+    fn patch_jump(&mut self, index: usize, target: u32) {
+        match &mut self.chunk.code[index] {
+            Instruction::Jump(to)
+            | Instruction::JumpIfFalse(to)
+            | Instruction::JumpIfBoolean(_, to) => *to = target,
+            instruction => unreachable!("cannot patch {:?}", instruction),
+        }
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        let index = match self
+            .constants
+            .iter()
+            .position(|constant| *constant == value)
+        {
+            Some(index) => index,
+            None => {
+                self.constants.push(value);
+                self.constants.len() - 1
+            }
+        };
+        operand(index)
+    }
+
+    /// Records the values that are pushed where they are used rather than computed once.
+    fn inline(&mut self, instruction: &ir::Instruction) {
+        let Some(result) = instruction.result else {
+            return;
+        };
+        match &instruction.kind {
+            InstructionKind::Constant(value) => {
+                let index = self.constant(value.clone());
+                self.inlined.insert(result, Instruction::Constant(index));
+            }
+            InstructionKind::Unit => {
+                self.inlined.insert(result, Instruction::Unit);
+            }
+            _ => {}
+        }
+    }
+
+    /// Gives a slot to every value that is loaded from one and returns the number of slots.
+    /// Values that are live at the same time get different slots, found by coloring the
+    /// interference graph in the order values are numbered. Parameter `i` is in slot `i`, where
+    /// calls put the arguments.
+    fn allocate_slots(&mut self, function: &ir::Function) -> usize {
+        let interference = self.interference(function);
+        let mut used = HashSet::new();
+        for block in &function.blocks {
+            for phi in &block.phis {
+                self.add_uses(&mut used, phi.incoming.iter().map(|(_, source)| *source));
+            }
+            for instruction in &block.instructions {
+                self.add_uses(&mut used, instruction.kind.operands());
+            }
+            self.add_uses(&mut used, block.terminator.operands());
+        }
+        self.slots = vec![None; function.value_types.len()];
+        for (index, parameter) in function.parameters.iter().enumerate() {
+            self.slots[parameter.value.0] = Some(operand(index));
+        }
+        let mut slot_count = function.parameters.len();
+        for (value, neighbors) in interference.iter().enumerate() {
+            if self.slots[value].is_some() || !used.contains(&ValueId(value)) {
+                continue;
+            }
+            let taken: HashSet<u32> = neighbors
+                .iter()
+                .filter_map(|neighbor| self.slots[neighbor.0])
+                .collect();
+            let slot = (0..).find(|slot| !taken.contains(slot)).unwrap();
+            slot_count = slot_count.max(slot as usize + 1);
+            self.slots[value] = Some(slot);
+        }
+        slot_count
+    }
+
+    /// The values each value must not share a slot with: those live when it is stored.
+    fn interference(&self, function: &ir::Function) -> Vec<HashSet<ValueId>> {
+        let live_out = self.live_out(function);
+        let mut interference = vec![HashSet::new(); function.value_types.len()];
+        for (index, block) in function.blocks.iter().enumerate() {
+            let mut interfere = |value: ValueId, live: &HashSet<ValueId>| {
+                for other in live.iter().filter(|other| **other != value) {
+                    interference[value.0].insert(*other);
+                    interference[other.0].insert(value);
+                }
+            };
+            let mut live = live_out[index].clone();
+            self.add_uses(&mut live, block.terminator.operands());
+            for instruction in block.instructions.iter().rev() {
+                if let Some(result) = instruction.result {
+                    interfere(result, &live);
+                    live.remove(&result);
+                }
+                self.add_uses(&mut live, instruction.kind.operands());
+            }
+            // Phis are stored together at the end of each predecessor.
+            live.extend(block.phis.iter().map(|phi| phi.result));
+            for phi in &block.phis {
+                interfere(phi.result, &live);
+            }
+        }
+        interference
+    }
+
+    /// The values live at the end of each block, including the phi operands it passes on.
+    fn live_out(&self, function: &ir::Function) -> Vec<HashSet<ValueId>> {
+        let mut live_in = vec![HashSet::new(); function.blocks.len()];
+        let mut live_out = vec![HashSet::new(); function.blocks.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, block) in function.blocks.iter().enumerate().rev() {
+                let mut live = HashSet::new();
+                for successor in block.terminator.successors() {
+                    live.extend(live_in[successor.0].iter().copied());
+                    let sources = Self::phi_copies(function, BlockId(index), successor);
+                    self.add_uses(&mut live, sources.into_iter().map(|(_, source)| source));
+                }
+                live_out[index] = live.clone();
+                self.add_uses(&mut live, block.terminator.operands());
+                for instruction in block.instructions.iter().rev() {
+                    if let Some(result) = instruction.result {
+                        live.remove(&result);
+                    }
+                    self.add_uses(&mut live, instruction.kind.operands());
+                }
+                for phi in &block.phis {
+                    live.remove(&phi.result);
+                }
+                if live != live_in[index] {
+                    live_in[index] = live;
+                    changed = true;
+                }
+            }
+        }
+        live_out
+    }
+
+    fn add_uses(&self, live: &mut HashSet<ValueId>, uses: impl IntoIterator<Item = ValueId>) {
+        live.extend(
+            uses.into_iter()
+                .filter(|value| !self.inlined.contains_key(value)),
+        );
+    }
+
+    fn load(&mut self, value: ValueId) {
+        let instruction = match self.inlined.get(&value) {
+            Some(instruction) => *instruction,
+            None => Instruction::LoadLocal(self.slots[value.0].unwrap()),
+        };
+        self.emit(instruction, None);
+    }
+
+    fn store(&mut self, value: Option<ValueId>) {
+        match value.and_then(|value| self.slots[value.0]) {
+            Some(slot) => self.emit(Instruction::StoreLocal(slot), None),
+            None => self.emit(Instruction::Pop, None),
+        };
+    }
+
+    fn compile_instruction(&mut self, instruction: &ir::Instruction) {
+        let span = instruction.span.clone();
+        match &instruction.kind {
+            InstructionKind::Constant(_) | InstructionKind::Unit => {}
+            InstructionKind::Unary(operator, operand) => {
+                self.load(*operand);
+                self.emit(Instruction::Unary(*operator), span);
+                self.store(instruction.result);
+            }
+            InstructionKind::Binary(operator, left, right) => {
+                self.load(*left);
+                self.load(*right);
+                self.emit(Instruction::Binary(*operator), span);
+                self.store(instruction.result);
+            }
+            InstructionKind::ExpectValue(operand) => {
+                self.load(*operand);
+                self.emit(Instruction::ExpectValue, span);
+                self.emit(Instruction::Pop, None);
+            }
+            InstructionKind::ExpectType(operand, ty) => {
+                self.load(*operand);
+                self.emit(Instruction::ExpectType(*ty), span);
+                self.store(instruction.result);
+            }
+            InstructionKind::Cast(operand, target) => {
+                self.load(*operand);
+                self.emit(Instruction::Cast(*target), span);
+                self.store(instruction.result);
+            }
+            InstructionKind::LoadGlobal(global) => {
+                self.emit(Instruction::LoadGlobal(operand(global.0)), None);
+                self.store(instruction.result);
+            }
+            InstructionKind::StoreGlobal(global, value) => {
+                self.load(*value);
+                self.emit(Instruction::StoreGlobal(operand(global.0)), None);
+            }
+            InstructionKind::Call(callee, arguments) => {
+                for argument in arguments {
+                    self.load(*argument);
+                }
+                self.emit(Instruction::Call(operand(callee.0)), span);
+                self.store(instruction.result);
+            }
+        }
+    }
+
+    fn compile_terminator(
+        &mut self,
+        function: &ir::Function,
+        from: BlockId,
+        next: BlockId,
+        terminator: &Terminator,
+    ) {
+        match terminator {
+            Terminator::Jump(target) => self.compile_edge(function, from, *target, Some(next)),
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+                span,
+            } => {
+                self.load(*condition);
+                let jump = Instruction::JumpIfFalse(0);
+                let branches = (*else_block, *then_block);
+                self.compile_conditional(function, from, next, jump, Some(span.clone()), branches);
+            }
+            Terminator::ShortCircuit {
+                value,
+                decisive,
+                decided,
+                undecided,
+            } => {
+                self.load(*value);
+                let jump = Instruction::JumpIfBoolean(*decisive, 0);
+                let branches = (*decided, *undecided);
+                self.compile_conditional(function, from, next, jump, None, branches);
+            }
+            Terminator::Return(value) => {
+                self.load(*value);
+                self.emit(Instruction::Return, None);
+            }
+        }
+    }
+
+    /// Compiles a conditional `jump` to the first of `branches`, falling through to the second.
+    fn compile_conditional(
+        &mut self,
+        function: &ir::Function,
+        from: BlockId,
+        next: BlockId,
+        jump: Instruction,
+        span: Option<TextSpan>,
+        (taken, fallthrough): (BlockId, BlockId),
+    ) {
+        let jump = self.emit(jump, span);
+        if Self::phi_copies(function, from, taken).is_empty() {
+            self.jumps.push((jump, taken));
+            self.compile_edge(function, from, fallthrough, Some(next));
+        } else {
+            self.compile_edge(function, from, fallthrough, None);
+            self.patch_jump(jump, self.position());
+            self.compile_edge(function, from, taken, Some(next));
+        }
+    }
+
+    /// Moves the values of the phis of `to` for the edge from `from`, then jumps to `to` unless
+    /// it comes next.
+    fn compile_edge(
+        &mut self,
+        function: &ir::Function,
+        from: BlockId,
+        to: BlockId,
+        next: Option<BlockId>,
+    ) {
+        // The phis are assigned all at once: a phi may use the previous value of another one.
+        let copies = Self::phi_copies(function, from, to);
+        for (_, source) in &copies {
+            self.load(*source);
+        }
+        for (destination, _) in copies.iter().rev() {
+            self.store(Some(*destination));
+        }
+        if next != Some(to) {
+            let jump = self.emit(Instruction::Jump(0), None);
+            self.jumps.push((jump, to));
+        }
+    }
+
+    /// The phis of `to` with the value each takes when coming from `from`.
+    fn phi_copies(function: &ir::Function, from: BlockId, to: BlockId) -> Vec<(ValueId, ValueId)> {
+        function
+            .block(to)
+            .phis
+            .iter()
+            .map(|phi| {
+                let (_, source) = phi
+                    .incoming
+                    .iter()
+                    .find(|(block, _)| *block == from)
+                    .unwrap();
+                (phi.result, *source)
+            })
+            .collect()
+    }
+}
+
+/// An index as an instruction operand.
+fn operand(index: usize) -> u32 {
+    u32::try_from(index).expect("bytecode operands are limited to 32 bits")
+}
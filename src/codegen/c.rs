@@ -0,0 +1,916 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    mem,
+};
+
+use crate::ast::{
+    evaluator::{ArithmeticMode, RuntimeErrorKind, Value, MAX_CALL_DEPTH},
+    lexer::TextSpan,
+    types::Type,
+    ASTBinaryOperatorKind, ASTUnaryOperatorKind,
+};
+use crate::diagnostics::{printer::DiagnosticsPrinter, DiagnosticsBagCell, MAX_STACK_TRACE_FRAMES};
+use crate::ir::{
+    self, BlockId, FunctionId, GlobalId, InstructionKind, Terminator, ValueId, MAIN_FUNCTION,
+};
+use crate::text::SourceText;
+
+/// Support code shared by every program. Runtime errors print the diagnostic that the
+/// evaluator would report, so the rendered source locations are baked into the program.
+const RUNTIME: &str = r#"static int rt_depth;
+static int rt_stack[RT_MAX_CALL_DEPTH];
+
+_Noreturn static void rt_fail(int error) {
+    fprintf(stderr, "%s\n", rt_errors[error]);
+    for (int shown = 0; shown < rt_depth && shown < RT_MAX_STACK_TRACE_FRAMES; shown++) {
+        fputs(rt_calls[rt_stack[rt_depth - 1 - shown]], stderr);
+        if (shown == RT_MAX_STACK_TRACE_FRAMES - 1 && rt_depth > RT_MAX_STACK_TRACE_FRAMES) {
+            fprintf(stderr, " (%d earlier calls not shown)", rt_depth - RT_MAX_STACK_TRACE_FRAMES);
+        }
+        fputc('\n', stderr);
+    }
+    exit(4);
+}
+
+static inline void rt_enter(int call, int overflow) {
+    if (rt_depth >= RT_MAX_CALL_DEPTH) {
+        rt_fail(overflow);
+    }
+    rt_stack[rt_depth++] = call;
+}
+
+static inline int64_t rt_add(int64_t left, int64_t right, int overflow) {
+    if (!RT_WRAPPING && (right > 0 ? left > INT64_MAX - right : left < INT64_MIN - right)) {
+        rt_fail(overflow);
+    }
+    return (int64_t)((uint64_t)left + (uint64_t)right);
+}
+
+static inline int64_t rt_sub(int64_t left, int64_t right, int overflow) {
+    if (!RT_WRAPPING && (right < 0 ? left > INT64_MAX + right : left < INT64_MIN + right)) {
+        rt_fail(overflow);
+    }
+    return (int64_t)((uint64_t)left - (uint64_t)right);
+}
+
+static inline int64_t rt_mul(int64_t left, int64_t right, int overflow) {
+    int64_t result = (int64_t)((uint64_t)left * (uint64_t)right);
+    if (!RT_WRAPPING && left != 0 && right != 0
+        && ((left == -1 && right == INT64_MIN) || (right == -1 && left == INT64_MIN)
+            || (right != -1 && result / right != left))) {
+        rt_fail(overflow);
+    }
+    return result;
+}
+
+static inline int64_t rt_div(int64_t left, int64_t right, int division_by_zero, int overflow) {
+    if (right == 0) {
+        rt_fail(division_by_zero);
+    }
+    if (left == INT64_MIN && right == -1) {
+        if (!RT_WRAPPING) {
+            rt_fail(overflow);
+        }
+        return INT64_MIN;
+    }
+    return left / right;
+}
+
+static inline int64_t rt_neg(int64_t operand, int overflow) {
+    if (operand == INT64_MIN) {
+        if (!RT_WRAPPING) {
+            rt_fail(overflow);
+        }
+        return INT64_MIN;
+    }
+    return -operand;
+}
+"#;
+
+/// Names that the generated code or the headers it includes already use.
+const RESERVED_NAMES: &[&str] = &[
+    "auto",
+    "break",
+    "case",
+    "char",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extern",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "register",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "typedef",
+    "union",
+    "unsigned",
+    "void",
+    "volatile",
+    "while",
+    "bool",
+    "true",
+    "false",
+    "main",
+    "abort",
+    "abs",
+    "atexit",
+    "atof",
+    "atoi",
+    "atol",
+    "atoll",
+    "bsearch",
+    "calloc",
+    "clearerr",
+    "div",
+    "exit",
+    "fclose",
+    "feof",
+    "ferror",
+    "fflush",
+    "fgetc",
+    "fgetpos",
+    "fgets",
+    "fopen",
+    "fprintf",
+    "fputc",
+    "fputs",
+    "fread",
+    "free",
+    "freopen",
+    "fscanf",
+    "fseek",
+    "fsetpos",
+    "ftell",
+    "fwrite",
+    "getc",
+    "getchar",
+    "getenv",
+    "gets",
+    "imaxabs",
+    "imaxdiv",
+    "labs",
+    "ldiv",
+    "llabs",
+    "lldiv",
+    "malloc",
+    "mblen",
+    "mbstowcs",
+    "mbtowc",
+    "perror",
+    "printf",
+    "putc",
+    "putchar",
+    "puts",
+    "qsort",
+    "rand",
+    "realloc",
+    "remove",
+    "rename",
+    "rewind",
+    "scanf",
+    "setbuf",
+    "setvbuf",
+    "snprintf",
+    "sprintf",
+    "srand",
+    "sscanf",
+    "stderr",
+    "stdin",
+    "stdout",
+    "strtod",
+    "strtof",
+    "strtoimax",
+    "strtol",
+    "strtold",
+    "strtoll",
+    "strtoul",
+    "strtoull",
+    "strtoumax",
+    "system",
+    "tmpfile",
+    "tmpnam",
+    "ungetc",
+    "vfprintf",
+    "vfscanf",
+    "vprintf",
+    "vscanf",
+    "vsnprintf",
+    "vsprintf",
+    "vsscanf",
+    "wcstombs",
+    "wctomb",
+];
+
+/// Translates the IR of a checked program into a self-contained C11 program.
+///
+/// Every type must be known statically: parameters and returned values need a type, either
+/// annotated or inferred from the calls and the body of their function. Each IR value that is used
+/// becomes a C variable, and the basic blocks of a function are connected with `goto`.
+pub struct CGenerator<'a> {
+    program: &'a ir::Program,
+    arithmetic: ArithmeticMode,
+    printer: DiagnosticsPrinter<'a>,
+    diagnostics_bag: DiagnosticsBagCell,
+    /// Rendered runtime errors, indexed by the ids passed to `rt_fail`.
+    errors: Vec<String>,
+    /// Rendered stack trace entries, indexed by the ids passed to `rt_enter`.
+    calls: Vec<String>,
+    function_names: Vec<String>,
+    /// A global gets a C variable for every type it is declared with.
+    globals: HashMap<(GlobalId, Type), String>,
+    global_declarations: Vec<String>,
+    global_names: HashSet<String>,
+    /// Functions can call the ones defined after them, so each is declared up front.
+    function_prototypes: Vec<String>,
+    function_definitions: Vec<String>,
+    /// The literal of each constant of the function being generated.
+    constants: HashMap<ValueId, String>,
+    /// The values of the function being generated that have a C variable.
+    variables: HashSet<ValueId>,
+    /// Phis whose value is required to exist, with the span to blame if it does not.
+    expected: HashMap<ValueId, TextSpan>,
+    /// The blocks that some `goto` jumps to.
+    labels: HashSet<BlockId>,
+    temporary_count: usize,
+    lines: Vec<String>,
+}
+
+impl<'a> CGenerator<'a> {
+    pub fn generate(
+        program: &'a ir::Program,
+        text: &'a SourceText,
+        arithmetic: ArithmeticMode,
+        diagnostics_bag: DiagnosticsBagCell,
+    ) -> String {
+        let mut generator = Self {
+            program,
+            arithmetic,
+            printer: DiagnosticsPrinter::new(text, &[]),
+            diagnostics_bag,
+            errors: Vec::new(),
+            calls: Vec::new(),
+            function_names: Vec::new(),
+            globals: HashMap::new(),
+            global_declarations: Vec::new(),
+            global_names: RESERVED_NAMES.iter().map(|name| name.to_string()).collect(),
+            function_prototypes: Vec::new(),
+            function_definitions: Vec::new(),
+            constants: HashMap::new(),
+            variables: HashSet::new(),
+            expected: HashMap::new(),
+            labels: HashSet::new(),
+            temporary_count: 0,
+            lines: Vec::new(),
+        };
+        for (index, function) in program.functions.iter().enumerate() {
+            let name = if index == MAIN_FUNCTION.0 {
+                "main".to_string()
+            } else {
+                generator.unique_name(&function.name)
+            };
+            generator.function_names.push(name);
+        }
+        for index in (1..program.functions.len()).chain([MAIN_FUNCTION.0]) {
+            let definition = generator.generate_function(FunctionId(index));
+            generator.function_definitions.push(definition);
+        }
+        generator.finish(text)
+    }
+
+    fn finish(self, text: &SourceText) -> String {
+        let mut c = String::new();
+        writeln!(c, "/* Generated from {} */", text.name()).unwrap();
+        for header in ["inttypes.h", "stdbool.h", "stdint.h", "stdio.h", "stdlib.h"] {
+            writeln!(c, "#include <{}>", header).unwrap();
+        }
+        writeln!(c).unwrap();
+        let wrapping = self.arithmetic == ArithmeticMode::Wrapping;
+        writeln!(c, "#define RT_WRAPPING {}", wrapping as u8).unwrap();
+        writeln!(c, "#define RT_MAX_CALL_DEPTH {}", MAX_CALL_DEPTH).unwrap();
+        writeln!(
+            c,
+            "#define RT_MAX_STACK_TRACE_FRAMES {}",
+            MAX_STACK_TRACE_FRAMES
+        )
+        .unwrap();
+        writeln!(c).unwrap();
+        Self::write_table(&mut c, "rt_errors", &self.errors);
+        Self::write_table(&mut c, "rt_calls", &self.calls);
+        writeln!(c).unwrap();
+        c.push_str(RUNTIME);
+        if !self.global_declarations.is_empty() {
+            writeln!(c).unwrap();
+            for global in &self.global_declarations {
+                writeln!(c, "{}", global).unwrap();
+            }
+        }
+        if !self.function_prototypes.is_empty() {
+            writeln!(c).unwrap();
+            for prototype in &self.function_prototypes {
+                writeln!(c, "{};", prototype).unwrap();
+            }
+        }
+        for function in &self.function_definitions {
+            writeln!(c, "\n{}", function).unwrap();
+        }
+        c
+    }
+
+    fn write_table(c: &mut String, name: &str, entries: &[String]) {
+        writeln!(c, "static const char *const {}[] = {{", name).unwrap();
+        for entry in entries {
+            writeln!(c, "    \"{}\",", escape(entry)).unwrap();
+        }
+        // C does not allow empty arrays.
+        writeln!(c, "    0,\n}};").unwrap();
+    }
+
+    fn line(&mut self, line: String) {
+        self.lines.push(format!("    {}", line));
+    }
+
+    /// Registers the runtime error reported when the operation at `span` fails.
+    fn error(&mut self, kind: RuntimeErrorKind, span: &TextSpan) -> usize {
+        let error = self.printer.stringify_plain_span(span, &kind.to_string());
+        self.errors.push(error);
+        self.errors.len() - 1
+    }
+
+    fn c_type(ty: Type) -> &'static str {
+        match ty {
+            Type::Boolean => "bool",
+            _ => "int64_t",
+        }
+    }
+
+    /// Picks a C name for `name` that hides nothing the generated code needs. Source
+    /// identifiers contain no digits or underscores, so the suffixed names never clash with
+    /// them, nor with the numbered names of values and temporaries.
+    fn unique_name(&mut self, name: &str) -> String {
+        let mut candidate = name.to_string();
+        let mut suffix = 1;
+        while self.global_names.contains(&candidate) {
+            suffix += 1;
+            candidate = format!("{}_{}", name, suffix);
+        }
+        self.global_names.insert(candidate.clone());
+        candidate
+    }
+
+    fn global(&mut self, global: GlobalId, ty: Type) -> String {
+        if let Some(name) = self.globals.get(&(global, ty)) {
+            return name.clone();
+        }
+        // Shadowed globals are named like `g.1` in the IR.
+        let name = self.program.globals[global.0].replace('.', "_");
+        let name = self.unique_name(&name);
+        self.global_declarations
+            .push(format!("static {} {};", Self::c_type(ty), name));
+        self.globals.insert((global, ty), name.clone());
+        name
+    }
+
+    fn temporary(&mut self) -> String {
+        self.temporary_count += 1;
+        format!("t{}", self.temporary_count)
+    }
+
+    /// The C expression for `value`. Values without a variable hold no value or are only
+    /// used where generation failed or the program fails first, and stand for a placeholder.
+    fn operand(&self, value: ValueId) -> String {
+        if let Some(literal) = self.constants.get(&value) {
+            return literal.clone();
+        }
+        if self.variables.contains(&value) {
+            return value_name(value);
+        }
+        "0".to_string()
+    }
+
+    fn generate_function(&mut self, id: FunctionId) -> String {
+        let function = &self.program.functions[id.0];
+        let is_main = id == MAIN_FUNCTION;
+        self.analyze(function, is_main);
+        self.temporary_count = 0;
+        self.labels.clear();
+
+        let mut parameters = Vec::new();
+        for parameter in &function.parameters {
+            let ty = function.type_of(parameter.value);
+            if ty == Type::Unknown {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_missing_parameter_type(&parameter.name, parameter.span.clone(), "C");
+            }
+            self.variables.insert(parameter.value);
+            parameters.push(format!(
+                "{} {}",
+                Self::c_type(ty),
+                value_name(parameter.value)
+            ));
+        }
+
+        let mut block_starts = Vec::new();
+        for (index, block) in function.blocks.iter().enumerate() {
+            block_starts.push(self.lines.len());
+            for instruction in &block.instructions {
+                self.generate_instruction(function, instruction);
+            }
+            self.generate_terminator(function, is_main, BlockId(index), &block.terminator);
+        }
+        let mut body = mem::take(&mut self.lines);
+        for (index, start) in block_starts.into_iter().enumerate().rev() {
+            if self.labels.contains(&BlockId(index)) {
+                body.insert(start, format!("{}:", BlockId(index)));
+            }
+        }
+
+        let signature = if is_main {
+            "int main(void)".to_string()
+        } else {
+            format!(
+                "static {} {}({})",
+                function.return_type.map_or("void", Self::c_type),
+                self.function_names[id.0],
+                if parameters.is_empty() {
+                    "void".to_string()
+                } else {
+                    parameters.join(", ")
+                }
+            )
+        };
+        if !is_main {
+            self.function_prototypes.push(signature.clone());
+        }
+        let mut definition = format!("{} {{\n", signature);
+        let mut declarations: Vec<&ValueId> = self
+            .variables
+            .iter()
+            .filter(|value| {
+                !function
+                    .parameters
+                    .iter()
+                    .any(|parameter| parameter.value == **value)
+            })
+            .collect();
+        declarations.sort();
+        for value in &declarations {
+            let ty = Self::c_type(function.type_of(**value));
+            writeln!(definition, "    {} {};", ty, value_name(**value)).unwrap();
+        }
+        if !declarations.is_empty() {
+            definition.push('\n');
+        }
+        for line in body {
+            writeln!(definition, "{}", line).unwrap();
+        }
+        definition.push('}');
+        definition
+    }
+
+    /// Finds the constants, the values that need a C variable and the phis whose value is
+    /// required, and reports the calls whose value is used without a known type.
+    fn analyze(&mut self, function: &ir::Function, is_main: bool) {
+        self.constants.clear();
+        self.variables.clear();
+        self.expected.clear();
+        let mut uses = Vec::new();
+        let mut phis = HashMap::new();
+        for block in &function.blocks {
+            for phi in &block.phis {
+                phis.insert(phi.result, phi);
+                uses.extend(phi.incoming.iter().map(|(_, value)| *value));
+            }
+            for instruction in &block.instructions {
+                match &instruction.kind {
+                    InstructionKind::Constant(value) => {
+                        self.constants
+                            .insert(instruction.result.unwrap(), literal(value));
+                    }
+                    InstructionKind::ExpectValue(value) => {
+                        if phis.contains_key(value) {
+                            self.expected
+                                .insert(*value, instruction.span.clone().unwrap());
+                        }
+                    }
+                    kind => uses.extend(kind.operands()),
+                }
+            }
+            match &block.terminator {
+                // Functions without a return type discard the value of their body.
+                Terminator::Return(_) if !is_main && function.return_type.is_none() => {}
+                terminator => uses.extend(terminator.operands()),
+            }
+        }
+        // A phi flowing into a required phi is required as well.
+        let mut worklist: Vec<ValueId> = self.expected.keys().copied().collect();
+        while let Some(value) = worklist.pop() {
+            let span = self.expected[&value].clone();
+            for (_, incoming) in &phis[&value].incoming {
+                if phis.contains_key(incoming) && !self.expected.contains_key(incoming) {
+                    self.expected.insert(*incoming, span.clone());
+                    worklist.push(*incoming);
+                }
+            }
+        }
+
+        let mut reported = HashSet::new();
+        for value in uses {
+            match function.type_of(value) {
+                Type::Unit => {}
+                Type::Unknown if !reported.insert(value) => {}
+                Type::Unknown => {
+                    if let Some((callee, span)) = Self::call_of(function, value) {
+                        let name = &self.program.functions[callee.0].name;
+                        self.diagnostics_bag
+                            .borrow_mut()
+                            .report_missing_return_type(name, span, "C");
+                    } else if !self.constants.contains_key(&value) {
+                        self.variables.insert(value);
+                    }
+                }
+                _ if !self.constants.contains_key(&value) => {
+                    self.variables.insert(value);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The function called to compute `value` and the span of the call, if it is a call.
+    fn call_of(function: &ir::Function, value: ValueId) -> Option<(FunctionId, TextSpan)> {
+        function
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .find(|instruction| instruction.result == Some(value))
+            .and_then(|instruction| match &instruction.kind {
+                InstructionKind::Call(callee, _) => Some((*callee, instruction.span.clone()?)),
+                _ => None,
+            })
+    }
+
+    /// Emits `code` as the value of `result`, or only for its failures if it is not used.
+    fn assign(&mut self, result: Option<ValueId>, code: String, fallible: bool) {
+        match result {
+            Some(result) if self.variables.contains(&result) => {
+                self.line(format!("{} = {};", value_name(result), code))
+            }
+            _ if fallible => self.line(format!("(void){};", code)),
+            _ => {}
+        }
+    }
+
+    fn generate_instruction(&mut self, function: &ir::Function, instruction: &ir::Instruction) {
+        let span = instruction.span.as_ref();
+        match &instruction.kind {
+            InstructionKind::Constant(_) | InstructionKind::Unit => {}
+            InstructionKind::Unary(operator, operand) => {
+                let operand = self.operand(*operand);
+                let (code, fallible) = match operator {
+                    ASTUnaryOperatorKind::Minus => {
+                        let span = span.unwrap();
+                        let overflow = RuntimeErrorKind::IntegerOverflow {
+                            operator: span.literal.clone(),
+                        };
+                        let overflow = self.error(overflow, span);
+                        (format!("rt_neg({}, {})", operand, overflow), true)
+                    }
+                    ASTUnaryOperatorKind::BitwiseNot => (format!("~{}", operand), false),
+                    ASTUnaryOperatorKind::LogicalNot => (format!("!{}", operand), false),
+                };
+                self.assign(instruction.result, code, fallible);
+            }
+            InstructionKind::Binary(operator, left, right) => {
+                let (left, right) = (self.operand(*left), self.operand(*right));
+                let operator = match operator {
+                    ASTBinaryOperatorKind::Plus
+                    | ASTBinaryOperatorKind::Minus
+                    | ASTBinaryOperatorKind::Multiply
+                    | ASTBinaryOperatorKind::Divide => {
+                        let code = self.integer_operation(*operator, span.unwrap(), &left, &right);
+                        self.assign(instruction.result, code, true);
+                        return;
+                    }
+                    ASTBinaryOperatorKind::Equals => "==",
+                    ASTBinaryOperatorKind::NotEquals => "!=",
+                    ASTBinaryOperatorKind::LessThan => "<",
+                    ASTBinaryOperatorKind::LessThanOrEqual => "<=",
+                    ASTBinaryOperatorKind::GreaterThan => ">",
+                    ASTBinaryOperatorKind::GreaterThanOrEqual => ">=",
+                    ASTBinaryOperatorKind::LogicalAnd => "&&",
+                    ASTBinaryOperatorKind::LogicalOr => "||",
+                };
+                let code = format!("{} {} {}", left, operator, right);
+                self.assign(instruction.result, code, false);
+            }
+            InstructionKind::ExpectValue(value) => {
+                if function.type_of(*value) == Type::Unit {
+                    let missing = self.error(RuntimeErrorKind::MissingValue, span.unwrap());
+                    self.line(format!("rt_fail({});", missing));
+                }
+            }
+            // Operands of unknown type are reported, so the value already has the target type.
+            InstructionKind::ExpectType(operand, _) | InstructionKind::Cast(operand, _) => {
+                let operand = self.operand(*operand);
+                self.assign(instruction.result, operand, false);
+            }
+            InstructionKind::LoadGlobal(global) => {
+                let result = instruction.result.unwrap();
+                if self.variables.contains(&result) {
+                    let name = self.global(*global, function.type_of(result));
+                    self.line(format!("{} = {};", value_name(result), name));
+                }
+            }
+            InstructionKind::StoreGlobal(global, value) => {
+                let ty = function.type_of(*value);
+                if ty != Type::Unit {
+                    let name = self.global(*global, ty);
+                    let value = self.operand(*value);
+                    self.line(format!("{} = {};", name, value));
+                }
+            }
+            InstructionKind::Call(callee, arguments) => {
+                let span = span.unwrap();
+                let name = &self.program.functions[callee.0].name;
+                let call_site = self
+                    .printer
+                    .stringify_plain_span(span, &format!("in call to '{}'", name));
+                self.calls.push(call_site);
+                let call_site = self.calls.len() - 1;
+                let overflow = self.error(
+                    RuntimeErrorKind::StackOverflow {
+                        function: name.clone(),
+                    },
+                    span,
+                );
+                self.line(format!("rt_enter({}, {});", call_site, overflow));
+                let arguments: Vec<String> = arguments
+                    .iter()
+                    .map(|argument| self.operand(*argument))
+                    .collect();
+                let code = format!(
+                    "{}({})",
+                    self.function_names[callee.0],
+                    arguments.join(", ")
+                );
+                match instruction.result {
+                    Some(result)
+                        if self.variables.contains(&result)
+                            && self.program.functions[callee.0].return_type.is_some() =>
+                    {
+                        self.line(format!("{} = {};", value_name(result), code))
+                    }
+                    _ => self.line(format!("{};", code)),
+                }
+                self.line("rt_depth--;".to_string());
+            }
+        }
+    }
+
+    fn integer_operation(
+        &mut self,
+        operator: ASTBinaryOperatorKind,
+        span: &TextSpan,
+        left: &str,
+        right: &str,
+    ) -> String {
+        let overflow = RuntimeErrorKind::IntegerOverflow {
+            operator: span.literal.clone(),
+        };
+        let helper = match operator {
+            ASTBinaryOperatorKind::Plus => "rt_add",
+            ASTBinaryOperatorKind::Minus => "rt_sub",
+            ASTBinaryOperatorKind::Multiply => "rt_mul",
+            _ => {
+                let division_by_zero = self.error(RuntimeErrorKind::DivisionByZero, span);
+                let overflow = self.error(overflow, span);
+                return format!(
+                    "rt_div({}, {}, {}, {})",
+                    left, right, division_by_zero, overflow
+                );
+            }
+        };
+        let overflow = self.error(overflow, span);
+        format!("{}({}, {}, {})", helper, left, right, overflow)
+    }
+
+    fn generate_terminator(
+        &mut self,
+        function: &ir::Function,
+        is_main: bool,
+        from: BlockId,
+        terminator: &Terminator,
+    ) {
+        let next = BlockId(from.0 + 1);
+        match terminator {
+            Terminator::Jump(target) => {
+                for line in self.edge(function, from, *target, Some(next)) {
+                    self.line(line);
+                }
+            }
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+                span,
+            } => {
+                if function.type_of(*condition) == Type::Unit {
+                    let missing = self.error(RuntimeErrorKind::MissingValue, span);
+                    self.line(format!("rt_fail({});", missing));
+                    return;
+                }
+                let condition = self.operand(*condition);
+                self.branch(function, from, condition, *then_block, *else_block);
+            }
+            Terminator::ShortCircuit {
+                value,
+                decisive,
+                decided,
+                undecided,
+            } => {
+                let value = self.operand(*value);
+                let condition = if *decisive { value } else { negate(&value) };
+                self.branch(function, from, condition, *decided, *undecided);
+            }
+            Terminator::Return(value) => {
+                let operand = self.operand(*value);
+                if is_main {
+                    // The value of the last statement is the result of the program.
+                    match function.type_of(*value) {
+                        Type::Integer => {
+                            let operand = match self.constants.get(value) {
+                                Some(_) => format!("(int64_t){}", operand),
+                                None => operand,
+                            };
+                            self.line(format!("printf(\"%\" PRId64 \"\\n\", {});", operand))
+                        }
+                        Type::Boolean => {
+                            self.line(format!("puts({} ? \"true\" : \"false\");", operand))
+                        }
+                        _ => {}
+                    }
+                    self.line("return 0;".to_string());
+                } else if function.return_type.is_some() {
+                    self.line(format!("return {};", operand));
+                } else {
+                    self.line("return;".to_string());
+                }
+            }
+        }
+    }
+
+    /// Goes to `then_block` if `condition` holds and to `else_block` otherwise, preferring to
+    /// fall through to the block that comes next.
+    fn branch(
+        &mut self,
+        function: &ir::Function,
+        from: BlockId,
+        condition: String,
+        then_block: BlockId,
+        else_block: BlockId,
+    ) {
+        let next = BlockId(from.0 + 1);
+        let (condition, taken, fallthrough) = if then_block == next {
+            (negate(&condition), else_block, then_block)
+        } else {
+            (condition, then_block, else_block)
+        };
+        let taken = self.edge(function, from, taken, None);
+        match &taken[..] {
+            [line] => self.line(format!("if ({}) {}", condition, line)),
+            _ => {
+                self.line(format!("if ({}) {{", condition));
+                for line in taken {
+                    self.line(format!("    {}", line));
+                }
+                self.line("}".to_string());
+            }
+        }
+        for line in self.edge(function, from, fallthrough, Some(next)) {
+            self.line(line);
+        }
+    }
+
+    /// The statements that go from `from` to `to`: the phis of `to` take their value, then
+    /// control jumps unless `to` comes next.
+    fn edge(
+        &mut self,
+        function: &ir::Function,
+        from: BlockId,
+        to: BlockId,
+        next: Option<BlockId>,
+    ) -> Vec<String> {
+        let mut copies = Vec::new();
+        for phi in &function.block(to).phis {
+            let (_, source) = phi
+                .incoming
+                .iter()
+                .find(|(block, _)| *block == from)
+                .unwrap();
+            if function.type_of(*source) == Type::Unit && function.type_of(phi.result) != Type::Unit
+            {
+                // A branch of an if expression produced no value.
+                if let Some(span) = self.expected.get(&phi.result).cloned() {
+                    let missing = self.error(RuntimeErrorKind::MissingValue, &span);
+                    return vec![format!("rt_fail({});", missing)];
+                }
+            } else if self.variables.contains(&phi.result) {
+                copies.push((phi.result, self.operand(*source)));
+            }
+        }
+        // The phis take their values all at once, so a copy must not read a variable that an
+        // earlier copy already assigned.
+        let destinations: HashSet<String> = copies
+            .iter()
+            .map(|(destination, _)| value_name(*destination))
+            .collect();
+        let overlapping = copies.iter().any(|(destination, source)| {
+            *source != value_name(*destination) && destinations.contains(source)
+        });
+        let mut lines = Vec::new();
+        if overlapping {
+            let mut temporaries = Vec::new();
+            for (destination, source) in &copies {
+                let temporary = self.temporary();
+                let ty = Self::c_type(function.type_of(*destination));
+                lines.push(format!("{} {} = {};", ty, temporary, source));
+                temporaries.push(temporary);
+            }
+            for ((destination, _), temporary) in copies.iter().zip(temporaries) {
+                lines.push(format!("{} = {};", value_name(*destination), temporary));
+            }
+            // Keeps the declarations of the temporaries out of the way of labels.
+            lines.insert(0, "{".to_string());
+            for line in lines.iter_mut().skip(1) {
+                line.insert_str(0, "    ");
+            }
+            lines.push("}".to_string());
+        } else {
+            for (destination, source) in copies {
+                if source != value_name(destination) {
+                    lines.push(format!("{} = {};", value_name(destination), source));
+                }
+            }
+        }
+        if next != Some(to) {
+            self.labels.insert(to);
+            lines.push(format!("goto {};", to));
+        }
+        lines
+    }
+}
+
+/// Negative constants only come out of constant folding. `INT64_MIN` has no literal in C,
+/// whose `-` is applied to a positive literal that would not fit.
+fn literal(value: &Value) -> String {
+    match value {
+        Value::Integer(i64::MIN) => "INT64_MIN".to_string(),
+        Value::Integer(value) if *value < 0 => format!("({})", value),
+        value => value.to_string(),
+    }
+}
+
+fn value_name(value: ValueId) -> String {
+    format!("v{}", value.0)
+}
+
+fn negate(condition: &str) -> String {
+    match condition.strip_prefix('!') {
+        Some(operand) => operand.to_string(),
+        None => format!("!{}", condition),
+    }
+}
+
+fn escape(string: &str) -> String {
+    let mut escaped = String::new();
+    for byte in string.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b'\n' => escaped.push_str("\\n"),
+            // Avoids trigraphs.
+            b'?' => escaped.push_str("\\?"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => write!(escaped, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    escaped
+}
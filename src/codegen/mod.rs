@@ -0,0 +1,84 @@
+use std::rc::Rc;
+
+use crate::ast::{
+    lexer::{TextSpan, Token},
+    type_checker::TypeChecker,
+    types::Type,
+    ASTBlockStatement, ASTBooleanExpression, ASTCastExpression, ASTFloatExpression,
+    ASTFunctionDeclaration, ASTLetStatement, ASTNumberExpression, ASTStatement, ASTStatementKind,
+    ASTStringExpression, ASTTypeAnnotation, ASTVariableExpression, ASTVisitor, Ast,
+};
+
+pub mod bytecode;
+pub mod c;
+pub mod wasm;
+pub mod x86_64;
+
+/// Where `ast` first uses a float or a string, either as a literal, in a cast or in a type
+/// annotation, with the type it uses. Only `run` supports them.
+pub fn find_unsupported_type(ast: &Ast) -> Option<(TextSpan, Type)> {
+    let mut finder = UnsupportedTypeFinder { found: None };
+    ast.visit(&mut finder);
+    finder.found
+}
+
+struct UnsupportedTypeFinder {
+    found: Option<(TextSpan, Type)>,
+}
+
+impl UnsupportedTypeFinder {
+    fn found(&mut self, span: &TextSpan, ty: Type) {
+        if self.found.is_none() {
+            self.found = Some((span.clone(), ty));
+        }
+    }
+
+    fn check_type_name(&mut self, type_name: &Token) {
+        match Type::from_name(&type_name.span.literal) {
+            Some(ty @ (Type::Float | Type::String)) => self.found(&type_name.span, ty),
+            _ => {}
+        }
+    }
+
+    fn check_annotation(&mut self, type_annotation: &Option<ASTTypeAnnotation>) {
+        if let Some(type_annotation) = type_annotation {
+            self.check_type_name(&type_annotation.type_name);
+        }
+    }
+}
+
+impl ASTVisitor for UnsupportedTypeFinder {
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        self.check_annotation(&let_statement.type_annotation);
+        self.visit_expression(&let_statement.initializer);
+    }
+
+    fn visit_function_declaration(&mut self, function: &Rc<ASTFunctionDeclaration>) {
+        for parameter in &function.parameters {
+            self.check_annotation(&parameter.type_annotation);
+        }
+        self.check_annotation(&function.return_type);
+        self.visit_block_statement(&function.body);
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        self.found(&string.token.span, Type::String);
+    }
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        self.found(&float.token.span, Type::Float);
+    }
+
+    fn visit_cast_expression(&mut self, cast: &ASTCastExpression) {
+        self.visit_expression(&cast.expression);
+        self.check_type_name(&cast.type_name);
+    }
+
+    fn visit_variable_expression(&mut self, variable: &ASTVariableExpression) {}
+
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {}
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {}
+
+    fn visit_error(&mut self, span: &TextSpan) {}
+}
@@ -0,0 +1,1043 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    mem,
+    rc::Rc,
+};
+
+use crate::ast::{
+    evaluator::{ArithmeticMode, RuntimeErrorKind, MAX_CALL_DEPTH},
+    lexer::TextSpan,
+    scopes::Scopes,
+    type_checker::TypeChecker,
+    types::Type,
+    ASTAssignmentExpression, ASTBinaryExpression, ASTBinaryOperatorKind, ASTBlockStatement,
+    ASTBooleanExpression, ASTBreakStatement, ASTCallExpression, ASTContinueStatement,
+    ASTExpression, ASTExpressionKind, ASTFloatExpression, ASTFunctionDeclaration, ASTIfStatement,
+    ASTLetStatement, ASTNumberExpression, ASTParenthesizedExpression, ASTReturnStatement,
+    ASTStatement, ASTStatementKind, ASTStringExpression, ASTUnaryExpression, ASTUnaryOperatorKind,
+    ASTVariableExpression, ASTVisitor, ASTWhileStatement, Ast,
+};
+use crate::diagnostics::{printer::DiagnosticsPrinter, DiagnosticsBagCell};
+use crate::text::SourceText;
+
+/// Support code shared by every module. The host provides `rt.fail`, which receives the
+/// address of a NUL-terminated diagnostic in the exported memory and must not return.
+const RUNTIME: &str = r#"  (func $rt_enter (param $overflow i32)
+    global.get $rt_depth
+    global.get $rt_max_call_depth
+    i32.ge_u
+    if
+      local.get $overflow
+      call $rt_fail
+      unreachable
+    end
+    global.get $rt_depth
+    i32.const 1
+    i32.add
+    global.set $rt_depth
+  )
+  (func $rt_leave
+    global.get $rt_depth
+    i32.const 1
+    i32.sub
+    global.set $rt_depth
+  )
+  (func $rt_add (param $left i64) (param $right i64) (param $overflow i32) (result i64)
+    (local $result i64)
+    local.get $left
+    local.get $right
+    i64.add
+    local.set $result
+    global.get $rt_wrapping
+    i32.eqz
+    local.get $left
+    local.get $result
+    i64.xor
+    local.get $right
+    local.get $result
+    i64.xor
+    i64.and
+    i64.const 0
+    i64.lt_s
+    i32.and
+    if
+      local.get $overflow
+      call $rt_fail
+      unreachable
+    end
+    local.get $result
+  )
+  (func $rt_sub (param $left i64) (param $right i64) (param $overflow i32) (result i64)
+    (local $result i64)
+    local.get $left
+    local.get $right
+    i64.sub
+    local.set $result
+    global.get $rt_wrapping
+    i32.eqz
+    local.get $left
+    local.get $right
+    i64.xor
+    local.get $left
+    local.get $result
+    i64.xor
+    i64.and
+    i64.const 0
+    i64.lt_s
+    i32.and
+    if
+      local.get $overflow
+      call $rt_fail
+      unreachable
+    end
+    local.get $result
+  )
+  (func $rt_mul (param $left i64) (param $right i64) (param $overflow i32) (result i64)
+    (local $result i64)
+    local.get $left
+    local.get $right
+    i64.mul
+    local.set $result
+    global.get $rt_wrapping
+    i32.eqz
+    if
+      local.get $left
+      i64.const -1
+      i64.eq
+      if
+        local.get $right
+        i64.const -9223372036854775808
+        i64.eq
+        if
+          local.get $overflow
+          call $rt_fail
+          unreachable
+        end
+      else
+        local.get $left
+        i64.eqz
+        i32.eqz
+        if
+          local.get $result
+          local.get $left
+          i64.div_s
+          local.get $right
+          i64.ne
+          if
+            local.get $overflow
+            call $rt_fail
+            unreachable
+          end
+        end
+      end
+    end
+    local.get $result
+  )
+  (func $rt_div (param $left i64) (param $right i64) (param $division_by_zero i32) (param $overflow i32) (result i64)
+    local.get $right
+    i64.eqz
+    if
+      local.get $division_by_zero
+      call $rt_fail
+      unreachable
+    end
+    ;; `i64.div_s` traps on the one quotient that does not fit.
+    local.get $right
+    i64.const -1
+    i64.eq
+    if
+      i64.const 0
+      local.get $left
+      local.get $overflow
+      call $rt_sub
+      return
+    end
+    local.get $left
+    local.get $right
+    i64.div_s
+  )
+  (func $rt_neg (param $operand i64) (param $overflow i32) (result i64)
+    i64.const 0
+    local.get $operand
+    local.get $overflow
+    call $rt_sub
+  )
+"#;
+
+/// Where a statement hands its value.
+#[derive(Clone, PartialEq)]
+enum Target {
+    Discard,
+    /// Left on the operand stack with the given type. The evaluator fails at the span if there
+    /// is no value.
+    Push(Type, TextSpan),
+}
+
+#[derive(Clone)]
+struct Variable {
+    name: String,
+    ty: Type,
+    global: bool,
+}
+
+struct WasmFunction {
+    name: String,
+    /// `None` for functions without results.
+    return_type: Option<Type>,
+}
+
+/// Translates a checked `Ast` into a WebAssembly module in text format.
+///
+/// Integers are `i64` and booleans `i32` values, so every type must be known statically, as
+/// for the C backend. Top-level `let` bindings become globals and all others locals of the
+/// function they are declared in. The exported `main` function runs the program and returns
+/// the value of its last statement, if it has one, which must then have the same type on
+/// every path.
+/// Runtime errors pass the diagnostic the evaluator would report to the imported `rt.fail`,
+/// without the stack trace.
+pub struct WasmGenerator<'a> {
+    types: &'a TypeChecker,
+    arithmetic: ArithmeticMode,
+    printer: DiagnosticsPrinter<'a>,
+    diagnostics_bag: DiagnosticsBagCell,
+    /// The NUL-terminated runtime errors, placed at the start of the memory.
+    data: Vec<u8>,
+    globals: Vec<String>,
+    function_definitions: Vec<String>,
+    functions: HashMap<String, WasmFunction>,
+    variables: Scopes<Variable>,
+    global_names: HashSet<String>,
+    function_names: HashSet<String>,
+    /// The local names used so far by the function being generated.
+    local_names: HashSet<String>,
+    locals: Vec<String>,
+    /// The labels of the loops that enclose the instructions being generated.
+    loops: Vec<usize>,
+    loop_count: usize,
+    instructions: Vec<String>,
+    indent: usize,
+    /// The result type of the function being generated, `None` if it has none.
+    return_type: Option<Type>,
+    target: Target,
+    /// The type of the value the last expression left on the stack, `Type::Unit` if none.
+    last_type: Type,
+}
+
+impl<'a> WasmGenerator<'a> {
+    pub fn generate(
+        ast: &Ast,
+        text: &'a SourceText,
+        types: &'a TypeChecker,
+        arithmetic: ArithmeticMode,
+        diagnostics_bag: DiagnosticsBagCell,
+    ) -> String {
+        let mut generator = Self {
+            types,
+            arithmetic,
+            printer: DiagnosticsPrinter::new(text, &[]),
+            diagnostics_bag,
+            data: Vec::new(),
+            globals: Vec::new(),
+            function_definitions: Vec::new(),
+            functions: HashMap::new(),
+            variables: Scopes::new(),
+            global_names: HashSet::new(),
+            function_names: HashSet::new(),
+            local_names: HashSet::new(),
+            locals: Vec::new(),
+            loops: Vec::new(),
+            loop_count: 0,
+            instructions: Vec::new(),
+            indent: 2,
+            return_type: None,
+            target: Target::Discard,
+            last_type: Type::Unit,
+        };
+        // Functions can call the ones declared after them.
+        for statement in &ast.statements {
+            if let ASTStatementKind::FunctionDeclaration(function) = &statement.kind {
+                generator.declare_function(function);
+            }
+        }
+        // Only the last top-level statement decides the result of the program.
+        let result_type = ast
+            .statements
+            .last()
+            .map_or(Type::Unit, |statement| generator.result_type(statement));
+        if let Some(statement) = ast.statements.last() {
+            if !matches!(result_type, Type::Integer | Type::Boolean) {
+                generator.check_untyped_result(statement);
+            }
+        }
+        for (index, statement) in ast.statements.iter().enumerate() {
+            generator.target = match result_type {
+                Type::Integer | Type::Boolean if index + 1 == ast.statements.len() => {
+                    Target::Push(result_type, statement.span())
+                }
+                _ => Target::Discard,
+            };
+            generator.visit_statement(statement);
+        }
+        generator.finish(text, result_type)
+    }
+
+    fn declare_function(&mut self, function: &ASTFunctionDeclaration) {
+        // Functions whose values are of unknown type have no results; their calls cannot be used.
+        let identifier = &function.identifier.span.literal;
+        let return_type = match self.types.function_type(identifier).return_type {
+            Type::Unit | Type::Unknown => None,
+            ty => Some(ty),
+        };
+        let name = Self::unique_name(&mut self.function_names, &function.identifier.span.literal);
+        self.functions.insert(
+            function.identifier.span.literal.clone(),
+            WasmFunction { name, return_type },
+        );
+    }
+
+    fn finish(self, text: &SourceText, result_type: Type) -> String {
+        let mut wat = String::new();
+        writeln!(wat, ";; Generated from {}", text.name()).unwrap();
+        writeln!(wat, "(module").unwrap();
+        writeln!(
+            wat,
+            "  (import \"rt\" \"fail\" (func $rt_fail (param i32)))"
+        )
+        .unwrap();
+        let pages = self.data.len().div_ceil(65536).max(1);
+        writeln!(wat, "  (memory (export \"memory\") {})", pages).unwrap();
+        if !self.data.is_empty() {
+            writeln!(wat, "  (data (i32.const 0) \"{}\")", escape(&self.data)).unwrap();
+        }
+        let wrapping = self.arithmetic == ArithmeticMode::Wrapping;
+        writeln!(
+            wat,
+            "  (global $rt_wrapping i32 (i32.const {}))",
+            wrapping as u8
+        )
+        .unwrap();
+        writeln!(
+            wat,
+            "  (global $rt_max_call_depth i32 (i32.const {}))",
+            MAX_CALL_DEPTH
+        )
+        .unwrap();
+        writeln!(wat, "  (global $rt_depth (mut i32) (i32.const 0))").unwrap();
+        for global in &self.globals {
+            writeln!(wat, "  {}", global).unwrap();
+        }
+        wat.push_str(RUNTIME);
+        for function in &self.function_definitions {
+            wat.push_str(function);
+        }
+        let result = match result_type {
+            Type::Integer | Type::Boolean => format!(" (result {})", Self::value_type(result_type)),
+            _ => String::new(),
+        };
+        let main = Self::function_definition(
+            "$rt_main (export \"main\")".to_string(),
+            &result,
+            &self.locals,
+            &self.instructions,
+        );
+        wat.push_str(&main);
+        writeln!(wat, ")").unwrap();
+        wat
+    }
+
+    fn function_definition(
+        header: String,
+        signature: &str,
+        locals: &[String],
+        instructions: &[String],
+    ) -> String {
+        let mut definition = String::new();
+        writeln!(definition, "  (func {}{}", header, signature).unwrap();
+        for local in locals {
+            writeln!(definition, "    {}", local).unwrap();
+        }
+        for instruction in instructions {
+            writeln!(definition, "{}", instruction).unwrap();
+        }
+        writeln!(definition, "  )").unwrap();
+        definition
+    }
+
+    fn instruction(&mut self, instruction: impl Into<String>) {
+        self.instructions.push(format!(
+            "{}{}",
+            "  ".repeat(self.indent),
+            instruction.into()
+        ));
+    }
+
+    /// Registers the runtime error reported when the operation at `span` fails and returns its
+    /// address.
+    fn error(&mut self, kind: RuntimeErrorKind, span: &TextSpan) -> usize {
+        let error = self.printer.stringify_plain_span(span, &kind.to_string());
+        let address = self.data.len();
+        self.data.extend(error.bytes());
+        self.data.push(0);
+        address
+    }
+
+    fn fail(&mut self, kind: RuntimeErrorKind, span: &TextSpan) {
+        let error = self.error(kind, span);
+        self.instruction(format!("i32.const {}", error));
+        self.instruction("call $rt_fail");
+        self.instruction("unreachable");
+    }
+
+    fn value_type(ty: Type) -> &'static str {
+        match ty {
+            Type::Boolean => "i32",
+            _ => "i64",
+        }
+    }
+
+    /// The type of the value a top-level statement leaves, if every path leaves one.
+    fn result_type(&self, statement: &ASTStatement) -> Type {
+        match &statement.kind {
+            ASTStatementKind::Expression(expression) => self.types.type_of(expression),
+            ASTStatementKind::LetStatement(let_statement) => match &let_statement.type_annotation {
+                Some(annotation) => {
+                    Type::from_name(&annotation.type_name.span.literal).unwrap_or(Type::Unknown)
+                }
+                None => self.types.type_of(&let_statement.initializer),
+            },
+            ASTStatementKind::Block(block) => self.result_type_of_block(block),
+            ASTStatementKind::If(if_statement) => {
+                let Some(else_branch) = &if_statement.else_branch else {
+                    return Type::Unit;
+                };
+                let then_type = self.result_type_of_block(&if_statement.then_branch);
+                let else_type = self.result_type(&else_branch.else_statement);
+                if then_type == else_type {
+                    then_type
+                } else {
+                    Type::Unit
+                }
+            }
+            _ => Type::Unit,
+        }
+    }
+
+    /// Reports a last top-level statement that produces a value without a single type, which
+    /// `main` could not return. Values of other statements report themselves where they are used.
+    fn check_untyped_result(&self, statement: &ASTStatement) {
+        match &statement.kind {
+            ASTStatementKind::Expression(expression) => {
+                if let ASTExpressionKind::Call(call) = &Self::unparenthesized(expression).kind {
+                    if self.types.type_of(expression) == Type::Unknown {
+                        self.diagnostics_bag
+                            .borrow_mut()
+                            .report_missing_return_type(
+                                &call.identifier.span.literal,
+                                expression.span(),
+                                "WebAssembly",
+                            );
+                    }
+                }
+            }
+            ASTStatementKind::Block(_) | ASTStatementKind::If(_)
+                if self.may_produce_value(statement) =>
+            {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_untyped_result(statement.span(), "WebAssembly");
+            }
+            _ => {}
+        }
+    }
+
+    fn may_produce_value(&self, statement: &ASTStatement) -> bool {
+        let in_block = |block: &ASTBlockStatement| {
+            block
+                .statements
+                .last()
+                .is_some_and(|statement| self.may_produce_value(statement))
+        };
+        match &statement.kind {
+            ASTStatementKind::Expression(expression) => {
+                self.types.type_of(expression) != Type::Unit
+            }
+            ASTStatementKind::LetStatement(_) => true,
+            ASTStatementKind::Block(block) => in_block(block),
+            ASTStatementKind::If(if_statement) => {
+                in_block(&if_statement.then_branch)
+                    || if_statement
+                        .else_branch
+                        .as_ref()
+                        .is_some_and(|else_branch| {
+                            self.may_produce_value(&else_branch.else_statement)
+                        })
+            }
+            _ => false,
+        }
+    }
+
+    fn result_type_of_block(&self, block: &ASTBlockStatement) -> Type {
+        block
+            .statements
+            .last()
+            .map_or(Type::Unit, |statement| self.result_type(statement))
+    }
+
+    /// A WebAssembly identifier for `name`, which differs from the names already in `names`.
+    /// Identifiers are ASCII-only, so other characters are written as their percent-encoded
+    /// UTF-8 bytes, like `caf%C3%A9` for `café`; source identifiers never contain `%`.
+    fn unique_name(names: &mut HashSet<String>, name: &str) -> String {
+        let mut name = name.to_string();
+        if !name.is_ascii() {
+            name = name
+                .bytes()
+                .map(|byte| match byte.is_ascii() {
+                    true => (byte as char).to_string(),
+                    false => format!("%{:02X}", byte),
+                })
+                .collect();
+        }
+        let mut candidate = name.clone();
+        let mut suffix = 1;
+        while names.contains(&candidate) {
+            suffix += 1;
+            candidate = format!("{}{}", name, suffix);
+        }
+        names.insert(candidate.clone());
+        candidate
+    }
+
+    fn expression(&mut self, expression: &ASTExpression) -> Type {
+        self.visit_expression(expression);
+        mem::replace(&mut self.last_type, Type::Unit)
+    }
+
+    /// Generates an expression whose value is used, which fails at runtime if it has none.
+    fn value(&mut self, expression: &ASTExpression) -> Type {
+        let ty = self.typed(expression);
+        if ty != Type::Unit {
+            return ty;
+        }
+        self.fail(RuntimeErrorKind::MissingValue, &expression.span());
+        // The stack is polymorphic after `unreachable`, so any consumer type checks.
+        Type::Unknown
+    }
+
+    /// Generates an expression whose value may be used, which requires its type to be known.
+    fn typed(&mut self, expression: &ASTExpression) -> Type {
+        let ty = self.expression(expression);
+        if ty == Type::Unknown {
+            if let ASTExpressionKind::Call(call) = &Self::unparenthesized(expression).kind {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_missing_return_type(
+                        &call.identifier.span.literal,
+                        expression.span(),
+                        "WebAssembly",
+                    );
+            }
+        }
+        ty
+    }
+
+    fn unparenthesized(expression: &ASTExpression) -> &ASTExpression {
+        match &expression.kind {
+            ASTExpressionKind::Parenthesized(parenthesized) => {
+                Self::unparenthesized(&parenthesized.expression)
+            }
+            _ => expression,
+        }
+    }
+
+    fn deliver(&mut self, ty: Type, target: Target) {
+        match target {
+            Target::Discard => self.discard(ty),
+            Target::Push(_, span) => {
+                if ty == Type::Unit {
+                    self.fail(RuntimeErrorKind::MissingValue, &span);
+                }
+            }
+        }
+    }
+
+    fn discard(&mut self, ty: Type) {
+        if matches!(ty, Type::Integer | Type::Boolean) {
+            self.instruction("drop");
+        }
+    }
+
+    fn block(&mut self, block: &ASTBlockStatement, target: Target) {
+        self.variables.enter_scope();
+        if block.statements.is_empty() {
+            self.deliver(Type::Unit, target.clone());
+        }
+        for (index, statement) in block.statements.iter().enumerate() {
+            self.target = if index + 1 == block.statements.len() {
+                target.clone()
+            } else {
+                Target::Discard
+            };
+            self.visit_statement(statement);
+        }
+        self.variables.exit_scope();
+    }
+
+    fn variable(&self, identifier: &str) -> Variable {
+        self.variables
+            .lookup(identifier)
+            .cloned()
+            .expect("variables are resolved by the symbol checker")
+    }
+
+    fn get(&mut self, variable: &Variable) {
+        let scope = if variable.global { "global" } else { "local" };
+        self.instruction(format!("{}.get ${}", scope, variable.name));
+    }
+
+    fn set(&mut self, variable: &Variable) {
+        let scope = if variable.global { "global" } else { "local" };
+        self.instruction(format!("{}.set ${}", scope, variable.name));
+    }
+
+    /// Combines the two integers on top of the stack.
+    fn integer_operation(&mut self, kind: &ASTBinaryOperatorKind, span: &TextSpan) {
+        let overflow = RuntimeErrorKind::IntegerOverflow {
+            operator: span.literal.clone(),
+        };
+        let helper = match kind {
+            ASTBinaryOperatorKind::Plus => "$rt_add",
+            ASTBinaryOperatorKind::Minus => "$rt_sub",
+            ASTBinaryOperatorKind::Multiply => "$rt_mul",
+            _ => {
+                let division_by_zero = self.error(RuntimeErrorKind::DivisionByZero, span);
+                self.instruction(format!("i32.const {}", division_by_zero));
+                "$rt_div"
+            }
+        };
+        let overflow = self.error(overflow, span);
+        self.instruction(format!("i32.const {}", overflow));
+        self.instruction(format!("call {}", helper));
+    }
+
+    fn short_circuit(&mut self, binary: &ASTBinaryExpression) {
+        self.value(&binary.left);
+        self.instruction("if (result i32)");
+        self.indent += 1;
+        match binary.operator.kind {
+            ASTBinaryOperatorKind::LogicalAnd => {
+                self.value(&binary.right);
+                self.indent -= 1;
+                self.instruction("else");
+                self.indent += 1;
+                self.instruction("i32.const 0");
+            }
+            _ => {
+                self.instruction("i32.const 1");
+                self.indent -= 1;
+                self.instruction("else");
+                self.indent += 1;
+                self.value(&binary.right);
+            }
+        }
+        self.indent -= 1;
+        self.instruction("end");
+    }
+}
+
+impl ASTVisitor for WasmGenerator<'_> {
+    fn visit_statement(&mut self, statement: &ASTStatement) {
+        match &statement.kind {
+            ASTStatementKind::Expression(expression) => {
+                let target = mem::replace(&mut self.target, Target::Discard);
+                let ty = if target == Target::Discard {
+                    self.expression(expression)
+                } else {
+                    self.typed(expression)
+                };
+                self.deliver(ty, target);
+            }
+            ASTStatementKind::While(_) | ASTStatementKind::FunctionDeclaration(_) => {
+                let target = mem::replace(&mut self.target, Target::Discard);
+                self.do_visit_statement(statement);
+                self.deliver(Type::Unit, target);
+            }
+            _ => self.do_visit_statement(statement),
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &ASTExpression) {
+        match &expression.kind {
+            ASTExpressionKind::If(if_expression) => {
+                let ty = self.types.type_of(expression);
+                self.target = Target::Push(ty, expression.span());
+                self.visit_if_statement(if_expression);
+                self.last_type = match ty {
+                    Type::Integer | Type::Boolean => ty,
+                    _ => {
+                        // No branch completes with a value.
+                        self.instruction("unreachable");
+                        Type::Unknown
+                    }
+                };
+            }
+            _ => self.do_visit_expression(expression),
+        }
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        let target = mem::replace(&mut self.target, Target::Discard);
+        let value_type = self.value(&let_statement.initializer);
+        let ty = match &let_statement.type_annotation {
+            Some(annotation) => {
+                Type::from_name(&annotation.type_name.span.literal).unwrap_or(Type::Unknown)
+            }
+            None => value_type,
+        };
+        let identifier = let_statement.identifier.span.literal.clone();
+
+        let variable = if self.variables.is_global() {
+            // Redeclaring a global makes a new one, so functions keep the one they saw.
+            let name = Self::unique_name(&mut self.global_names, &identifier);
+            let value_type = Self::value_type(ty);
+            self.globals.push(format!(
+                "(global ${} (mut {}) ({}.const 0))",
+                name, value_type, value_type
+            ));
+            Variable {
+                name,
+                ty,
+                global: true,
+            }
+        } else {
+            let name = Self::unique_name(&mut self.local_names, &identifier);
+            self.locals
+                .push(format!("(local ${} {})", name, Self::value_type(ty)));
+            Variable {
+                name,
+                ty,
+                global: false,
+            }
+        };
+        self.set(&variable);
+        self.variables.declare(identifier, variable.clone());
+        if target != Target::Discard {
+            self.get(&variable);
+        }
+    }
+
+    fn visit_block_statement(&mut self, block: &ASTBlockStatement) {
+        let target = mem::replace(&mut self.target, Target::Discard);
+        self.block(block, target);
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        let target = mem::replace(&mut self.target, Target::Discard);
+        self.value(&if_statement.condition);
+        match &target {
+            Target::Push(ty @ (Type::Integer | Type::Boolean), _) => {
+                self.instruction(format!("if (result {})", Self::value_type(*ty)))
+            }
+            _ => self.instruction("if"),
+        }
+        self.indent += 1;
+        self.block(&if_statement.then_branch, target.clone());
+        self.indent -= 1;
+        match &if_statement.else_branch {
+            Some(else_branch) => {
+                self.instruction("else");
+                self.indent += 1;
+                match &else_branch.else_statement.kind {
+                    ASTStatementKind::Block(block) => self.block(block, target),
+                    _ => {
+                        self.target = target;
+                        self.visit_statement(&else_branch.else_statement);
+                    }
+                }
+                self.indent -= 1;
+            }
+            // A missing else branch produces no value.
+            None if target != Target::Discard => {
+                self.instruction("else");
+                self.indent += 1;
+                self.deliver(Type::Unit, target);
+                self.indent -= 1;
+            }
+            None => {}
+        }
+        self.instruction("end");
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        self.target = Target::Discard;
+        self.loop_count += 1;
+        let label = self.loop_count;
+        self.loops.push(label);
+        self.instruction(format!("block $break{}", label));
+        self.indent += 1;
+        self.instruction(format!("loop $continue{}", label));
+        self.indent += 1;
+        self.value(&while_statement.condition);
+        self.instruction("i32.eqz");
+        self.instruction(format!("br_if $break{}", label));
+        self.block(&while_statement.body, Target::Discard);
+        self.instruction(format!("br $continue{}", label));
+        self.indent -= 1;
+        self.instruction("end");
+        self.indent -= 1;
+        self.instruction("end");
+        self.loops.pop();
+    }
+
+    fn visit_break_statement(&mut self, break_statement: &ASTBreakStatement) {
+        let label = self
+            .loops
+            .last()
+            .expect("loops are checked by the symbol checker");
+        self.instruction(format!("br $break{}", label));
+    }
+
+    fn visit_continue_statement(&mut self, continue_statement: &ASTContinueStatement) {
+        let label = self
+            .loops
+            .last()
+            .expect("loops are checked by the symbol checker");
+        self.instruction(format!("br $continue{}", label));
+    }
+
+    fn visit_function_declaration(&mut self, function: &Rc<ASTFunctionDeclaration>) {
+        let declared = &self.functions[&function.identifier.span.literal];
+        let (name, return_type) = (declared.name.clone(), declared.return_type);
+
+        let outer_instructions = mem::take(&mut self.instructions);
+        let outer_locals = mem::take(&mut self.locals);
+        let outer_names = mem::take(&mut self.local_names);
+        let outer_loop_count = mem::replace(&mut self.loop_count, 0);
+        let outer_return_type = mem::replace(&mut self.return_type, return_type);
+        self.variables.enter_frame();
+        let mut signature = String::new();
+        let parameter_types = &self
+            .types
+            .function_type(&function.identifier.span.literal)
+            .parameters;
+        for (parameter, &ty) in function.parameters.iter().zip(parameter_types) {
+            if ty == Type::Unknown {
+                self.diagnostics_bag
+                    .borrow_mut()
+                    .report_missing_parameter_type(
+                        &parameter.identifier.span.literal,
+                        parameter.identifier.span.clone(),
+                        "WebAssembly",
+                    );
+            }
+            let identifier = &parameter.identifier.span.literal;
+            let parameter_name = Self::unique_name(&mut self.local_names, identifier);
+            write!(
+                signature,
+                " (param ${} {})",
+                parameter_name,
+                Self::value_type(ty)
+            )
+            .unwrap();
+            self.variables.declare(
+                identifier.clone(),
+                Variable {
+                    name: parameter_name,
+                    ty,
+                    global: false,
+                },
+            );
+        }
+        let body_target = match return_type {
+            Some(ty) => {
+                write!(signature, " (result {})", Self::value_type(ty)).unwrap();
+                Target::Push(ty, function.body.close_brace.span.clone())
+            }
+            None => Target::Discard,
+        };
+        self.block(&function.body, body_target);
+        self.variables.exit_frame();
+        let instructions = mem::replace(&mut self.instructions, outer_instructions);
+        let locals = mem::replace(&mut self.locals, outer_locals);
+        self.return_type = outer_return_type;
+        self.loop_count = outer_loop_count;
+        self.local_names = outer_names;
+
+        self.function_definitions.push(Self::function_definition(
+            format!("${}", name),
+            &signature,
+            &locals,
+            &instructions,
+        ));
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        if let Some(return_value) = &return_statement.return_value {
+            match self.return_type {
+                Some(_) => {
+                    self.value(return_value);
+                }
+                None => {
+                    let ty = self.expression(return_value);
+                    self.discard(ty);
+                }
+            }
+        }
+        self.instruction("return");
+    }
+
+    fn visit_call_expression(&mut self, call: &ASTCallExpression) {
+        for argument in &call.arguments {
+            self.value(argument);
+        }
+        let identifier = &call.identifier.span.literal;
+        let overflow = self.error(
+            RuntimeErrorKind::StackOverflow {
+                function: identifier.clone(),
+            },
+            &call.span(),
+        );
+        self.instruction(format!("i32.const {}", overflow));
+        self.instruction("call $rt_enter");
+        let name = self.functions[identifier].name.clone();
+        self.last_type = self.types.function_type(identifier).return_type;
+        self.instruction(format!("call ${}", name));
+        self.instruction("call $rt_leave");
+    }
+
+    fn visit_assignment_expression(&mut self, assignment: &ASTAssignmentExpression) {
+        let variable = self.variable(&assignment.identifier.span.literal);
+        if let Some(operator) = assignment.operator.binary_operator() {
+            // The value is evaluated before the variable is read.
+            let operand = self.value(&assignment.value);
+            if operand != Type::Unknown {
+                self.instruction("local.set $rt_operand");
+                if !self
+                    .locals
+                    .iter()
+                    .any(|local| local == "(local $rt_operand i64)")
+                {
+                    self.locals.push("(local $rt_operand i64)".to_string());
+                }
+                self.get(&variable);
+                self.instruction("local.get $rt_operand");
+                self.integer_operation(&operator.kind, &operator.token.span);
+            }
+        } else {
+            self.value(&assignment.value);
+        }
+        self.set(&variable);
+        self.get(&variable);
+        self.last_type = variable.ty;
+    }
+
+    fn visit_binary_expression(&mut self, binary: &ASTBinaryExpression) {
+        if matches!(
+            binary.operator.kind,
+            ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr
+        ) {
+            self.short_circuit(binary);
+            self.last_type = Type::Boolean;
+            return;
+        }
+        let left = self.value(&binary.left);
+        let right = self.value(&binary.right);
+        let prefix = if left == Type::Boolean || right == Type::Boolean {
+            "i32"
+        } else {
+            "i64"
+        };
+        let operation = match binary.operator.kind {
+            ASTBinaryOperatorKind::Plus
+            | ASTBinaryOperatorKind::Minus
+            | ASTBinaryOperatorKind::Multiply
+            | ASTBinaryOperatorKind::Divide => {
+                self.integer_operation(&binary.operator.kind, &binary.operator.token.span);
+                self.last_type = Type::Integer;
+                return;
+            }
+            ASTBinaryOperatorKind::Equals => "eq",
+            ASTBinaryOperatorKind::NotEquals => "ne",
+            ASTBinaryOperatorKind::LessThan => "lt_s",
+            ASTBinaryOperatorKind::LessThanOrEqual => "le_s",
+            ASTBinaryOperatorKind::GreaterThan => "gt_s",
+            ASTBinaryOperatorKind::GreaterThanOrEqual => "ge_s",
+            ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr => unreachable!(),
+        };
+        self.instruction(format!("{}.{}", prefix, operation));
+        self.last_type = Type::Boolean;
+    }
+
+    fn visit_unary_expression(&mut self, unary: &ASTUnaryExpression) {
+        self.value(&unary.operand);
+        self.last_type = match unary.operator.kind {
+            ASTUnaryOperatorKind::Minus => {
+                let span = &unary.operator.token.span;
+                let overflow = self.error(
+                    RuntimeErrorKind::IntegerOverflow {
+                        operator: span.literal.clone(),
+                    },
+                    span,
+                );
+                self.instruction(format!("i32.const {}", overflow));
+                self.instruction("call $rt_neg");
+                Type::Integer
+            }
+            ASTUnaryOperatorKind::BitwiseNot => {
+                self.instruction("i64.const -1");
+                self.instruction("i64.xor");
+                Type::Integer
+            }
+            ASTUnaryOperatorKind::LogicalNot => {
+                self.instruction("i32.eqz");
+                Type::Boolean
+            }
+        };
+    }
+
+    fn visit_variable_expression(&mut self, variable: &ASTVariableExpression) {
+        let variable = self.variable(&variable.identifier().span.literal);
+        self.get(&variable);
+        self.last_type = variable.ty;
+    }
+
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {
+        self.instruction(format!("i64.const {}", number.number));
+        self.last_type = Type::Integer;
+    }
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        unreachable!("floats are rejected before code generation");
+    }
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
+        self.instruction(format!("i32.const {}", boolean.value as u8));
+        self.last_type = Type::Boolean;
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        unreachable!("strings are rejected before code generation");
+    }
+
+    fn visit_parenthesized_expression(&mut self, parenthesized: &ASTParenthesizedExpression) {
+        self.visit_expression(&parenthesized.expression);
+    }
+
+    fn visit_error(&mut self, span: &TextSpan) {
+        unreachable!("syntax errors are reported before code generation");
+    }
+}
+
+/// Escapes bytes for a string literal in the text format.
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for byte in bytes {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(*byte as char),
+            _ => write!(escaped, "\\{:02x}", byte).unwrap(),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,1340 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    io::{self, Write as _},
+    mem,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::ast::{
+    evaluator::{ArithmeticMode, RuntimeErrorKind, Value, MAX_CALL_DEPTH},
+    lexer::TextSpan,
+    types::Type,
+    ASTBinaryOperatorKind, ASTUnaryOperatorKind,
+};
+use crate::diagnostics::{printer::DiagnosticsPrinter, MAX_STACK_TRACE_FRAMES};
+use crate::ir::{self, BlockId, FunctionId, InstructionKind, Terminator, ValueId, MAIN_FUNCTION};
+use crate::text::SourceText;
+
+const TAG_UNIT: i64 = 0;
+const TAG_INTEGER: i64 = 1;
+const TAG_BOOLEAN: i64 = 2;
+
+/// Support code shared by every program. Runtime errors print the diagnostic that the
+/// evaluator would report, so the rendered source locations are baked into the program.
+const RUNTIME: &str = r#"
+# rdi: file descriptor, rsi: tag, rdx: payload
+.Lrt_write_value:
+    cmpq $2, %rsi
+    je .Lrt_write_boolean
+    leaq .Lrt_integer_format(%rip), %rsi
+    xorl %eax, %eax
+    jmp dprintf@PLT
+.Lrt_write_boolean:
+    leaq .Lrt_false(%rip), %rsi
+    leaq .Lrt_true(%rip), %rax
+    testq %rdx, %rdx
+    cmovne %rax, %rsi
+    xorl %eax, %eax
+    jmp dprintf@PLT
+
+# rdi: file descriptor, rsi: tag, rdx: payload. Writes the type, then the value in
+# parentheses, as `Value::describe` does.
+.Lrt_write_described:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    movl %edi, %ebx
+    movq %rsi, %r12
+    movq %rdx, %r13
+    leaq .Lrt_integer_open(%rip), %rsi
+    leaq .Lrt_boolean_open(%rip), %rax
+    cmpq $2, %r12
+    cmove %rax, %rsi
+    call .Lrt_write_string
+    movl %ebx, %edi
+    movq %r12, %rsi
+    movq %r13, %rdx
+    call .Lrt_write_value
+    movl %ebx, %edi
+    leaq .Lrt_close(%rip), %rsi
+    call .Lrt_write_string
+    popq %r13
+    popq %r12
+    popq %rbx
+    ret
+
+# rdi: file descriptor, rsi: string
+.Lrt_write_string:
+    movq %rsi, %rdx
+    leaq .Lrt_string_format(%rip), %rsi
+    xorl %eax, %eax
+    jmp dprintf@PLT
+
+# rdi: diagnostic up to its message, rsi: message, rdx: number of values in .Lrt_operands
+# to append to the message
+.Lrt_fail:
+    pushq %rbp
+    movq %rsp, %rbp
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    pushq %r14
+    movq %rsi, %r12
+    movq %rdx, %r13
+    movq %rdi, %rsi
+    movl $2, %edi
+    call .Lrt_write_string
+    movl $2, %edi
+    movq %r12, %rsi
+    call .Lrt_write_string
+    xorl %ebx, %ebx
+.Lrt_fail_operands:
+    cmpq %r13, %rbx
+    jge .Lrt_fail_trace
+    testq %rbx, %rbx
+    je .Lrt_fail_operand
+    movl $2, %edi
+    leaq .Lrt_and(%rip), %rsi
+    call .Lrt_write_string
+.Lrt_fail_operand:
+    movq %rbx, %rax
+    shlq $4, %rax
+    leaq .Lrt_operands(%rip), %rcx
+    movq (%rcx,%rax), %rsi
+    movq 8(%rcx,%rax), %rdx
+    movl $2, %edi
+    call .Lrt_write_described
+    incq %rbx
+    jmp .Lrt_fail_operands
+.Lrt_fail_trace:
+    movl $2, %edi
+    leaq .Lrt_newline(%rip), %rsi
+    call .Lrt_write_string
+    movq .Lrt_depth(%rip), %rbx
+    xorl %r12d, %r12d
+.Lrt_fail_frame:
+    testq %rbx, %rbx
+    je .Lrt_fail_exit
+    cmpq $MAX_STACK_TRACE_FRAMES, %r12
+    je .Lrt_fail_exit
+    decq %rbx
+    leaq .Lrt_call_sites(%rip), %rax
+    movq (%rax,%rbx,8), %rsi
+    movl $2, %edi
+    call .Lrt_write_string
+    cmpq $MAX_STACK_TRACE_FRAMES - 1, %r12
+    jne .Lrt_fail_frame_end
+    movq .Lrt_depth(%rip), %rdx
+    subq $MAX_STACK_TRACE_FRAMES, %rdx
+    jle .Lrt_fail_frame_end
+    movl $2, %edi
+    leaq .Lrt_omitted_format(%rip), %rsi
+    xorl %eax, %eax
+    call dprintf@PLT
+.Lrt_fail_frame_end:
+    movl $2, %edi
+    leaq .Lrt_newline(%rip), %rsi
+    call .Lrt_write_string
+    incq %r12
+    jmp .Lrt_fail_frame
+.Lrt_fail_exit:
+    movl $4, %edi
+    call exit@PLT
+
+    .section .rodata
+.Lrt_integer_format:
+    .asciz "%ld"
+.Lrt_string_format:
+    .asciz "%s"
+.Lrt_omitted_format:
+    .asciz " (%ld earlier calls not shown)"
+.Lrt_true:
+    .asciz "true"
+.Lrt_false:
+    .asciz "false"
+.Lrt_and:
+    .asciz " and "
+.Lrt_integer_open:
+    .asciz "<int> ("
+.Lrt_boolean_open:
+    .asciz "<bool> ("
+.Lrt_close:
+    .asciz ")"
+.Lrt_newline:
+    .asciz "\n"
+
+    .bss
+    .p2align 4
+.Lrt_operands:
+    .zero 32
+.Lrt_depth:
+    .zero 8
+.Lrt_call_sites:
+    .zero 8 * MAX_CALL_DEPTH
+"#;
+
+/// How a value is stored. Values whose type is known statically take one machine word, the
+/// payload. The others also take a tag, since they are only typed at runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Representation {
+    /// Holds no value, so nothing is stored.
+    Unit,
+    Integer,
+    Boolean,
+    Tagged,
+}
+
+impl Representation {
+    fn of(ty: Type) -> Self {
+        match ty {
+            Type::Unit => Representation::Unit,
+            Type::Integer => Representation::Integer,
+            Type::Boolean => Representation::Boolean,
+            _ => Representation::Tagged,
+        }
+    }
+
+    /// The tag of the values stored this way, if it is known without running the program.
+    fn tag(self) -> Option<i64> {
+        match self {
+            Representation::Unit => Some(TAG_UNIT),
+            Representation::Integer => Some(TAG_INTEGER),
+            Representation::Boolean => Some(TAG_BOOLEAN),
+            Representation::Tagged => None,
+        }
+    }
+}
+
+/// Where the generated code finds a value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Location {
+    /// The value is not used, or holds nothing.
+    Nowhere,
+    Immediate(i64),
+    /// The offset from %rbp of the payload. The tag of a tagged value is the word below it.
+    Slot(i64),
+}
+
+/// Translates the IR into x86-64 assembly for the GNU assembler.
+///
+/// Every value that is used gets a stack slot, except constants, which are immediates. The
+/// static types decide how values are stored, see `Representation`: values of a known type
+/// are used as they are, and only the tags of the others are checked. Arguments are passed
+/// on the stack in two words each, the tag first. Results come back in %rax, with the tag in
+/// %rdx for functions whose result is tagged. Frames keep the stack 16-byte aligned.
+pub struct AssemblyGenerator<'a> {
+    program: &'a ir::Program,
+    arithmetic: ArithmeticMode,
+    printer: DiagnosticsPrinter<'a>,
+    assembly: String,
+    /// Code kept out of the way of the instructions: the reports of runtime errors, and the
+    /// phi copies of conditional branches.
+    out_of_line: String,
+    strings: Vec<String>,
+    string_labels: HashMap<String, usize>,
+    label_count: usize,
+    /// How each function returns its result.
+    returns: Vec<Representation>,
+    /// The function being generated, with how and where each of its values is stored.
+    function: FunctionId,
+    representations: Vec<Representation>,
+    locations: Vec<Location>,
+    /// Comparisons whose only use is the branch right after them, which tests the flags.
+    fused: HashSet<ValueId>,
+    /// The condition code of the fused comparison whose flags the branch tests.
+    condition: Option<&'static str>,
+}
+
+impl<'a> AssemblyGenerator<'a> {
+    pub fn generate(
+        program: &'a ir::Program,
+        text: &'a SourceText,
+        arithmetic: ArithmeticMode,
+    ) -> String {
+        let mut generator = Self {
+            program,
+            arithmetic,
+            printer: DiagnosticsPrinter::new(text, &[]),
+            assembly: String::new(),
+            out_of_line: String::new(),
+            strings: Vec::new(),
+            string_labels: HashMap::new(),
+            label_count: 0,
+            returns: Self::return_representations(program),
+            function: MAIN_FUNCTION,
+            representations: Vec::new(),
+            locations: Vec::new(),
+            fused: HashSet::new(),
+            condition: None,
+        };
+        generator.assembly.push_str("    .text\n    .globl main\n");
+        for index in 0..program.functions.len() {
+            generator.generate_function(FunctionId(index));
+        }
+        generator.finish()
+    }
+
+    /// Functions with a return type return their result untagged, unless one of their
+    /// `return`s hands back a value that is stored otherwise, like one that may be missing.
+    fn return_representations(program: &ir::Program) -> Vec<Representation> {
+        let mut returns: Vec<Representation> = program
+            .functions
+            .iter()
+            .map(|function| {
+                function
+                    .return_type
+                    .map_or(Representation::Tagged, Representation::of)
+            })
+            .collect();
+        loop {
+            let mut changed = false;
+            for (index, function) in program.functions.iter().enumerate() {
+                if returns[index] == Representation::Tagged {
+                    continue;
+                }
+                let representations = Self::representations(function, &returns);
+                let mismatched = function.blocks.iter().any(|block| match block.terminator {
+                    Terminator::Return(value) => representations[value.0] != returns[index],
+                    _ => false,
+                });
+                if mismatched {
+                    returns[index] = Representation::Tagged;
+                    changed = true;
+                }
+            }
+            if !changed {
+                return returns;
+            }
+        }
+    }
+
+    /// How each value of `function` is stored, which its static type decides, except for the
+    /// values of calls, globals of type unit, and phis whose sources are not all stored alike.
+    fn representations(function: &ir::Function, returns: &[Representation]) -> Vec<Representation> {
+        let mut representations: Vec<Representation> = function
+            .value_types
+            .iter()
+            .map(|ty| Representation::of(*ty))
+            .collect();
+        for parameter in &function.parameters {
+            representations[parameter.value.0] =
+                Self::parameter_representation(function.type_of(parameter.value));
+        }
+        for instruction in function.blocks.iter().flat_map(|block| &block.instructions) {
+            let Some(result) = instruction.result else {
+                continue;
+            };
+            match &instruction.kind {
+                InstructionKind::Call(callee, _) => representations[result.0] = returns[callee.0],
+                // Globals are stored with their tag, which says whether they hold a value.
+                InstructionKind::LoadGlobal(_)
+                    if representations[result.0] == Representation::Unit =>
+                {
+                    representations[result.0] = Representation::Tagged
+                }
+                _ => {}
+            }
+        }
+        // A branch of an if expression may produce no value, for instance.
+        let phis: Vec<&ir::Phi> = function
+            .blocks
+            .iter()
+            .flat_map(|block| &block.phis)
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for phi in &phis {
+                let representation = representations[phi.result.0];
+                if representation != Representation::Tagged
+                    && phi
+                        .incoming
+                        .iter()
+                        .any(|(_, source)| representations[source.0] != representation)
+                {
+                    representations[phi.result.0] = Representation::Tagged;
+                    changed = true;
+                }
+            }
+        }
+        representations
+    }
+
+    /// Arguments always hold a value, so only parameters of unknown type are tagged.
+    fn parameter_representation(ty: Type) -> Representation {
+        match Representation::of(ty) {
+            Representation::Unit => Representation::Tagged,
+            representation => representation,
+        }
+    }
+
+    /// The comparisons that end a block and whose only use is the branch of that block.
+    fn fused_comparisons(function: &ir::Function) -> HashSet<ValueId> {
+        let mut uses: HashMap<ValueId, usize> = HashMap::new();
+        for block in &function.blocks {
+            let sources = block
+                .phis
+                .iter()
+                .flat_map(|phi| phi.incoming.iter().map(|(_, source)| *source));
+            let operands = block
+                .instructions
+                .iter()
+                .flat_map(|instruction| instruction.kind.operands());
+            for value in sources.chain(operands).chain(block.terminator.operands()) {
+                *uses.entry(value).or_default() += 1;
+            }
+        }
+        function
+            .blocks
+            .iter()
+            .filter_map(|block| {
+                let instruction = block.instructions.last()?;
+                let InstructionKind::Binary(operator, ..) = instruction.kind else {
+                    return None;
+                };
+                match block.terminator {
+                    Terminator::Branch { condition, .. }
+                        if instruction.result == Some(condition)
+                            && Self::condition_code(operator).is_some()
+                            && uses[&condition] == 1 =>
+                    {
+                        Some(condition)
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Gives a location to every value of `function` and returns the size of its frame: the
+    /// slots of the values, then the arguments of its calls at the bottom.
+    fn allocate(&mut self, function: &ir::Function) -> i64 {
+        let mut used = HashSet::new();
+        let mut arguments = 0;
+        for block in &function.blocks {
+            for phi in &block.phis {
+                used.extend(phi.incoming.iter().map(|(_, source)| *source));
+            }
+            for instruction in &block.instructions {
+                used.extend(instruction.kind.operands());
+                if let InstructionKind::Call(_, values) = &instruction.kind {
+                    arguments = arguments.max(16 * values.len() as i64);
+                }
+            }
+            used.extend(block.terminator.operands());
+        }
+        self.locations = vec![Location::Nowhere; function.value_types.len()];
+        // The caller stored the arguments above the return address and the saved %rbp.
+        for (index, parameter) in function.parameters.iter().enumerate() {
+            self.locations[parameter.value.0] = Location::Slot(24 + 16 * index as i64);
+        }
+        for instruction in function.blocks.iter().flat_map(|block| &block.instructions) {
+            if let InstructionKind::Constant(value) = &instruction.kind {
+                let immediate = match value {
+                    Value::Integer(value) => *value,
+                    Value::Boolean(value) => *value as i64,
+                    Value::Float(_) | Value::Str(_) => {
+                        unreachable!("floats and strings are rejected before compiling")
+                    }
+                };
+                self.locations[instruction.result.unwrap().0] = Location::Immediate(immediate);
+            }
+        }
+        let mut size = 0;
+        for value in (0..function.value_types.len()).map(ValueId) {
+            if self.locations[value.0] != Location::Nowhere
+                || !used.contains(&value)
+                || self.fused.contains(&value)
+            {
+                continue;
+            }
+            match self.representations[value.0] {
+                Representation::Unit => {}
+                Representation::Tagged => {
+                    size += 16;
+                    self.locations[value.0] = Location::Slot(8 - size);
+                }
+                _ => {
+                    size += 8;
+                    self.locations[value.0] = Location::Slot(-size);
+                }
+            }
+        }
+        (size + arguments + 15) / 16 * 16
+    }
+
+    fn generate_function(&mut self, id: FunctionId) {
+        let function = &self.program.functions[id.0];
+        self.function = id;
+        self.representations = Self::representations(function, &self.returns);
+        self.fused = Self::fused_comparisons(function);
+        let frame_size = self.allocate(function);
+
+        if id == MAIN_FUNCTION {
+            self.label("main");
+        } else {
+            self.label(&Self::function_label(id));
+        }
+        self.line("pushq %rbp");
+        self.line("movq %rsp, %rbp");
+        if frame_size > 0 {
+            self.line(&format!("subq ${}, %rsp", frame_size));
+        }
+        for (index, block) in function.blocks.iter().enumerate() {
+            self.label(&self.block_label(BlockId(index)));
+            for instruction in &block.instructions {
+                self.generate_instruction(instruction);
+            }
+            self.generate_terminator(function, BlockId(index), &block.terminator);
+        }
+    }
+
+    fn generate_instruction(&mut self, instruction: &ir::Instruction) {
+        let result = instruction.result;
+        match &instruction.kind {
+            InstructionKind::Constant(_) | InstructionKind::Unit => {}
+            InstructionKind::Unary(operator, operand) => {
+                let span = instruction.span.as_ref().unwrap();
+                let tag = match operator {
+                    ASTUnaryOperatorKind::LogicalNot => TAG_BOOLEAN,
+                    _ => TAG_INTEGER,
+                };
+                self.expect_tags(&[*operand], tag, |generator| {
+                    generator.invalid_operands(span, &[*operand])
+                });
+                self.load(*operand, "%rax");
+                match operator {
+                    ASTUnaryOperatorKind::Minus => {
+                        self.line("negq %rax");
+                        self.check_overflow(span);
+                    }
+                    ASTUnaryOperatorKind::BitwiseNot => self.line("notq %rax"),
+                    ASTUnaryOperatorKind::LogicalNot => self.line("xorq $1, %rax"),
+                }
+                self.store(result, "%rax", tag);
+            }
+            InstructionKind::Binary(operator, left, right) => {
+                self.generate_binary(instruction, *operator, *left, *right)
+            }
+            InstructionKind::ExpectValue(value) => {
+                let span = instruction.span.as_ref().unwrap();
+                match self.tag(*value) {
+                    Some(TAG_UNIT) => {
+                        let missing = self.missing_value(span);
+                        self.line(&format!("jmp {}", missing));
+                    }
+                    Some(_) => {}
+                    None => {
+                        let missing = self.missing_value(span);
+                        self.line(&format!("cmpq ${}, {}", TAG_UNIT, self.tag_address(*value)));
+                        self.line(&format!("je {}", missing));
+                    }
+                }
+            }
+            InstructionKind::ExpectType(value, expected) => {
+                let span = instruction.span.as_ref().unwrap();
+                let tag = Self::type_tag(*expected);
+                let message = format!("Expected a value of type <{}>, found ", expected);
+                match self.tag(*value) {
+                    Some(TAG_UNIT) => {
+                        let missing = self.missing_value(span);
+                        self.line(&format!("jmp {}", missing));
+                    }
+                    Some(found) if found != tag => {
+                        let mismatch = self.error_path(span, &message, &[*value]);
+                        self.line(&format!("jmp {}", mismatch));
+                    }
+                    Some(_) => {}
+                    None => {
+                        let missing = self.missing_value(span);
+                        let mismatch = self.error_path(span, &message, &[*value]);
+                        let address = self.tag_address(*value);
+                        self.line(&format!("cmpq ${}, {}", TAG_UNIT, address));
+                        self.line(&format!("je {}", missing));
+                        self.line(&format!("cmpq ${}, {}", tag, address));
+                        self.line(&format!("jne {}", mismatch));
+                    }
+                }
+                self.copy_to_result(result, *value);
+            }
+            InstructionKind::Cast(value, target) => {
+                if *target != Type::Integer {
+                    unreachable!("floats are rejected before compiling");
+                }
+                let span = instruction.span.as_ref().unwrap();
+                self.expect_tags(&[*value], TAG_INTEGER, |generator| {
+                    generator.invalid_operands(span, &[*value])
+                });
+                self.copy_to_result(result, *value);
+            }
+            InstructionKind::LoadGlobal(global) => {
+                let Some(Location::Slot(offset)) = result.map(|result| self.locations[result.0])
+                else {
+                    return;
+                };
+                let address = 16 * global.0;
+                self.line(&format!("movq .Lglobals+{}(%rip), %rax", address + 8));
+                self.line(&format!("movq %rax, {}(%rbp)", offset));
+                if self.representations[result.unwrap().0] == Representation::Tagged {
+                    self.line(&format!("movq .Lglobals+{}(%rip), %rax", address));
+                    self.line(&format!("movq %rax, {}(%rbp)", offset - 8));
+                }
+            }
+            InstructionKind::StoreGlobal(global, value) => {
+                let address = 16 * global.0;
+                let tag = format!(".Lglobals+{}(%rip)", address);
+                self.copy(
+                    *value,
+                    &format!(".Lglobals+{}(%rip)", address + 8),
+                    Some(&tag),
+                );
+            }
+            InstructionKind::Call(callee, arguments) => {
+                self.generate_call(instruction, *callee, arguments)
+            }
+        }
+    }
+
+    fn generate_binary(
+        &mut self,
+        instruction: &ir::Instruction,
+        operator: ASTBinaryOperatorKind,
+        left: ValueId,
+        right: ValueId,
+    ) {
+        let span = instruction.span.as_ref().unwrap();
+        let operands = [left, right];
+        let invalid = |generator: &mut Self| generator.invalid_operands(span, &operands);
+        match operator {
+            ASTBinaryOperatorKind::Plus
+            | ASTBinaryOperatorKind::Minus
+            | ASTBinaryOperatorKind::Multiply => {
+                self.expect_tags(&operands, TAG_INTEGER, invalid);
+                let mnemonic = match operator {
+                    ASTBinaryOperatorKind::Plus => "addq",
+                    ASTBinaryOperatorKind::Minus => "subq",
+                    _ => "imulq",
+                };
+                self.load(left, "%rax");
+                let right = self.source(right, "%rcx");
+                self.line(&format!("{} {}, %rax", mnemonic, right));
+                self.check_overflow(span);
+                self.store(instruction.result, "%rax", TAG_INTEGER);
+            }
+            ASTBinaryOperatorKind::Divide => {
+                self.expect_tags(&operands, TAG_INTEGER, invalid);
+                let divisor = match self.locations[right.0] {
+                    Location::Immediate(divisor) => Some(divisor),
+                    _ => None,
+                };
+                let division_by_zero = RuntimeErrorKind::DivisionByZero.to_string();
+                self.load(right, "%rcx");
+                match divisor {
+                    Some(0) => {
+                        let division_by_zero = self.error_path(span, &division_by_zero, &[]);
+                        self.line(&format!("jmp {}", division_by_zero));
+                        return;
+                    }
+                    Some(_) => {}
+                    None => {
+                        let division_by_zero = self.error_path(span, &division_by_zero, &[]);
+                        self.line("testq %rcx, %rcx");
+                        self.line(&format!("je {}", division_by_zero));
+                    }
+                }
+                self.load(left, "%rax");
+                // `idiv` traps on the one quotient that does not fit, i64::MIN / -1.
+                let done = self.new_label();
+                if divisor.is_none() || divisor == Some(-1) {
+                    let divide = self.new_label();
+                    self.line("cmpq $-1, %rcx");
+                    self.line(&format!("jne {}", divide));
+                    self.line(&format!("movabsq ${}, %rdx", i64::MIN));
+                    self.line("cmpq %rdx, %rax");
+                    self.line(&format!("jne {}", divide));
+                    match self.arithmetic {
+                        ArithmeticMode::Checked => {
+                            let overflow = self.overflow(span);
+                            self.line(&format!("jmp {}", overflow));
+                        }
+                        // The wrapped quotient is i64::MIN, which is already in %rax.
+                        ArithmeticMode::Wrapping => self.line(&format!("jmp {}", done)),
+                    }
+                    self.label(&divide);
+                }
+                self.line("cqto");
+                self.line("idivq %rcx");
+                self.label(&done);
+                self.store(instruction.result, "%rax", TAG_INTEGER);
+            }
+            ASTBinaryOperatorKind::LessThan
+            | ASTBinaryOperatorKind::LessThanOrEqual
+            | ASTBinaryOperatorKind::GreaterThan
+            | ASTBinaryOperatorKind::GreaterThanOrEqual => {
+                self.expect_tags(&operands, TAG_INTEGER, invalid);
+                self.compare(instruction.result, left, right, operator);
+            }
+            ASTBinaryOperatorKind::Equals | ASTBinaryOperatorKind::NotEquals => {
+                // Both operands hold values, so equal tags mean they have the same type.
+                match (self.tag(left), self.tag(right)) {
+                    (Some(left), Some(right)) if left == right => {}
+                    (Some(_), Some(_)) => {
+                        let invalid = invalid(self);
+                        self.line(&format!("jmp {}", invalid));
+                    }
+                    (Some(tag), None) | (None, Some(tag)) => {
+                        let invalid = invalid(self);
+                        let tagged = if self.tag(left).is_none() {
+                            left
+                        } else {
+                            right
+                        };
+                        self.line(&format!("cmpq ${}, {}", tag, self.tag_address(tagged)));
+                        self.line(&format!("jne {}", invalid));
+                    }
+                    (None, None) => {
+                        let invalid = invalid(self);
+                        self.line(&format!("movq {}, %rcx", self.tag_address(left)));
+                        self.line(&format!("cmpq %rcx, {}", self.tag_address(right)));
+                        self.line(&format!("jne {}", invalid));
+                    }
+                }
+                self.compare(instruction.result, left, right, operator);
+            }
+            ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr => {
+                self.expect_tags(&operands, TAG_BOOLEAN, invalid);
+                let mnemonic = match operator {
+                    ASTBinaryOperatorKind::LogicalAnd => "andq",
+                    _ => "orq",
+                };
+                self.load(left, "%rax");
+                let right = self.source(right, "%rcx");
+                self.line(&format!("{} {}, %rax", mnemonic, right));
+                self.store(instruction.result, "%rax", TAG_BOOLEAN);
+            }
+        }
+    }
+
+    /// Compares two payloads. A fused comparison leaves the result in the flags for the branch
+    /// that follows it.
+    fn compare(
+        &mut self,
+        result: Option<ValueId>,
+        left: ValueId,
+        right: ValueId,
+        operator: ASTBinaryOperatorKind,
+    ) {
+        let condition = Self::condition_code(operator).unwrap();
+        self.load(left, "%rax");
+        let right = self.source(right, "%rcx");
+        self.line(&format!("cmpq {}, %rax", right));
+        match result {
+            Some(result) if self.fused.contains(&result) => self.condition = Some(condition),
+            _ => {
+                self.line(&format!("set{} %al", condition));
+                self.line("movzbl %al, %eax");
+                self.store(result, "%rax", TAG_BOOLEAN);
+            }
+        }
+    }
+
+    /// Calls keep the call site of every active frame for the stack traces of runtime errors.
+    fn generate_call(
+        &mut self,
+        instruction: &ir::Instruction,
+        callee: FunctionId,
+        arguments: &[ValueId],
+    ) {
+        let span = instruction.span.as_ref().unwrap();
+        let function = &self.program.functions[callee.0];
+        let call_site = self.string(
+            self.printer
+                .stringify_plain_span(span, &format!("in call to '{}'", function.name)),
+        );
+        let overflow = RuntimeErrorKind::StackOverflow {
+            function: function.name.clone(),
+        };
+        let overflow = self.error_path(span, &overflow.to_string(), &[]);
+        for (index, argument) in arguments.iter().enumerate() {
+            let parameter = function.parameters[index].value;
+            let representation = Self::parameter_representation(function.type_of(parameter));
+            let tag = format!("{}(%rsp)", 16 * index);
+            self.copy(
+                *argument,
+                &format!("{}(%rsp)", 16 * index + 8),
+                (representation == Representation::Tagged).then_some(tag.as_str()),
+            );
+        }
+        self.line("movq .Lrt_depth(%rip), %rax");
+        self.line(&format!("cmpq ${}, %rax", MAX_CALL_DEPTH));
+        self.line(&format!("jge {}", overflow));
+        self.line(&format!("leaq {}(%rip), %rcx", call_site));
+        self.line("leaq .Lrt_call_sites(%rip), %rdx");
+        self.line("movq %rcx, (%rdx,%rax,8)");
+        self.line("incq %rax");
+        self.line("movq %rax, .Lrt_depth(%rip)");
+        self.line(&format!("call {}", Self::function_label(callee)));
+        self.line("decq .Lrt_depth(%rip)");
+        if let Some(Location::Slot(offset)) =
+            instruction.result.map(|result| self.locations[result.0])
+        {
+            self.line(&format!("movq %rax, {}(%rbp)", offset));
+            if self.returns[callee.0] == Representation::Tagged {
+                self.line(&format!("movq %rdx, {}(%rbp)", offset - 8));
+            }
+        }
+    }
+
+    fn generate_terminator(
+        &mut self,
+        function: &ir::Function,
+        from: BlockId,
+        terminator: &Terminator,
+    ) {
+        match terminator {
+            Terminator::Jump(target) => self.edge(function, from, *target),
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+                span,
+            } => {
+                let code = match self.condition.take() {
+                    Some(code) => code,
+                    None => match (self.tag(*condition), self.locations[condition.0]) {
+                        (Some(TAG_BOOLEAN), Location::Immediate(value)) => {
+                            let target = if value != 0 { then_block } else { else_block };
+                            self.edge(function, from, *target);
+                            return;
+                        }
+                        (Some(TAG_BOOLEAN), _) => {
+                            let payload = self.source(*condition, "%rax");
+                            self.line(&format!("cmpq $0, {}", payload));
+                            "ne"
+                        }
+                        (Some(TAG_UNIT), _) => {
+                            let missing = self.missing_value(span);
+                            self.line(&format!("jmp {}", missing));
+                            return;
+                        }
+                        (Some(_), _) => {
+                            let non_boolean = self.non_boolean_condition(span, *condition);
+                            self.line(&format!("jmp {}", non_boolean));
+                            return;
+                        }
+                        (None, _) => {
+                            let missing = self.missing_value(span);
+                            let non_boolean = self.non_boolean_condition(span, *condition);
+                            let not_boolean = self.new_label();
+                            let tag = self.tag_address(*condition);
+                            self.line(&format!("cmpq ${}, {}", TAG_BOOLEAN, tag));
+                            self.line(&format!("jne {}", not_boolean));
+                            writeln!(self.out_of_line, "{}:", not_boolean).unwrap();
+                            self.out_of_line_line(&format!("cmpq ${}, {}", TAG_UNIT, tag));
+                            self.out_of_line_line(&format!("je {}", missing));
+                            self.out_of_line_line(&format!("jmp {}", non_boolean));
+                            let payload = self.source(*condition, "%rax");
+                            self.line(&format!("cmpq $0, {}", payload));
+                            "ne"
+                        }
+                    },
+                };
+                self.branch(function, from, code, *then_block, *else_block);
+            }
+            Terminator::ShortCircuit {
+                value,
+                decisive,
+                decided,
+                undecided,
+            } => match (self.tag(*value), self.locations[value.0]) {
+                (Some(TAG_BOOLEAN), Location::Immediate(boolean)) => {
+                    let target = if (boolean != 0) == *decisive {
+                        decided
+                    } else {
+                        undecided
+                    };
+                    self.edge(function, from, *target);
+                }
+                // A value that is not a boolean is reported with the right operand.
+                (Some(tag), _) if tag != TAG_BOOLEAN => self.edge(function, from, *undecided),
+                (tag, _) => {
+                    if tag.is_none() {
+                        let undecided = self.edge_target(function, from, *undecided);
+                        let tag = self.tag_address(*value);
+                        self.line(&format!("cmpq ${}, {}", TAG_BOOLEAN, tag));
+                        self.line(&format!("jne {}", undecided));
+                    }
+                    let payload = self.source(*value, "%rax");
+                    self.line(&format!("cmpq ${}, {}", *decisive as i64, payload));
+                    self.branch(function, from, "e", *decided, *undecided);
+                }
+            },
+            Terminator::Return(value) => {
+                if self.function == MAIN_FUNCTION {
+                    self.print_result(*value);
+                    self.line("xorl %eax, %eax");
+                } else {
+                    if self.representations[value.0] != Representation::Unit {
+                        self.load(*value, "%rax");
+                    }
+                    if self.returns[self.function.0] == Representation::Tagged {
+                        match self.tag(*value) {
+                            Some(tag) => self.line(&format!("movq ${}, %rdx", tag)),
+                            None => self.line(&format!("movq {}, %rdx", self.tag_address(*value))),
+                        }
+                    }
+                }
+                self.line("leave");
+                self.line("ret");
+            }
+        }
+    }
+
+    /// Prints the value of the last statement, which is the result of the program.
+    fn print_result(&mut self, value: ValueId) {
+        let skip = self.new_label();
+        match self.tag(value) {
+            Some(TAG_UNIT) => return,
+            Some(tag) => self.line(&format!("movq ${}, %rsi", tag)),
+            None => {
+                self.line(&format!("movq {}, %rsi", self.tag_address(value)));
+                self.line(&format!("cmpq ${}, %rsi", TAG_UNIT));
+                self.line(&format!("je {}", skip));
+            }
+        }
+        self.load(value, "%rdx");
+        self.line("movl $1, %edi");
+        self.line("call .Lrt_write_value");
+        self.line("movl $1, %edi");
+        self.line("leaq .Lrt_newline(%rip), %rsi");
+        self.line("call .Lrt_write_string");
+        self.label(&skip);
+    }
+
+    /// Goes to `when_true` if the flags satisfy `condition` and to `when_false` otherwise,
+    /// preferring to fall through to the block that comes next.
+    fn branch(
+        &mut self,
+        function: &ir::Function,
+        from: BlockId,
+        condition: &str,
+        when_true: BlockId,
+        when_false: BlockId,
+    ) {
+        let (condition, taken, fallthrough) = if when_true.0 == from.0 + 1 {
+            (Self::negate(condition), when_false, when_true)
+        } else {
+            (condition, when_true, when_false)
+        };
+        let target = self.edge_target(function, from, taken);
+        self.line(&format!("j{} {}", condition, target));
+        self.edge(function, from, fallthrough);
+    }
+
+    /// Gives the phis of `to` their values for the edge from `from`, then jumps to `to` unless
+    /// it comes next.
+    fn edge(&mut self, function: &ir::Function, from: BlockId, to: BlockId) {
+        self.phi_copies(function, from, to);
+        if to.0 != from.0 + 1 {
+            self.line(&format!("jmp {}", self.block_label(to)));
+        }
+    }
+
+    /// Where a conditional jump along the edge from `from` to `to` goes: to the block itself,
+    /// or to code out of the way that first gives the phis of the block their values.
+    fn edge_target(&mut self, function: &ir::Function, from: BlockId, to: BlockId) -> String {
+        if self.copies(function, from, to).is_empty() {
+            return self.block_label(to);
+        }
+        let label = self.new_label();
+        let assembly = mem::take(&mut self.assembly);
+        self.label(&label);
+        self.phi_copies(function, from, to);
+        self.line(&format!("jmp {}", self.block_label(to)));
+        let copies = mem::replace(&mut self.assembly, assembly);
+        self.out_of_line.push_str(&copies);
+        label
+    }
+
+    /// The phis of `to` that are used, with the value each takes when coming from `from`.
+    fn copies(
+        &self,
+        function: &ir::Function,
+        from: BlockId,
+        to: BlockId,
+    ) -> Vec<(ValueId, ValueId)> {
+        function
+            .block(to)
+            .phis
+            .iter()
+            .filter(|phi| self.locations[phi.result.0] != Location::Nowhere)
+            .map(|phi| {
+                let (_, source) = phi
+                    .incoming
+                    .iter()
+                    .find(|(block, _)| *block == from)
+                    .unwrap();
+                (phi.result, *source)
+            })
+            .filter(|(phi, source)| phi != source)
+            .collect()
+    }
+
+    fn phi_copies(&mut self, function: &ir::Function, from: BlockId, to: BlockId) {
+        let copies = self.copies(function, from, to);
+        let destinations: HashSet<ValueId> = copies.iter().map(|(phi, _)| *phi).collect();
+        let slot = |generator: &Self, phi: ValueId| match generator.locations[phi.0] {
+            Location::Slot(offset) => offset,
+            location => unreachable!("phis that are used are in slots, not {:?}", location),
+        };
+        // The phis take their values all at once. When a phi reads another one, the values
+        // go through the stack so that none is overwritten before it is read.
+        if !copies
+            .iter()
+            .any(|(_, source)| destinations.contains(source))
+        {
+            for (phi, source) in copies {
+                let offset = slot(self, phi);
+                let tag = format!("{}(%rbp)", offset - 8);
+                let tagged = self.representations[phi.0] == Representation::Tagged;
+                self.copy(
+                    source,
+                    &format!("{}(%rbp)", offset),
+                    tagged.then_some(tag.as_str()),
+                );
+            }
+            return;
+        }
+        for (phi, source) in &copies {
+            if self.representations[phi.0] == Representation::Tagged && self.tag(*source).is_none()
+            {
+                self.line(&format!("pushq {}", self.tag_address(*source)));
+            }
+            let payload = self.source(*source, "%rax");
+            self.line(&format!("pushq {}", payload));
+        }
+        for (phi, source) in copies.iter().rev() {
+            let offset = slot(self, *phi);
+            self.line(&format!("popq {}(%rbp)", offset));
+            if self.representations[phi.0] == Representation::Tagged {
+                match self.tag(*source) {
+                    Some(tag) => self.line(&format!("movq ${}, {}(%rbp)", tag, offset - 8)),
+                    None => self.line(&format!("popq {}(%rbp)", offset - 8)),
+                }
+            }
+        }
+    }
+
+    /// Jumps to the error path that `error` emits unless all of `values` have the tag `tag`.
+    /// The path is only emitted if some value is not known to have it.
+    fn expect_tags(
+        &mut self,
+        values: &[ValueId],
+        tag: i64,
+        error: impl FnOnce(&mut Self) -> String,
+    ) {
+        if values.iter().all(|value| self.tag(*value) == Some(tag)) {
+            return;
+        }
+        let label = error(self);
+        for value in values {
+            match self.tag(*value) {
+                Some(known) if known == tag => {}
+                Some(_) => {
+                    self.line(&format!("jmp {}", label));
+                    return;
+                }
+                None => {
+                    self.line(&format!("cmpq ${}, {}", tag, self.tag_address(*value)));
+                    self.line(&format!("jne {}", label));
+                }
+            }
+        }
+    }
+
+    fn check_overflow(&mut self, span: &TextSpan) {
+        if self.arithmetic == ArithmeticMode::Checked {
+            let overflow = self.overflow(span);
+            self.line(&format!("jo {}", overflow));
+        }
+    }
+
+    fn overflow(&mut self, span: &TextSpan) -> String {
+        let overflow = RuntimeErrorKind::IntegerOverflow {
+            operator: span.literal.clone(),
+        };
+        self.error_path(span, &overflow.to_string(), &[])
+    }
+
+    fn missing_value(&mut self, span: &TextSpan) -> String {
+        self.error_path(span, &RuntimeErrorKind::MissingValue.to_string(), &[])
+    }
+
+    /// Operators are blamed with the span of their symbol.
+    fn invalid_operands(&mut self, span: &TextSpan, operands: &[ValueId]) -> String {
+        let message = format!("Operator '{}' cannot be applied to ", span.literal);
+        self.error_path(span, &message, operands)
+    }
+
+    fn non_boolean_condition(&mut self, span: &TextSpan, condition: ValueId) -> String {
+        self.error_path(span, "Expected a boolean condition, found ", &[condition])
+    }
+
+    /// Emits code that reports a runtime error at `span` and returns its label. The values of
+    /// `operands` are described after `message`.
+    fn error_path(&mut self, span: &TextSpan, message: &str, operands: &[ValueId]) -> String {
+        let diagnostic = self.string(self.printer.stringify_plain_span(span, ""));
+        let message = self.string(message.to_string());
+        let label = self.new_label();
+        writeln!(self.out_of_line, "{}:", label).unwrap();
+        if !operands.is_empty() {
+            self.out_of_line_line("leaq .Lrt_operands(%rip), %rdi");
+        }
+        for (index, operand) in operands.iter().enumerate() {
+            let tag = format!("{}(%rdi)", 16 * index);
+            let payload = format!("{}(%rdi)", 16 * index + 8);
+            for line in self.copy_instructions(*operand, &payload, Some(&tag)) {
+                self.out_of_line_line(&line);
+            }
+        }
+        self.out_of_line_line(&format!("leaq {}(%rip), %rdi", diagnostic));
+        self.out_of_line_line(&format!("leaq {}(%rip), %rsi", message));
+        self.out_of_line_line(&format!("movl ${}, %edx", operands.len()));
+        self.out_of_line_line("call .Lrt_fail");
+        label
+    }
+
+    fn finish(self) -> String {
+        let mut assembly = self.assembly;
+        assembly.push_str(&self.out_of_line);
+        let runtime = RUNTIME
+            .replace(
+                "MAX_STACK_TRACE_FRAMES",
+                &MAX_STACK_TRACE_FRAMES.to_string(),
+            )
+            .replace("MAX_CALL_DEPTH", &MAX_CALL_DEPTH.to_string());
+        assembly.push_str(&runtime);
+
+        assembly.push_str("\n    .section .rodata\n");
+        for (index, string) in self.strings.iter().enumerate() {
+            writeln!(
+                assembly,
+                ".Lstring{}:\n    .asciz \"{}\"",
+                index,
+                escape(string)
+            )
+            .unwrap();
+        }
+        assembly.push_str("\n    .bss\n    .p2align 4\n.Lglobals:\n");
+        writeln!(
+            assembly,
+            "    .zero {}",
+            16 * self.program.globals.len().max(1)
+        )
+        .unwrap();
+        assembly.push_str("\n    .section .note.GNU-stack,\"\",@progbits\n");
+        assembly
+    }
+
+    fn tag(&self, value: ValueId) -> Option<i64> {
+        self.representations[value.0].tag()
+    }
+
+    fn tag_address(&self, value: ValueId) -> String {
+        match self.locations[value.0] {
+            Location::Slot(offset) => format!("{}(%rbp)", offset - 8),
+            location => unreachable!("tagged values are in slots, not {:?}", location),
+        }
+    }
+
+    /// An operand for the payload of `value`, which is loaded into `scratch` if it is an
+    /// immediate that does not fit in 32 bits.
+    fn source(&mut self, value: ValueId, scratch: &str) -> String {
+        match self.locations[value.0] {
+            Location::Immediate(immediate) if i32::try_from(immediate).is_ok() => {
+                format!("${}", immediate)
+            }
+            Location::Slot(offset) => format!("{}(%rbp)", offset),
+            _ => {
+                self.load(value, scratch);
+                scratch.to_string()
+            }
+        }
+    }
+
+    fn load(&mut self, value: ValueId, register: &str) {
+        let instruction = self.load_instruction(value, register);
+        self.line(&instruction);
+    }
+
+    fn load_instruction(&self, value: ValueId, register: &str) -> String {
+        match self.locations[value.0] {
+            Location::Immediate(immediate) if i32::try_from(immediate).is_ok() => {
+                format!("movq ${}, {}", immediate, register)
+            }
+            Location::Immediate(immediate) => format!("movabsq ${}, {}", immediate, register),
+            Location::Slot(offset) => format!("movq {}(%rbp), {}", offset, register),
+            // Values that hold nothing have no payload.
+            Location::Nowhere => format!("movq $0, {}", register),
+        }
+    }
+
+    /// Stores the payload in `register` as the value of `result`, which has the tag `tag`.
+    fn store(&mut self, result: Option<ValueId>, register: &str, tag: i64) {
+        let Some(result) = result else {
+            return;
+        };
+        if let Location::Slot(offset) = self.locations[result.0] {
+            self.line(&format!("movq {}, {}(%rbp)", register, offset));
+            if self.representations[result.0] == Representation::Tagged {
+                self.line(&format!("movq ${}, {}(%rbp)", tag, offset - 8));
+            }
+        }
+    }
+
+    /// Copies `value` as the value of `result`, which has the same type.
+    fn copy_to_result(&mut self, result: Option<ValueId>, value: ValueId) {
+        if let Some(Location::Slot(offset)) = result.map(|result| self.locations[result.0]) {
+            self.copy(value, &format!("{}(%rbp)", offset), None);
+        }
+    }
+
+    /// Copies the payload of `value` to `payload` and, if the destination is tagged, its tag
+    /// to `tag`, going through %rax.
+    fn copy(&mut self, value: ValueId, payload: &str, tag: Option<&str>) {
+        for line in self.copy_instructions(value, payload, tag) {
+            self.line(&line);
+        }
+    }
+
+    fn copy_instructions(&self, value: ValueId, payload: &str, tag: Option<&str>) -> Vec<String> {
+        let mut instructions = Vec::new();
+        if let Some(tag) = tag {
+            match self.tag(value) {
+                Some(known) => instructions.push(format!("movq ${}, {}", known, tag)),
+                None => {
+                    instructions.push(format!("movq {}, %rax", self.tag_address(value)));
+                    instructions.push(format!("movq %rax, {}", tag));
+                }
+            }
+        }
+        match self.locations[value.0] {
+            Location::Nowhere => {}
+            Location::Immediate(immediate) if i32::try_from(immediate).is_ok() => {
+                instructions.push(format!("movq ${}, {}", immediate, payload))
+            }
+            _ => {
+                instructions.push(self.load_instruction(value, "%rax"));
+                instructions.push(format!("movq %rax, {}", payload));
+            }
+        }
+        instructions
+    }
+
+    fn type_tag(ty: Type) -> i64 {
+        match ty {
+            Type::Integer => TAG_INTEGER,
+            Type::Boolean => TAG_BOOLEAN,
+            _ => unreachable!("floats and strings are rejected before compiling"),
+        }
+    }
+
+    fn condition_code(operator: ASTBinaryOperatorKind) -> Option<&'static str> {
+        match operator {
+            ASTBinaryOperatorKind::Equals => Some("e"),
+            ASTBinaryOperatorKind::NotEquals => Some("ne"),
+            ASTBinaryOperatorKind::LessThan => Some("l"),
+            ASTBinaryOperatorKind::LessThanOrEqual => Some("le"),
+            ASTBinaryOperatorKind::GreaterThan => Some("g"),
+            ASTBinaryOperatorKind::GreaterThanOrEqual => Some("ge"),
+            _ => None,
+        }
+    }
+
+    fn negate(condition: &str) -> &'static str {
+        match condition {
+            "e" => "ne",
+            "ne" => "e",
+            "l" => "ge",
+            "ge" => "l",
+            "le" => "g",
+            "g" => "le",
+            _ => unreachable!("unknown condition code '{}'", condition),
+        }
+    }
+
+    fn line(&mut self, instruction: &str) {
+        writeln!(self.assembly, "    {}", instruction).unwrap();
+    }
+
+    fn out_of_line_line(&mut self, instruction: &str) {
+        writeln!(self.out_of_line, "    {}", instruction).unwrap();
+    }
+
+    fn label(&mut self, label: &str) {
+        writeln!(self.assembly, "{}:", label).unwrap();
+    }
+
+    fn new_label(&mut self) -> String {
+        self.label_count += 1;
+        format!(".L{}", self.label_count)
+    }
+
+    /// Interns a string constant and returns its label.
+    fn string(&mut self, string: String) -> String {
+        let index = match self.string_labels.get(&string) {
+            Some(index) => *index,
+            None => {
+                self.strings.push(string.clone());
+                self.string_labels.insert(string, self.strings.len() - 1);
+                self.strings.len() - 1
+            }
+        };
+        format!(".Lstring{}", index)
+    }
+
+    fn function_label(function: FunctionId) -> String {
+        format!(".Lfunction{}", function.0)
+    }
+
+    fn block_label(&self, block: BlockId) -> String {
+        format!(".Lfunction{}_{}", self.function.0, block.0)
+    }
+}
+
+fn escape(string: &str) -> String {
+    let mut escaped = String::new();
+    for byte in string.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => write!(escaped, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    escaped
+}
+
+/// Assembles and links `assembly` into an executable at `output` with the system C compiler.
+pub fn link(assembly: &str, output: &Path) -> io::Result<()> {
+    let mut compiler = Command::new("cc")
+        .args(["-x", "assembler", "-", "-o"])
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    compiler
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(assembly.as_bytes())?;
+    let status = compiler.wait()?;
+    if !status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("'cc' exited with {}", status),
+        ));
+    }
+    Ok(())
+}
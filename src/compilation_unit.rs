@@ -0,0 +1,256 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::ast::{
+    evaluator::{ASTEvaluator, ArithmeticMode, RuntimeError, Value},
+    lexer::{Lexer, Token},
+    linter::Linter,
+    optimizer::ASTOptimizer,
+    parser::Parser,
+    symbol_checker::SymbolChecker,
+    type_checker::TypeChecker,
+    Ast,
+};
+use crate::codegen::{
+    bytecode::BytecodeCompiler, c::CGenerator, find_unsupported_type, wasm::WasmGenerator,
+    x86_64::AssemblyGenerator,
+};
+use crate::diagnostics::{printer::DiagnosticsPrinter, DiagnosticBag, DiagnosticsBagCell};
+use crate::ir::{self, lowering::IrBuilder, verifier};
+use crate::text::SourceText;
+use crate::vm::VM;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompilationFailure {
+    Syntax,
+    Semantic,
+    Runtime,
+    /// The system toolchain failed to assemble or link the generated code.
+    Build,
+}
+
+pub struct CompilationUnit {
+    pub text: SourceText,
+    pub ast: Ast,
+    pub diagnostics_bag: DiagnosticsBagCell,
+}
+
+impl CompilationUnit {
+    /// Lexes `text` without reporting lexical errors.
+    pub fn lex(text: &SourceText) -> Vec<Token> {
+        Self::lex_from(text, 0, Rc::new(RefCell::new(DiagnosticBag::new())))
+    }
+
+    /// Lexes `text` from byte `start` on. The spans stay relative to the whole text.
+    fn lex_from(
+        text: &SourceText,
+        start: usize,
+        diagnostics_bag: DiagnosticsBagCell,
+    ) -> Vec<Token> {
+        let mut lexer = Lexer::starting_at(text.as_str(), start, diagnostics_bag);
+        let mut tokens: Vec<Token> = Vec::new();
+        while let Some(token) = lexer.next_token() {
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    pub fn parse(text: SourceText) -> Result<Self, CompilationFailure> {
+        Self::parse_with(text, Rc::new(RefCell::new(DiagnosticBag::new())))
+    }
+
+    pub fn parse_with(
+        text: SourceText,
+        diagnostics_bag: DiagnosticsBagCell,
+    ) -> Result<Self, CompilationFailure> {
+        Self::parse_from(text, 0, diagnostics_bag)
+    }
+
+    /// Parses only what follows byte `start` of `text`. The REPL uses this to keep the whole
+    /// session in one text, so that spans from earlier inputs can still be printed.
+    pub fn parse_from(
+        text: SourceText,
+        start: usize,
+        diagnostics_bag: DiagnosticsBagCell,
+    ) -> Result<Self, CompilationFailure> {
+        let tokens = Self::lex_from(&text, start, Rc::clone(&diagnostics_bag));
+        let mut ast = Ast::new();
+        let mut parser = Parser::new(tokens, Rc::clone(&diagnostics_bag));
+        while let Some(statement) = parser.next_statement() {
+            ast.add_statement(statement);
+        }
+
+        let unit = Self {
+            text,
+            ast,
+            diagnostics_bag,
+        };
+        unit.check_diagnostics()
+            .map_err(|_| CompilationFailure::Syntax)?;
+        Ok(unit)
+    }
+
+    /// Checks and lints the tree and returns the type checker, which holds the type of every
+    /// expression.
+    pub fn check(&self) -> Result<TypeChecker, CompilationFailure> {
+        let mut symbol_checker = SymbolChecker::new(Rc::clone(&self.diagnostics_bag));
+        let mut type_checker = TypeChecker::new(Rc::clone(&self.diagnostics_bag));
+        self.check_with(&mut symbol_checker, &mut type_checker)?;
+        Linter::new(Rc::clone(&self.diagnostics_bag)).lint(&self.ast);
+        self.check_diagnostics()
+            .map_err(|_| CompilationFailure::Semantic)?;
+        Ok(type_checker)
+    }
+
+    /// Resolves names, then types. Types are only checked once every name is known to resolve.
+    pub fn check_with(
+        &self,
+        symbol_checker: &mut SymbolChecker,
+        type_checker: &mut TypeChecker,
+    ) -> Result<(), CompilationFailure> {
+        symbol_checker.check(&self.ast);
+        self.check_diagnostics()
+            .map_err(|_| CompilationFailure::Semantic)?;
+        type_checker.check(&self.ast);
+        self.check_diagnostics()
+            .map_err(|_| CompilationFailure::Semantic)
+    }
+
+    /// Folds the constant parts of the checked tree, see `ASTOptimizer`.
+    pub fn optimize(&mut self, types: &TypeChecker, arithmetic: ArithmeticMode) {
+        ASTOptimizer::optimize(&mut self.ast, types, arithmetic);
+    }
+
+    pub fn evaluate(
+        &self,
+        types: &TypeChecker,
+        arithmetic: ArithmeticMode,
+    ) -> Result<Option<Value>, CompilationFailure> {
+        self.evaluate_with(&mut ASTEvaluator::new(arithmetic), types)
+    }
+
+    pub fn evaluate_with(
+        &self,
+        eval: &mut ASTEvaluator,
+        types: &TypeChecker,
+    ) -> Result<Option<Value>, CompilationFailure> {
+        eval.evaluate(&self.ast, types)
+            .map_err(|error| self.report_runtime_error(&error))
+    }
+
+    /// Lowers the checked tree into the IR that the VM and the compiled backends consume.
+    pub fn lower(&self, types: &TypeChecker) -> Result<ir::Program, CompilationFailure> {
+        let program = IrBuilder::lower(&self.ast, types);
+        if let Err(errors) = verifier::verify(&program) {
+            panic!(
+                "the lowered IR is malformed:\n{}\n\n{}",
+                errors.join("\n"),
+                program
+            );
+        }
+        Ok(program)
+    }
+
+    /// Rejects floats and strings, which only `run` supports. This runs before `optimize`, so
+    /// that what the compiled backends accept does not depend on what gets folded.
+    pub fn check_backend_support(&self) -> Result<(), CompilationFailure> {
+        if let Some((span, ty)) = find_unsupported_type(&self.ast) {
+            self.diagnostics_bag
+                .borrow_mut()
+                .report_unsupported_type(&ty, span);
+        }
+        self.check_diagnostics()
+            .map_err(|_| CompilationFailure::Semantic)
+    }
+
+    /// Prints the IR of the checked tree.
+    pub fn emit_ir(&self, types: &TypeChecker) -> Result<String, CompilationFailure> {
+        Ok(self.lower(types)?.to_string())
+    }
+
+    /// Compiles the tree to bytecode through the IR and runs it on the VM.
+    pub fn run_bytecode(
+        &self,
+        types: &TypeChecker,
+        arithmetic: ArithmeticMode,
+    ) -> Result<Option<Value>, CompilationFailure> {
+        let program = BytecodeCompiler::compile(&self.lower(types)?);
+        VM::new(&program, arithmetic)
+            .run()
+            .map_err(|error| self.report_runtime_error(&error))
+    }
+
+    /// Compiles the tree to x86-64 assembly for the GNU assembler through the IR, using the
+    /// types found by `types`. The tree must have passed `check_backend_support`.
+    pub fn emit_assembly(
+        &self,
+        types: &TypeChecker,
+        arithmetic: ArithmeticMode,
+    ) -> Result<String, CompilationFailure> {
+        Ok(AssemblyGenerator::generate(
+            &self.lower(types)?,
+            &self.text,
+            arithmetic,
+        ))
+    }
+
+    /// Translates the tree into a C11 program through the IR, using the types found by `types`.
+    /// The tree must have passed `check_backend_support`.
+    pub fn emit_c(
+        &self,
+        types: &TypeChecker,
+        arithmetic: ArithmeticMode,
+    ) -> Result<String, CompilationFailure> {
+        let c = CGenerator::generate(
+            &self.lower(types)?,
+            &self.text,
+            arithmetic,
+            Rc::clone(&self.diagnostics_bag),
+        );
+        self.check_diagnostics()
+            .map_err(|_| CompilationFailure::Semantic)?;
+        Ok(c)
+    }
+
+    /// Translates the tree into a WebAssembly module in text format, using the types found by
+    /// `types`. The tree must have passed `check_backend_support`.
+    pub fn emit_wat(
+        &self,
+        types: &TypeChecker,
+        arithmetic: ArithmeticMode,
+    ) -> Result<String, CompilationFailure> {
+        let wat = WasmGenerator::generate(
+            &self.ast,
+            &self.text,
+            types,
+            arithmetic,
+            Rc::clone(&self.diagnostics_bag),
+        );
+        self.check_diagnostics()
+            .map_err(|_| CompilationFailure::Semantic)?;
+        Ok(wat)
+    }
+
+    fn report_runtime_error(&self, error: &RuntimeError) -> CompilationFailure {
+        self.diagnostics_bag
+            .borrow_mut()
+            .report_runtime_error(error);
+        let _ = self.check_diagnostics();
+        CompilationFailure::Runtime
+    }
+
+    /// Prints the reported diagnostics and fails if any of them is an error. Warnings alone
+    /// are cleared once printed, so that later checks do not print them again.
+    fn check_diagnostics(&self) -> Result<(), ()> {
+        let mut diagnostics_binding = self.diagnostics_bag.borrow_mut();
+        if diagnostics_binding.diagnostics.len() > 0 {
+            let diagnostics_printer =
+                DiagnosticsPrinter::new(&self.text, &diagnostics_binding.diagnostics);
+            diagnostics_printer.print();
+            if diagnostics_binding.has_errors() {
+                return Err(());
+            }
+            diagnostics_binding.diagnostics.clear();
+        }
+        Ok(())
+    }
+}
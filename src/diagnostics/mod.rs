@@ -1,17 +1,32 @@
 use std::{cell::RefCell, rc::Rc};
 
-use crate::ast::lexer::{TextSpan, Token, TokenKind};
+use crate::ast::{
+    evaluator::RuntimeError,
+    lexer::{TextSpan, Token, TokenKind},
+    types::Type,
+};
+
+/// Runtime errors only show the innermost calls of their stack trace.
+pub const MAX_STACK_TRACE_FRAMES: usize = 8;
 
 pub mod printer;
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DiagnosticKind {
     Error,
     Warning,
 }
 
+/// A secondary location that explains a diagnostic, such as the declaration it conflicts with.
+pub struct DiagnosticLabel {
+    pub message: String,
+    pub span: TextSpan,
+}
+
 pub struct Diagnostic {
     pub message: String,
     pub span: TextSpan,
     pub kind: DiagnosticKind,
+    pub labels: Vec<DiagnosticLabel>,
 }
 
 impl Diagnostic {
@@ -20,20 +35,43 @@ impl Diagnostic {
             message,
             span,
             kind,
+            labels: vec![],
         }
     }
+
+    pub fn with_label(mut self, message: String, span: TextSpan) -> Self {
+        self.labels.push(DiagnosticLabel { message, span });
+        self
+    }
 }
 
 pub type DiagnosticsBagCell = Rc<RefCell<DiagnosticBag>>;
 
 pub struct DiagnosticBag {
     pub diagnostics: Vec<Diagnostic>,
+    /// Report warnings as errors, as asked by `-D warnings`.
+    pub deny_warnings: bool,
 }
 
 impl DiagnosticBag {
     pub fn new() -> Self {
         DiagnosticBag {
             diagnostics: vec![],
+            deny_warnings: false,
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.kind == DiagnosticKind::Error)
+    }
+
+    fn warning_kind(&self) -> DiagnosticKind {
+        if self.deny_warnings {
+            DiagnosticKind::Error
+        } else {
+            DiagnosticKind::Warning
         }
     }
 
@@ -43,7 +81,7 @@ impl DiagnosticBag {
     }
 
     pub fn report_warning(&mut self, message: String, span: TextSpan) {
-        let warning = Diagnostic::new(message, span, DiagnosticKind::Warning);
+        let warning = Diagnostic::new(message, span, self.warning_kind());
         self.diagnostics.push(warning);
     }
 
@@ -54,6 +92,76 @@ impl DiagnosticBag {
         );
     }
 
+    pub fn report_unterminated_block_comment(&mut self, opening: TextSpan) {
+        self.report_error("Unterminated block comment".to_string(), opening);
+    }
+
+    pub fn report_integer_literal_too_large(&mut self, literal: TextSpan) {
+        self.report_error(
+            format!(
+                "Integer literal '{}' does not fit in <int>",
+                literal.literal
+            ),
+            literal,
+        );
+    }
+
+    pub fn report_float_literal_too_large(&mut self, literal: TextSpan) {
+        self.report_error(
+            format!(
+                "Float literal '{}' does not fit in <float>",
+                literal.literal
+            ),
+            literal,
+        );
+    }
+
+    pub fn report_unterminated_string(&mut self, opening: TextSpan) {
+        self.report_error("Unterminated string".to_string(), opening);
+    }
+
+    pub fn report_invalid_escape(&mut self, escape: TextSpan) {
+        self.report_error(
+            format!("Invalid escape sequence '{}'", escape.literal),
+            escape,
+        );
+    }
+
+    pub fn report_unsupported_type(&mut self, ty: &Type, span: TextSpan) {
+        self.report_error(
+            format!("Values of type <{}> are only supported by `run`", ty),
+            span,
+        );
+    }
+
+    pub fn report_invalid_cast(&mut self, from: &Type, to: &Type, span: TextSpan) {
+        self.report_error(format!("Cannot cast <{}> to <{}>", from, to), span);
+    }
+
+    pub fn report_detached_doc_comment(&mut self, span: TextSpan) {
+        self.report_warning(
+            "This doc comment does not document a `let` or `fn`".to_string(),
+            span,
+        );
+    }
+
+    pub fn report_missing_terminator(&mut self, token: &Token) {
+        self.report_error(
+            format!(
+                "Expected a newline or ';' after the statement, found <{}>",
+                token.kind
+            ),
+            token.span.clone(),
+        );
+    }
+
+    pub fn report_block_on_next_line(&mut self, open_brace: &Token) {
+        self.report_error(
+            "A block must start on the same line as the statement it belongs to".to_string(),
+            open_brace.span.clone(),
+        );
+    }
+
     pub fn report_expected_expression(&mut self, token: &Token) {
         self.report_error(
             format!("Expected expression, found <{}>", token.kind),
@@ -67,4 +175,275 @@ impl DiagnosticBag {
             token.span.clone(),
         );
     }
+
+    pub fn report_missing_else_branch(&mut self, if_keyword: &Token) {
+        self.report_error(
+            "An if expression must have an else branch".to_string(),
+            if_keyword.span.clone(),
+        );
+    }
+
+    pub fn report_non_boolean_condition(&mut self, found: &Type, span: TextSpan) {
+        self.report_error(
+            format!("Expected a condition of type <bool>, found <{}>", found),
+            span,
+        );
+    }
+
+    pub fn report_undeclared_type(&mut self, type_name: &Token) {
+        self.report_error(
+            format!("Unknown type '{}'", type_name.span.literal),
+            type_name.span.clone(),
+        );
+    }
+
+    pub fn report_type_mismatch(&mut self, expected: &Type, found: &Type, span: TextSpan) {
+        self.report_error(
+            format!("Expected a value of type <{}>, found <{}>", expected, found),
+            span,
+        );
+    }
+
+    pub fn report_return_type_mismatch(&mut self, expected: &Type, found: &Type, span: TextSpan) {
+        self.report_error(
+            format!(
+                "Expected a return value of type <{}>, found <{}>",
+                expected, found
+            ),
+            span,
+        );
+    }
+
+    pub fn report_invalid_binary_operator(&mut self, operator: &Token, left: &Type, right: &Type) {
+        self.report_error(
+            format!(
+                "Operator '{}' cannot be applied to <{}> and <{}>",
+                operator.span.literal, left, right
+            ),
+            operator.span.clone(),
+        );
+    }
+
+    pub fn report_mixed_number_types(&mut self, operator: &Token, left: &Type, right: &Type) {
+        self.report_error(
+            format!(
+                "Operator '{}' cannot be applied to <{}> and <{}>, convert one of them with `as`",
+                operator.span.literal, left, right
+            ),
+            operator.span.clone(),
+        );
+    }
+
+    pub fn report_invalid_unary_operator(&mut self, operator: &Token, operand: &Type) {
+        self.report_error(
+            format!(
+                "Operator '{}' cannot be applied to <{}>",
+                operator.span.literal, operand
+            ),
+            operator.span.clone(),
+        );
+    }
+
+    pub fn report_assignment_to_immutable(
+        &mut self,
+        identifier: &Token,
+        assignment: TextSpan,
+        declaration: TextSpan,
+    ) {
+        let error = Diagnostic::new(
+            format!(
+                "Cannot assign to immutable variable '{}'",
+                identifier.span.literal
+            ),
+            assignment,
+            DiagnosticKind::Error,
+        )
+        .with_label(
+            format!(
+                "'{}' is declared here, use `let mut {}` to make it mutable",
+                identifier.span.literal, identifier.span.literal
+            ),
+            declaration,
+        );
+        self.diagnostics.push(error);
+    }
+
+    pub fn report_nested_function(&mut self, identifier: &Token) {
+        self.report_error(
+            format!(
+                "Function '{}' must be declared at the top level",
+                identifier.span.literal
+            ),
+            identifier.span.clone(),
+        );
+    }
+
+    pub fn report_duplicate_function(&mut self, identifier: &Token, previous: TextSpan) {
+        let error = Diagnostic::new(
+            format!("Function '{}' is already declared", identifier.span.literal),
+            identifier.span.clone(),
+            DiagnosticKind::Error,
+        )
+        .with_label("First declared here".to_string(), previous);
+        self.diagnostics.push(error);
+    }
+
+    pub fn report_duplicate_parameter(&mut self, identifier: &Token, previous: TextSpan) {
+        let error = Diagnostic::new(
+            format!(
+                "Parameter '{}' is already declared",
+                identifier.span.literal
+            ),
+            identifier.span.clone(),
+            DiagnosticKind::Error,
+        )
+        .with_label("First declared here".to_string(), previous);
+        self.diagnostics.push(error);
+    }
+
+    /// `function` is the callee itself or a function it may call, directly or not.
+    pub fn report_call_before_declaration(
+        &mut self,
+        identifier: &Token,
+        function: &str,
+        declaration: TextSpan,
+    ) {
+        let message = if identifier.span.literal == function {
+            format!("Function '{}' is called before it is declared", function)
+        } else {
+            format!(
+                "Function '{}' is called before '{}', which it may call, is declared",
+                identifier.span.literal, function
+            )
+        };
+        let error = Diagnostic::new(message, identifier.span.clone(), DiagnosticKind::Error)
+            .with_label(format!("'{}' is declared here", function), declaration);
+        self.diagnostics.push(error);
+    }
+
+    pub fn report_return_outside_function(&mut self, return_keyword: &Token) {
+        self.report_error(
+            "'return' outside of a function".to_string(),
+            return_keyword.span.clone(),
+        );
+    }
+
+    pub fn report_undeclared_function(&mut self, identifier: &Token) {
+        self.report_error(
+            format!("Undeclared function '{}'", identifier.span.literal),
+            identifier.span.clone(),
+        );
+    }
+
+    pub fn report_invalid_argument_count(
+        &mut self,
+        identifier: &Token,
+        expected: usize,
+        found: usize,
+        span: TextSpan,
+    ) {
+        self.report_error(
+            format!(
+                "Function '{}' expects {} argument(s), found {}",
+                identifier.span.literal, expected, found
+            ),
+            span,
+        );
+    }
+
+    pub fn report_missing_parameter_type(&mut self, parameter: &str, span: TextSpan, target: &str) {
+        self.report_error(
+            format!(
+                "Parameter '{}' needs a type annotation to be compiled to {}",
+                parameter, target
+            ),
+            span,
+        );
+    }
+
+    pub fn report_missing_return_type(&mut self, function: &str, span: TextSpan, target: &str) {
+        self.report_error(
+            format!(
+                "Function '{}' needs a return type annotation for its value to be used in {}",
+                function, target
+            ),
+            span,
+        );
+    }
+
+    pub fn report_untyped_result(&mut self, span: TextSpan, target: &str) {
+        self.report_error(
+            format!(
+                "The value of the program needs the same type on every path to be returned in {}",
+                target
+            ),
+            span,
+        );
+    }
+
+    pub fn report_runtime_error(&mut self, error: &RuntimeError) {
+        let mut diagnostic = Diagnostic::new(
+            error.kind.to_string(),
+            error.span.clone(),
+            DiagnosticKind::Error,
+        );
+        let omitted = error
+            .stack_trace
+            .len()
+            .saturating_sub(MAX_STACK_TRACE_FRAMES);
+        for (index, frame) in error.stack_trace.iter().rev().enumerate() {
+            if index == MAX_STACK_TRACE_FRAMES {
+                break;
+            }
+            let mut message = format!("in call to '{}'", frame.function);
+            if index + 1 == MAX_STACK_TRACE_FRAMES && omitted > 0 {
+                message.push_str(&format!(" ({} earlier calls not shown)", omitted));
+            }
+            diagnostic = diagnostic.with_label(message, frame.call_site.clone());
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn report_unused_variable(&mut self, name: &str, declaration: TextSpan) {
+        self.report_warning(format!("Variable '{}' is never read", name), declaration);
+    }
+
+    pub fn report_overwritten_value(
+        &mut self,
+        name: &str,
+        written: TextSpan,
+        overwritten: TextSpan,
+    ) {
+        let warning = Diagnostic::new(
+            format!("The value of '{}' is overwritten before it is read", name),
+            written,
+            self.warning_kind(),
+        )
+        .with_label(format!("'{}' is overwritten here", name), overwritten);
+        self.diagnostics.push(warning);
+    }
+
+    pub fn report_unused_value(&mut self, span: TextSpan) {
+        self.report_warning(
+            "The value of this expression is discarded".to_string(),
+            span,
+        );
+    }
+
+    pub fn report_outside_of_loop(&mut self, keyword: &Token) {
+        self.report_error(
+            format!("'{}' outside of a loop", keyword.span.literal),
+            keyword.span.clone(),
+        );
+    }
+
+    pub fn report_variable_out_of_scope(&mut self, token: &Token) {
+        self.report_error(
+            format!(
+                "Variable '{}' is used outside of the scope that declares it",
+                token.span.literal
+            ),
+            token.span.clone(),
+        );
+    }
 }
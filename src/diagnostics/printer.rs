@@ -1,8 +1,12 @@
 use std::cmp;
 
-use termion::color::{Fg, Red, Reset};
+use termion::color::{self, Fg, Red, Reset, Yellow};
 
-use crate::{diagnostics::Diagnostic, text::SourceText};
+use crate::{
+    ast::lexer::TextSpan,
+    diagnostics::{Diagnostic, DiagnosticKind},
+    text::SourceText,
+};
 pub struct DiagnosticsPrinter<'a> {
     text: &'a SourceText,
     diagnostics: &'a [Diagnostic],
@@ -16,24 +20,68 @@ impl<'a> DiagnosticsPrinter<'a> {
     }
 
     pub fn stringify_diagnostic(&self, diagnostic: &Diagnostic) -> String {
-        let line_index = self.text.line_index(diagnostic.span.start);
+        let mut result = match diagnostic.kind {
+            DiagnosticKind::Error => {
+                self.stringify_span(&diagnostic.span, &diagnostic.message, &Red)
+            }
+            DiagnosticKind::Warning => self.stringify_span(
+                &diagnostic.span,
+                &format!("warning: {}", diagnostic.message),
+                &Yellow,
+            ),
+        };
+        for label in &diagnostic.labels {
+            result.push('\n');
+            result.push_str(&self.stringify_span(&label.span, &label.message, &color::Blue));
+        }
+        result
+    }
+
+    pub fn stringify_span(
+        &self,
+        text_span: &TextSpan,
+        message: &str,
+        color: &dyn color::Color,
+    ) -> String {
+        self.render_span(text_span, message, Some(color))
+    }
+
+    /// Like `stringify_span`, without the escape codes that color the span. Used for diagnostics
+    /// embedded in generated programs, which may not print to a terminal.
+    pub fn stringify_plain_span(&self, text_span: &TextSpan, message: &str) -> String {
+        self.render_span(text_span, message, None)
+    }
+
+    fn render_span(
+        &self,
+        text_span: &TextSpan,
+        message: &str,
+        color: Option<&dyn color::Color>,
+    ) -> String {
+        let line_index = self.text.line_index(text_span.start);
         let line = self.text.get_line(line_index);
         let line_start = self.text.line_start(line_index);
 
-        let column = diagnostic.span.start - line_start;
+        let offset = floor_char_boundary(line, text_span.start - line_start);
+        let column = line[..offset].chars().count();
 
-        let (prefix, span, suffix) = self.get_text_spans(column, diagnostic, line);
+        let (prefix, span, suffix) = self.get_text_spans(offset, text_span, line);
 
-        let indent = cmp::min(PREFIX_LENGTH, column);
-        let (arrow_pointers, arrow_line) = self.format_arrow(diagnostic, indent);
-        let error_message = self.format_error_message(diagnostic, indent);
+        let indent = prefix.chars().count();
+        let (arrow_pointers, arrow_line) = self.format_arrow(text_span, indent);
+        let error_message = self.format_error_message(message, indent);
+        let span = match color {
+            Some(color) => format!("{}{}{}", Fg(color), span, Fg(Reset)),
+            None => span.to_string(),
+        };
 
         format!(
-            "{}{}{}{}{}\n{}\n{}\n{}",
+            "--> {}:{}:{}\n{}{}{}\n{}\n{}\n{}",
+            self.text.name(),
+            line_index + 1,
+            column + 1,
             prefix,
-            Fg(Red),
             span,
-            Fg(Reset),
             suffix,
             arrow_pointers,
             arrow_line,
@@ -43,20 +91,30 @@ impl<'a> DiagnosticsPrinter<'a> {
 
     pub fn print(&self) {
         for diagnostic in self.diagnostics {
-            println!("{}", self.stringify_diagnostic(diagnostic));
+            eprintln!("{}", self.stringify_diagnostic(diagnostic));
         }
     }
 
+    /// Splits `line` around the span starting at byte `offset`, keeping up to `PREFIX_LENGTH`
+    /// characters of context on either side.
     fn get_text_spans(
         &'a self,
-        column: usize,
-        diagnostic: &Diagnostic,
+        offset: usize,
+        text_span: &TextSpan,
         line: &'a str,
     ) -> (&'a str, &'a str, &'a str) {
-        let prefix_start = cmp::max(0, column as isize - PREFIX_LENGTH as isize) as usize;
-        let prefix_end = column;
-        let suffix_start = cmp::min(column + diagnostic.span.length(), line.len());
-        let suffix_end = cmp::min(suffix_start + PREFIX_LENGTH, line.len());
+        let prefix_start = line[..offset]
+            .char_indices()
+            .rev()
+            .take(PREFIX_LENGTH)
+            .last()
+            .map_or(offset, |(index, _)| index);
+        let prefix_end = offset;
+        let suffix_start = floor_char_boundary(line, offset + text_span.length());
+        let suffix_end = line[suffix_start..]
+            .char_indices()
+            .nth(PREFIX_LENGTH)
+            .map_or(line.len(), |(index, _)| suffix_start + index);
 
         let prefix = &line[prefix_start..prefix_end];
         let span = &line[prefix_end..suffix_start];
@@ -64,12 +122,15 @@ impl<'a> DiagnosticsPrinter<'a> {
         (prefix, span, suffix)
     }
 
-    fn format_arrow(&self, diagnostic: &Diagnostic, indent: usize) -> (String, String) {
+    fn format_arrow(&self, text_span: &TextSpan, indent: usize) -> (String, String) {
+        let text = self.text.as_str();
+        let end = floor_char_boundary(text, text_span.end);
+        let start = floor_char_boundary(text, cmp::min(text_span.start, end));
         let arrow_pointers = format!(
             "{:indent$}{}",
             "",
             std::iter::repeat('^')
-                .take(diagnostic.span.length())
+                .take(text[start..end].chars().count())
                 .collect::<String>(),
             indent = indent
         );
@@ -77,8 +138,17 @@ impl<'a> DiagnosticsPrinter<'a> {
         (arrow_pointers, arrow_line)
     }
 
-    fn format_error_message(&self, diagnostic: &Diagnostic, indent: usize) -> String {
-        let error_message = format!("{:indent$}+-- {}", "", diagnostic.message, indent = indent);
+    fn format_error_message(&self, message: &str, indent: usize) -> String {
+        let error_message = format!("{:indent$}+-- {}", "", message, indent = indent);
         error_message
     }
 }
+
+/// The largest index of a character boundary in `text` that is not past `index`.
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = cmp::min(index, text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
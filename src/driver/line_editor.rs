@@ -0,0 +1,129 @@
+use std::{
+    cmp,
+    io::{self, BufRead, Write},
+};
+
+use termion::{clear, cursor, event::Key, input::TermRead, raw::IntoRawMode};
+
+pub enum ReadResult {
+    Line(String),
+    Interrupted,
+    Eof,
+}
+
+pub struct LineEditor {
+    history: Vec<String>,
+    interactive: bool,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self {
+            history: Vec::new(),
+            interactive: termion::is_tty(&io::stdin()),
+        }
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().map(|last| last.as_str()) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_string());
+    }
+
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<ReadResult> {
+        if self.interactive {
+            self.read_raw_line(prompt)
+        } else {
+            self.read_plain_line()
+        }
+    }
+
+    fn read_plain_line(&mut self) -> io::Result<ReadResult> {
+        let mut line = String::new();
+        if BufRead::read_line(&mut io::stdin().lock(), &mut line)? == 0 {
+            return Ok(ReadResult::Eof);
+        }
+        let line = line.trim_end_matches(&['\r', '\n'][..]).to_string();
+        Ok(ReadResult::Line(line))
+    }
+
+    fn read_raw_line(&mut self, prompt: &str) -> io::Result<ReadResult> {
+        let mut stdout = io::stdout().into_raw_mode()?;
+        let mut buffer: Vec<char> = Vec::new();
+        let mut cursor_pos = 0;
+        let mut history_index = self.history.len();
+        let mut draft: Vec<char> = Vec::new();
+
+        Self::render(&mut stdout, prompt, &buffer, cursor_pos)?;
+        for key in io::stdin().keys() {
+            match key? {
+                Key::Char('\n') => {
+                    write!(stdout, "\r\n")?;
+                    return Ok(ReadResult::Line(buffer.iter().collect()));
+                }
+                Key::Ctrl('c') => {
+                    write!(stdout, "^C\r\n")?;
+                    return Ok(ReadResult::Interrupted);
+                }
+                Key::Ctrl('d') if buffer.is_empty() => {
+                    write!(stdout, "\r\n")?;
+                    return Ok(ReadResult::Eof);
+                }
+                Key::Char(c) => {
+                    buffer.insert(cursor_pos, c);
+                    cursor_pos += 1;
+                }
+                Key::Backspace if cursor_pos > 0 => {
+                    cursor_pos -= 1;
+                    buffer.remove(cursor_pos);
+                }
+                Key::Delete if cursor_pos < buffer.len() => {
+                    buffer.remove(cursor_pos);
+                }
+                Key::Left => cursor_pos = cursor_pos.saturating_sub(1),
+                Key::Right => cursor_pos = cmp::min(cursor_pos + 1, buffer.len()),
+                Key::Home | Key::Ctrl('a') => cursor_pos = 0,
+                Key::End | Key::Ctrl('e') => cursor_pos = buffer.len(),
+                Key::Up if history_index > 0 => {
+                    if history_index == self.history.len() {
+                        draft = buffer.clone();
+                    }
+                    history_index -= 1;
+                    buffer = self.history[history_index].chars().collect();
+                    cursor_pos = buffer.len();
+                }
+                Key::Down if history_index < self.history.len() => {
+                    history_index += 1;
+                    buffer = match self.history.get(history_index) {
+                        Some(line) => line.chars().collect(),
+                        None => draft.clone(),
+                    };
+                    cursor_pos = buffer.len();
+                }
+                _ => {}
+            }
+            Self::render(&mut stdout, prompt, &buffer, cursor_pos)?;
+        }
+        Ok(ReadResult::Eof)
+    }
+
+    fn render(
+        stdout: &mut impl Write,
+        prompt: &str,
+        buffer: &[char],
+        cursor_pos: usize,
+    ) -> io::Result<()> {
+        let line: String = buffer.iter().collect();
+        write!(stdout, "\r{}{}{}", clear::CurrentLine, prompt, line)?;
+        let offset = buffer.len() - cursor_pos;
+        if offset > 0 {
+            write!(stdout, "{}", cursor::Left(offset as u16))?;
+        }
+        stdout.flush()
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    cell::RefCell,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::ast::{evaluator::ArithmeticMode, lexer::TokenKind};
+use crate::codegen::x86_64;
+use crate::compilation_unit::{CompilationFailure, CompilationUnit};
+use crate::diagnostics::DiagnosticBag;
+use crate::text::SourceText;
+
+mod line_editor;
+mod repl;
+
+const USAGE: &str = "\
+Usage: rust-compiler <command> [options] [<path>...]
+       rust-compiler repl [options]
+
+Commands:
+    run      Compile and evaluate each input, printing the final value
+    build    Compile each input to a native x86-64 executable
+    check    Run the lexer, parser, symbol checker, type checker and linter without evaluating
+    tokens   Print the token stream of each input
+    ast      Print the parsed syntax tree of each input
+    repl     Start an interactive session
+
+Options:
+    --wrapping          Let integer overflow wrap around instead of failing at runtime
+    -D warnings         Treat warnings as errors
+    --backend=<name>    How `run` executes programs: `tree` walks the syntax tree (default),
+                        `vm` compiles them to bytecode for the virtual machine
+    -o <path>           Where `build` writes the executable of its single input. Defaults to
+                        the input path without its extension
+    --emit=<kind>       Make `build` print an intermediate form instead of linking:
+                        `optimized-ast` prints the syntax tree after constant folding,
+                        `ir` the SSA form the compiled backends start from, `asm`
+                        the x86-64 assembly, `c` a C11 translation and `wat` a WebAssembly
+                        module in text format. Both translations need the type of every
+                        parameter and returned value to be annotated or inferable
+
+Inputs are processed in order and processing stops at the first failing one.
+Use `-` as a path to read from standard input.
+
+Exit codes:
+    0  success
+    1  usage or I/O error
+    2  lex or parse errors
+    3  semantic errors
+    4  runtime failure
+    5  assembling or linking failed";
+
+const STDIN_NAME: &str = "<stdin>";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitStatus {
+    Success = 0,
+    Usage = 1,
+    SyntaxError = 2,
+    SemanticError = 3,
+    RuntimeError = 4,
+    BuildError = 5,
+}
+
+impl From<CompilationFailure> for ExitStatus {
+    fn from(failure: CompilationFailure) -> Self {
+        match failure {
+            CompilationFailure::Syntax => ExitStatus::SyntaxError,
+            CompilationFailure::Semantic => ExitStatus::SemanticError,
+            CompilationFailure::Runtime => ExitStatus::RuntimeError,
+            CompilationFailure::Build => ExitStatus::BuildError,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Run,
+    Build,
+    Check,
+    Tokens,
+    Ast,
+    Repl,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "run" => Some(Command::Run),
+            "build" => Some(Command::Build),
+            "check" => Some(Command::Check),
+            "tokens" => Some(Command::Tokens),
+            "ast" => Some(Command::Ast),
+            "repl" => Some(Command::Repl),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Tree,
+    Vm,
+}
+
+impl Backend {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tree" => Some(Backend::Tree),
+            "vm" => Some(Backend::Vm),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Assembly,
+    C,
+    Wat,
+    Ir,
+    OptimizedAst,
+}
+
+impl Emit {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "asm" => Some(Emit::Assembly),
+            "c" => Some(Emit::C),
+            "wat" => Some(Emit::Wat),
+            "ir" => Some(Emit::Ir),
+            "optimized-ast" => Some(Emit::OptimizedAst),
+            _ => None,
+        }
+    }
+}
+
+pub struct Options {
+    pub command: Command,
+    pub inputs: Vec<String>,
+    pub arithmetic: ArithmeticMode,
+    pub backend: Backend,
+    pub emit: Option<Emit>,
+    pub output: Option<String>,
+    pub deny_warnings: bool,
+}
+
+impl Options {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let name = args.next().ok_or_else(|| "missing command".to_string())?;
+        let command =
+            Command::from_name(&name).ok_or_else(|| format!("unknown command '{}'", name))?;
+
+        let mut inputs = Vec::new();
+        let mut arithmetic = ArithmeticMode::Checked;
+        let mut backend = Backend::Tree;
+        let mut emit = None;
+        let mut output = None;
+        let mut deny_warnings = false;
+        while let Some(arg) = args.next() {
+            if arg == "--wrapping" {
+                arithmetic = ArithmeticMode::Wrapping;
+                continue;
+            }
+            if let Some(name) = arg.strip_prefix("--backend=") {
+                backend = Backend::from_name(name)
+                    .ok_or_else(|| format!("unknown backend '{}'", name))?;
+                continue;
+            }
+            if let Some(name) = arg.strip_prefix("--emit=") {
+                emit = Some(
+                    Emit::from_name(name).ok_or_else(|| format!("unknown emit kind '{}'", name))?,
+                );
+                continue;
+            }
+            if arg == "-D" {
+                match args.next().as_deref() {
+                    Some("warnings") => deny_warnings = true,
+                    Some(lint) => return Err(format!("unknown lint group '{}'", lint)),
+                    None => return Err("missing lint group after '-D'".to_string()),
+                }
+                continue;
+            }
+            if arg == "-o" {
+                output = Some(
+                    args.next()
+                        .ok_or_else(|| "missing path after '-o'".to_string())?,
+                );
+                continue;
+            }
+            if arg != "-" && arg.starts_with('-') {
+                return Err(format!("unknown option '{}'", arg));
+            }
+            inputs.push(arg);
+        }
+        if command == Command::Repl && !inputs.is_empty() {
+            return Err("repl does not take input files".to_string());
+        }
+        if command != Command::Repl && inputs.is_empty() {
+            return Err("no input files".to_string());
+        }
+        if (emit.is_some() || output.is_some()) && command != Command::Build {
+            return Err("'--emit' and '-o' only apply to build".to_string());
+        }
+        if output.is_some() && inputs.len() > 1 {
+            return Err("'-o' cannot be used with several inputs".to_string());
+        }
+
+        Ok(Self {
+            command,
+            inputs,
+            arithmetic,
+            backend,
+            emit,
+            output,
+            deny_warnings,
+        })
+    }
+}
+
+pub fn run(args: impl Iterator<Item = String>) -> ExitStatus {
+    let args: Vec<String> = args.collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitStatus::Success;
+    }
+
+    let options = match Options::parse(args.into_iter()) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return ExitStatus::Usage;
+        }
+    };
+
+    if options.command == Command::Repl {
+        return repl::Repl::new(options.arithmetic).run();
+    }
+
+    for input in &options.inputs {
+        let text = match read_input(input) {
+            Ok(text) => text,
+            Err(error) => {
+                eprintln!("error: cannot read '{}': {}", input, error);
+                return ExitStatus::Usage;
+            }
+        };
+
+        if let Err(failure) = run_command(&options, text) {
+            return failure.into();
+        }
+    }
+
+    ExitStatus::Success
+}
+
+fn read_input(path: &str) -> io::Result<SourceText> {
+    if path == "-" {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        return Ok(SourceText::new(STDIN_NAME.to_string(), text));
+    }
+    let text = fs::read_to_string(path)?;
+    Ok(SourceText::new(path.to_string(), text))
+}
+
+fn run_command(options: &Options, text: SourceText) -> Result<(), CompilationFailure> {
+    match options.command {
+        Command::Tokens => print_tokens(&text),
+        Command::Ast => {
+            let unit = CompilationUnit::parse(text)?;
+            unit.ast.visualize();
+        }
+        Command::Check => {
+            let unit = parse(options, text)?;
+            unit.check()?;
+        }
+        Command::Run => {
+            let mut unit = parse(options, text)?;
+            let types = unit.check()?;
+            unit.optimize(&types, options.arithmetic);
+            let value = match options.backend {
+                Backend::Tree => unit.evaluate(&types, options.arithmetic)?,
+                Backend::Vm => unit.run_bytecode(&types, options.arithmetic)?,
+            };
+            if let Some(value) = value {
+                println!("{}", value);
+            }
+        }
+        Command::Build => {
+            let output = output_path(options, &text);
+            let mut unit = parse(options, text)?;
+            let types = unit.check()?;
+            if !matches!(options.emit, Some(Emit::Ir | Emit::OptimizedAst)) {
+                unit.check_backend_support()?;
+            }
+            unit.optimize(&types, options.arithmetic);
+            match options.emit {
+                Some(Emit::Assembly) => {
+                    print!("{}", unit.emit_assembly(&types, options.arithmetic)?)
+                }
+                Some(Emit::C) => print!("{}", unit.emit_c(&types, options.arithmetic)?),
+                Some(Emit::Wat) => print!("{}", unit.emit_wat(&types, options.arithmetic)?),
+                Some(Emit::Ir) => print!("{}", unit.emit_ir(&types)?),
+                Some(Emit::OptimizedAst) => unit.ast.visualize(),
+                None => {
+                    let assembly = unit.emit_assembly(&types, options.arithmetic)?;
+                    x86_64::link(&assembly, &output).map_err(|error| {
+                        eprintln!("error: cannot build '{}': {}", output.display(), error);
+                        CompilationFailure::Build
+                    })?
+                }
+            }
+        }
+        Command::Repl => unreachable!(),
+    }
+    Ok(())
+}
+
+fn parse(options: &Options, text: SourceText) -> Result<CompilationUnit, CompilationFailure> {
+    let mut diagnostics_bag = DiagnosticBag::new();
+    diagnostics_bag.deny_warnings = options.deny_warnings;
+    CompilationUnit::parse_with(text, Rc::new(RefCell::new(diagnostics_bag)))
+}
+
+fn output_path(options: &Options, text: &SourceText) -> PathBuf {
+    if let Some(output) = &options.output {
+        return PathBuf::from(output);
+    }
+    if text.name() == STDIN_NAME {
+        return PathBuf::from("a.out");
+    }
+    // An input without an extension would otherwise be overwritten.
+    let input = Path::new(text.name());
+    match input.extension() {
+        Some(_) => input.with_extension(""),
+        None => input.with_extension("out"),
+    }
+}
+
+fn print_tokens(text: &SourceText) {
+    for token in CompilationUnit::lex(text) {
+        if token.kind != TokenKind::Whitespace {
+            println!(
+                "{:?} {:?} {}..{}",
+                token.kind, token.span.literal, token.span.start, token.span.end
+            );
+        }
+    }
+}
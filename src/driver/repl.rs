@@ -0,0 +1,212 @@
+use std::{cell::RefCell, io, rc::Rc};
+
+use crate::ast::{
+    evaluator::{ASTEvaluator, ArithmeticMode},
+    lexer::TokenKind,
+    parser::Parser,
+    symbol_checker::SymbolChecker,
+    type_checker::TypeChecker,
+};
+use crate::compilation_unit::CompilationUnit;
+use crate::diagnostics::{DiagnosticBag, DiagnosticsBagCell};
+use crate::text::SourceText;
+
+use super::line_editor::{LineEditor, ReadResult};
+use super::{print_tokens, ExitStatus};
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+const SOURCE_NAME: &str = "<repl>";
+
+const HELP: &str = "\
+:vars            List the variables defined so far
+:ast [code]      Print the syntax tree of the code, or of the last input
+:tokens [code]   Print the tokens of the code, or of the last input
+:history         List the lines entered so far
+:help            Show this message
+:quit            Leave the REPL";
+
+pub struct Repl {
+    editor: LineEditor,
+    diagnostics_bag: DiagnosticsBagCell,
+    symbol_checker: SymbolChecker,
+    type_checker: TypeChecker,
+    evaluator: ASTEvaluator,
+    /// Every input of the session, so that diagnostics can point into earlier inputs.
+    session: String,
+    last_input: Option<String>,
+}
+
+impl Repl {
+    pub fn new(arithmetic: ArithmeticMode) -> Self {
+        let diagnostics_bag: DiagnosticsBagCell = Rc::new(RefCell::new(DiagnosticBag::new()));
+        Self {
+            editor: LineEditor::new(),
+            symbol_checker: SymbolChecker::new(Rc::clone(&diagnostics_bag)),
+            type_checker: TypeChecker::new(Rc::clone(&diagnostics_bag)),
+            evaluator: ASTEvaluator::new(arithmetic),
+            session: String::new(),
+            diagnostics_bag,
+            last_input: None,
+        }
+    }
+
+    pub fn run(&mut self) -> ExitStatus {
+        loop {
+            let input = match self.read_input() {
+                Ok(Some(input)) => input,
+                Ok(None) => return ExitStatus::Success,
+                Err(error) => {
+                    eprintln!("error: {}", error);
+                    return ExitStatus::Usage;
+                }
+            };
+
+            let trimmed = input.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            if let Some(command) = trimmed.strip_prefix(':') {
+                if !self.execute_meta_command(command) {
+                    return ExitStatus::Success;
+                }
+                continue;
+            }
+
+            self.execute(&input);
+            self.last_input = Some(input);
+        }
+    }
+
+    /// Reads one complete input, asking for more lines while parentheses or braces are still open.
+    /// Returns `None` once the input is exhausted.
+    fn read_input(&mut self) -> io::Result<Option<String>> {
+        let mut input = String::new();
+        loop {
+            let prompt = if input.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+            match self.editor.read_line(prompt)? {
+                ReadResult::Line(line) => {
+                    self.editor.add_history(&line);
+                    if !input.is_empty() {
+                        input.push('\n');
+                    }
+                    input.push_str(&line);
+                }
+                ReadResult::Interrupted => {
+                    input.clear();
+                    continue;
+                }
+                ReadResult::Eof if input.is_empty() => return Ok(None),
+                ReadResult::Eof => return Ok(Some(input)),
+            }
+
+            if input.trim_start().starts_with(':') || !Self::is_incomplete(&input) {
+                return Ok(Some(input));
+            }
+        }
+    }
+
+    /// Whether `input` has unclosed brackets or ends with a token that continues on the next
+    /// line, such as a binary operator.
+    fn is_incomplete(input: &str) -> bool {
+        let text = SourceText::new(SOURCE_NAME.to_string(), input.to_string());
+        let tokens = CompilationUnit::lex(&text);
+        let continued = tokens
+            .iter()
+            .rev()
+            .find(|token| {
+                !matches!(
+                    token.kind,
+                    TokenKind::Whitespace
+                        | TokenKind::Newline
+                        | TokenKind::Comment
+                        | TokenKind::DocComment
+                        | TokenKind::EOF
+                )
+            })
+            .is_some_and(|token| Parser::is_continued(&token.kind));
+        let depth: isize = tokens
+            .iter()
+            .map(|token| match token.kind {
+                TokenKind::LeftParen | TokenKind::OpenBrace => 1,
+                TokenKind::RightParen | TokenKind::CloseBrace => -1,
+                _ => 0,
+            })
+            .sum();
+        depth > 0 || continued
+    }
+
+    fn execute(&mut self, input: &str) {
+        let start = self.session.len();
+        self.session.push_str(input);
+        self.session.push('\n');
+        let text = SourceText::new(SOURCE_NAME.to_string(), self.session.clone());
+        if let Ok(unit) = CompilationUnit::parse_from(text, start, Rc::clone(&self.diagnostics_bag))
+        {
+            // A rejected line must not leave its declarations behind.
+            let snapshot = (
+                self.symbol_checker.clone(),
+                self.type_checker.clone(),
+                self.evaluator.clone(),
+            );
+            let mut result = unit.check_with(&mut self.symbol_checker, &mut self.type_checker);
+            if result.is_ok() {
+                self.evaluator.last_value = None;
+                match unit.evaluate_with(&mut self.evaluator, &self.type_checker) {
+                    Ok(Some(value)) => println!("{}", value),
+                    Ok(None) => {}
+                    Err(failure) => result = Err(failure),
+                }
+            }
+            if result.is_err() {
+                (self.symbol_checker, self.type_checker, self.evaluator) = snapshot;
+            }
+        }
+        self.diagnostics_bag.borrow_mut().diagnostics.clear();
+    }
+
+    /// Runs a `:command`. Returns `false` when the session should end.
+    fn execute_meta_command(&mut self, command: &str) -> bool {
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command, ""),
+        };
+        let code = if argument.is_empty() {
+            self.last_input.clone()
+        } else {
+            Some(argument.to_string())
+        };
+
+        match name {
+            "vars" => {
+                for (name, value) in self.evaluator.globals() {
+                    println!("{} = {}", name, value);
+                }
+            }
+            "ast" | "tokens" => match code {
+                Some(code) => {
+                    let text = SourceText::new(SOURCE_NAME.to_string(), code);
+                    if name == "tokens" {
+                        print_tokens(&text);
+                    } else if let Ok(unit) = CompilationUnit::parse(text) {
+                        unit.ast.visualize();
+                    }
+                }
+                None => eprintln!("error: nothing to show yet"),
+            },
+            "history" => {
+                for (index, line) in self.editor.history().iter().enumerate() {
+                    println!("{:>4}  {}", index + 1, line);
+                }
+            }
+            "help" => println!("{}", HELP),
+            "quit" | "q" => return false,
+            _ => eprintln!("error: unknown command ':{}', try :help", name),
+        }
+        true
+    }
+}
@@ -0,0 +1,1063 @@
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    rc::Rc,
+};
+
+use crate::ast::{
+    evaluator::Value, lexer::TextSpan, scopes::Scopes, type_checker::TypeChecker, types::Type,
+    ASTAssignmentExpression, ASTBinaryExpression, ASTBinaryOperatorKind, ASTBlockStatement,
+    ASTBooleanExpression, ASTBreakStatement, ASTCallExpression, ASTCastExpression,
+    ASTContinueStatement, ASTExpression, ASTExpressionKind, ASTFloatExpression,
+    ASTFunctionDeclaration, ASTIfStatement, ASTLetStatement, ASTNumberExpression,
+    ASTParenthesizedExpression, ASTReturnStatement, ASTStatement, ASTStatementKind,
+    ASTStringExpression, ASTUnaryExpression, ASTUnaryOperatorKind, ASTVariableExpression,
+    ASTVisitor, ASTWhileStatement, Ast,
+};
+use crate::ir::{
+    BasicBlock, BlockId, Function, FunctionId, GlobalId, Instruction, InstructionKind, Parameter,
+    Phi, Program, Terminator, ValueId, MAIN_FUNCTION,
+};
+
+/// Where a statement hands its value.
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    Discard,
+    /// Returned from the function being lowered.
+    Return,
+    /// Left in `last_value` for the if expression being lowered, whose type is given.
+    Value(Type),
+}
+
+#[derive(Clone, Copy)]
+enum Variable {
+    Global(GlobalId, Type),
+    /// An index into `variable_types`. Its value in each block is tracked by the SSA builder.
+    Local(usize),
+}
+
+struct FunctionSignature {
+    id: FunctionId,
+    return_type: Option<Type>,
+}
+
+struct Loop {
+    continue_block: BlockId,
+    break_block: BlockId,
+}
+
+#[derive(Default)]
+struct BlockBuilder {
+    phis: Vec<Phi>,
+    instructions: Vec<Instruction>,
+    terminator: Option<Terminator>,
+    predecessors: Vec<BlockId>,
+    sealed: bool,
+    /// Phis created before every predecessor was known, with the variable they stand for.
+    incomplete_phis: Vec<(usize, ValueId)>,
+}
+
+/// Builds the SSA form of one function while its statements are lowered, following Braun et
+/// al., "Simple and Efficient Construction of Static Single Assignment Form". Reading a
+/// variable looks for its definition in the current block and otherwise asks the predecessors,
+/// placing phis where they disagree. A block is sealed once all its predecessors are known.
+struct FunctionBuilder {
+    name: String,
+    parameters: Vec<Parameter>,
+    return_type: Option<Type>,
+    value_types: Vec<Type>,
+    blocks: Vec<BlockBuilder>,
+    current: BlockId,
+    definitions: HashMap<(usize, BlockId), ValueId>,
+    loops: Vec<Loop>,
+}
+
+impl FunctionBuilder {
+    fn new(name: String, return_type: Option<Type>) -> Self {
+        let mut builder = Self {
+            name,
+            parameters: Vec::new(),
+            return_type,
+            value_types: Vec::new(),
+            blocks: Vec::new(),
+            current: BlockId(0),
+            definitions: HashMap::new(),
+            loops: Vec::new(),
+        };
+        let entry = builder.new_block();
+        builder.seal(entry);
+        builder
+    }
+
+    fn new_value(&mut self, ty: Type) -> ValueId {
+        self.value_types.push(ty);
+        ValueId(self.value_types.len() - 1)
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BlockBuilder::default());
+        BlockId(self.blocks.len() - 1)
+    }
+
+    fn emit(
+        &mut self,
+        kind: InstructionKind,
+        result_type: Option<Type>,
+        span: Option<TextSpan>,
+    ) -> Option<ValueId> {
+        let result = result_type.map(|ty| self.new_value(ty));
+        self.blocks[self.current.0]
+            .instructions
+            .push(Instruction { result, kind, span });
+        result
+    }
+
+    fn emit_value(&mut self, kind: InstructionKind, ty: Type, span: Option<TextSpan>) -> ValueId {
+        self.emit(kind, Some(ty), span).unwrap()
+    }
+
+    /// Ends the current block and continues in a new one. Code lowered after a jump is
+    /// unreachable until the current block is switched again.
+    fn terminate(&mut self, terminator: Terminator) {
+        for successor in terminator.successors() {
+            self.blocks[successor.0].predecessors.push(self.current);
+        }
+        self.blocks[self.current.0].terminator = Some(terminator);
+        let unreachable = self.new_block();
+        self.seal(unreachable);
+        self.current = unreachable;
+    }
+
+    fn seal(&mut self, block: BlockId) {
+        for (variable, phi) in mem::take(&mut self.blocks[block.0].incomplete_phis) {
+            self.add_phi_operands(variable, phi, block);
+        }
+        self.blocks[block.0].sealed = true;
+    }
+
+    fn new_phi(&mut self, block: BlockId, ty: Type) -> ValueId {
+        let result = self.new_value(ty);
+        self.blocks[block.0].phis.push(Phi {
+            result,
+            incoming: Vec::new(),
+        });
+        result
+    }
+
+    fn write_variable(&mut self, variable: usize, block: BlockId, value: ValueId) {
+        self.definitions.insert((variable, block), value);
+    }
+
+    fn read_variable(&mut self, variable: usize, ty: Type, block: BlockId) -> ValueId {
+        if let Some(value) = self.definitions.get(&(variable, block)) {
+            return *value;
+        }
+        let predecessors = &self.blocks[block.0].predecessors;
+        let value = if !self.blocks[block.0].sealed {
+            let phi = self.new_phi(block, ty);
+            self.blocks[block.0].incomplete_phis.push((variable, phi));
+            phi
+        } else if predecessors.len() == 1 {
+            let predecessor = predecessors[0];
+            self.read_variable(variable, ty, predecessor)
+        } else {
+            // Written first so that reads through loops find the phi and stop there. Blocks
+            // without predecessors are unreachable, so their phi without operands is removed.
+            let phi = self.new_phi(block, ty);
+            self.write_variable(variable, block, phi);
+            self.add_phi_operands(variable, phi, block);
+            phi
+        };
+        self.write_variable(variable, block, value);
+        value
+    }
+
+    fn add_phi_operands(&mut self, variable: usize, phi: ValueId, block: BlockId) {
+        let ty = self.value_types[phi.0];
+        for predecessor in self.blocks[block.0].predecessors.clone() {
+            let value = self.read_variable(variable, ty, predecessor);
+            let phis = &mut self.blocks[block.0].phis;
+            let phi = phis
+                .iter_mut()
+                .find(|candidate| candidate.result == phi)
+                .unwrap();
+            phi.incoming.push((predecessor, value));
+        }
+    }
+
+    /// The reachable blocks in reverse postorder, which puts the `then` branch of an `if`
+    /// right after the condition and the body of a loop right after its header.
+    fn reverse_postorder(&self) -> Vec<BlockId> {
+        let successors = |block: BlockId| {
+            self.blocks[block.0]
+                .terminator
+                .as_ref()
+                .expect("every reachable block is terminated")
+                .successors()
+        };
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = Vec::new();
+        // Each entry holds a block and its successors left to visit. Taking the last one first
+        // puts the first successor first in the reversed order.
+        let mut stack = vec![(BlockId(0), successors(BlockId(0)))];
+        visited[0] = true;
+        while let Some((block, remaining)) = stack.last_mut() {
+            match remaining.pop() {
+                Some(successor) => {
+                    if !mem::replace(&mut visited[successor.0], true) {
+                        stack.push((successor, successors(successor)));
+                    }
+                }
+                None => {
+                    postorder.push(*block);
+                    stack.pop();
+                }
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+
+    /// Skips blocks that do nothing but jump, such as those `break` and `continue` leave.
+    /// When the target has phis, the block must have a single predecessor which takes its
+    /// place in the phis, and which must not already jump to the target.
+    fn thread_jumps(&mut self) {
+        let order = self.reverse_postorder();
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for block in &order {
+            for successor in self.blocks[block.0]
+                .terminator
+                .as_ref()
+                .unwrap()
+                .successors()
+            {
+                predecessors[successor.0].push(*block);
+            }
+        }
+        for block in order.into_iter().skip(1) {
+            let builder = &self.blocks[block.0];
+            let target = match builder.terminator {
+                Some(Terminator::Jump(target))
+                    if target != block
+                        && builder.phis.is_empty()
+                        && builder.instructions.is_empty() =>
+                {
+                    target
+                }
+                _ => continue,
+            };
+            let sources = predecessors[block.0].clone();
+            if !self.blocks[target.0].phis.is_empty() {
+                match sources[..] {
+                    [source] if !predecessors[target.0].contains(&source) => {
+                        for phi in self.blocks[target.0].phis.iter_mut() {
+                            for (predecessor, _) in phi.incoming.iter_mut() {
+                                if *predecessor == block {
+                                    *predecessor = source;
+                                }
+                            }
+                        }
+                    }
+                    _ => continue,
+                }
+            }
+            for source in &sources {
+                let terminator = self.blocks[source.0].terminator.as_mut().unwrap();
+                for successor in terminator.successors_mut() {
+                    if *successor == block {
+                        *successor = target;
+                    }
+                }
+            }
+            predecessors[target.0].retain(|predecessor| *predecessor != block);
+            predecessors[target.0].extend(sources);
+            predecessors[block.0].clear();
+        }
+    }
+
+    /// Drops unreachable blocks and trivial phis, then numbers blocks and values in order.
+    fn finish(mut self) -> Function {
+        self.thread_jumps();
+        let order = self.reverse_postorder();
+        let block_ids: HashMap<BlockId, BlockId> = order
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (*block, BlockId(index)))
+            .collect();
+        let mut builders: Vec<Option<BlockBuilder>> = self.blocks.into_iter().map(Some).collect();
+        let mut blocks: Vec<BasicBlock> = order
+            .iter()
+            .map(|block| {
+                let block = builders[block.0].take().unwrap();
+                let mut terminator = block.terminator.unwrap();
+                for successor in terminator.successors_mut() {
+                    *successor = block_ids[successor];
+                }
+                let phis = block
+                    .phis
+                    .into_iter()
+                    .map(|phi| Phi {
+                        result: phi.result,
+                        incoming: phi
+                            .incoming
+                            .into_iter()
+                            .filter_map(|(block, value)| Some((*block_ids.get(&block)?, value)))
+                            .collect(),
+                    })
+                    .collect();
+                BasicBlock {
+                    phis,
+                    instructions: block.instructions,
+                    terminator,
+                }
+            })
+            .collect();
+
+        Self::remove_trivial_phis(&mut blocks);
+
+        // Parameters keep the first values.
+        let mut value_ids: HashMap<ValueId, ValueId> = self
+            .parameters
+            .iter()
+            .map(|parameter| (parameter.value, parameter.value))
+            .collect();
+        let mut value_types: Vec<Type> = self
+            .parameters
+            .iter()
+            .map(|parameter| self.value_types[parameter.value.0])
+            .collect();
+        let mut number = |value: &mut ValueId| {
+            let next = ValueId(value_types.len());
+            value_types.push(self.value_types[value.0]);
+            value_ids.insert(*value, next);
+            *value = next;
+        };
+        for block in blocks.iter_mut() {
+            for phi in block.phis.iter_mut() {
+                number(&mut phi.result);
+            }
+            for instruction in block.instructions.iter_mut() {
+                if let Some(result) = instruction.result.as_mut() {
+                    number(result);
+                }
+            }
+        }
+        for block in blocks.iter_mut() {
+            for phi in block.phis.iter_mut() {
+                for (_, value) in phi.incoming.iter_mut() {
+                    *value = value_ids[value];
+                }
+            }
+            for instruction in block.instructions.iter_mut() {
+                for operand in instruction.kind.operands_mut() {
+                    *operand = value_ids[operand];
+                }
+            }
+            for operand in block.terminator.operands_mut() {
+                *operand = value_ids[operand];
+            }
+        }
+
+        Function {
+            name: self.name,
+            parameters: self.parameters,
+            return_type: self.return_type,
+            value_types,
+            blocks,
+        }
+    }
+
+    /// Replaces phis whose operands are all the same value, or the phi itself, by that value.
+    fn remove_trivial_phis(blocks: &mut [BasicBlock]) {
+        loop {
+            let mut replacements = HashMap::new();
+            for block in blocks.iter_mut() {
+                block.phis.retain(|phi| {
+                    let operands: HashSet<ValueId> = phi
+                        .incoming
+                        .iter()
+                        .map(|(_, value)| *value)
+                        .filter(|value| *value != phi.result)
+                        .collect();
+                    if operands.len() != 1 {
+                        return true;
+                    }
+                    replacements.insert(phi.result, *operands.iter().next().unwrap());
+                    false
+                });
+            }
+            if replacements.is_empty() {
+                return;
+            }
+            let resolve = |mut value: ValueId| {
+                while let Some(replacement) = replacements.get(&value) {
+                    value = *replacement;
+                }
+                value
+            };
+            for block in blocks.iter_mut() {
+                for phi in block.phis.iter_mut() {
+                    for (_, value) in phi.incoming.iter_mut() {
+                        *value = resolve(*value);
+                    }
+                }
+                for instruction in block.instructions.iter_mut() {
+                    for operand in instruction.kind.operands_mut() {
+                        *operand = resolve(*operand);
+                    }
+                }
+                for operand in block.terminator.operands_mut() {
+                    *operand = resolve(*operand);
+                }
+            }
+        }
+    }
+}
+
+/// Collects the names that function bodies read or assign without declaring them: the
+/// top-level variables that functions use.
+struct FreeVariables {
+    locals: Scopes<()>,
+    names: HashSet<String>,
+}
+
+impl FreeVariables {
+    fn of(ast: &Ast) -> HashSet<String> {
+        let mut free_variables = Self {
+            locals: Scopes::new(),
+            names: HashSet::new(),
+        };
+        for statement in &ast.statements {
+            if let ASTStatementKind::FunctionDeclaration(function) = &statement.kind {
+                free_variables.visit_function_declaration(function);
+            }
+        }
+        free_variables.names
+    }
+
+    fn use_variable(&mut self, identifier: &str) {
+        if self.locals.lookup(identifier).is_none() {
+            self.names.insert(identifier.to_string());
+        }
+    }
+}
+
+impl ASTVisitor for FreeVariables {
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        self.visit_expression(&let_statement.initializer);
+        self.locals
+            .declare(let_statement.identifier.span.literal.clone(), ());
+    }
+
+    fn visit_block_statement(&mut self, block: &ASTBlockStatement) {
+        self.locals.enter_scope();
+        for statement in &block.statements {
+            self.visit_statement(statement);
+        }
+        self.locals.exit_scope();
+    }
+
+    fn visit_function_declaration(&mut self, function: &Rc<ASTFunctionDeclaration>) {
+        self.locals.enter_frame();
+        for parameter in &function.parameters {
+            self.locals
+                .declare(parameter.identifier.span.literal.clone(), ());
+        }
+        self.visit_block_statement(&function.body);
+        self.locals.exit_frame();
+    }
+
+    fn visit_variable_expression(&mut self, variable: &ASTVariableExpression) {
+        self.use_variable(&variable.identifier().span.literal);
+    }
+
+    fn visit_assignment_expression(&mut self, assignment: &ASTAssignmentExpression) {
+        self.visit_expression(&assignment.value);
+        self.use_variable(&assignment.identifier.span.literal);
+    }
+
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {}
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {}
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {}
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {}
+
+    fn visit_error(&mut self, span: &TextSpan) {}
+}
+
+/// Lowers an `Ast` that passed the checkers into the IR.
+///
+/// Values keep the semantics of the evaluator: statements may produce no value, which is
+/// represented by `unit` values, and operators check the types of their operands at runtime.
+/// The static types found by the type checker are recorded for backends that need them.
+pub struct IrBuilder<'a> {
+    types: &'a TypeChecker,
+    /// The names of the top-level variables that functions use, which are kept in globals.
+    /// The others are SSA values of the main function, like the variables of blocks.
+    shared: HashSet<String>,
+    globals: Vec<String>,
+    functions: Vec<Option<Function>>,
+    signatures: HashMap<String, FunctionSignature>,
+    variables: Scopes<Variable>,
+    variable_types: Vec<Type>,
+    builder: FunctionBuilder,
+    target: Target,
+    /// The value of the last statement or expression, `None` if it produced none.
+    last_value: Option<ValueId>,
+}
+
+impl<'a> IrBuilder<'a> {
+    pub fn lower(ast: &Ast, types: &'a TypeChecker) -> Program {
+        let mut lowering = Self {
+            types,
+            shared: FreeVariables::of(ast),
+            globals: Vec::new(),
+            functions: vec![None],
+            signatures: HashMap::new(),
+            variables: Scopes::new(),
+            variable_types: Vec::new(),
+            builder: FunctionBuilder::new("<main>".to_string(), None),
+            target: Target::Discard,
+            last_value: None,
+        };
+        // Functions can call the ones declared after them.
+        for statement in &ast.statements {
+            if let ASTStatementKind::FunctionDeclaration(function) = &statement.kind {
+                lowering.declare_function(function);
+            }
+        }
+        // Only the last top-level statement decides the result of the program.
+        for (index, statement) in ast.statements.iter().enumerate() {
+            lowering.target = if index + 1 == ast.statements.len() {
+                Target::Return
+            } else {
+                Target::Discard
+            };
+            lowering.visit_statement(statement);
+        }
+        if ast.statements.is_empty() {
+            lowering.last_value = None;
+            lowering.deliver(Target::Return);
+        }
+        let main = mem::replace(
+            &mut lowering.builder,
+            FunctionBuilder::new(String::new(), None),
+        );
+        lowering.functions[MAIN_FUNCTION.0] = Some(main.finish());
+        Program {
+            globals: lowering.globals,
+            functions: lowering
+                .functions
+                .into_iter()
+                .map(|function| function.unwrap())
+                .collect(),
+        }
+    }
+
+    fn declare_function(&mut self, function: &ASTFunctionDeclaration) {
+        let name = &function.identifier.span.literal;
+        // Functions whose values are of unknown type are declared without a return type.
+        let return_type = match self.types.function_type(name).return_type {
+            Type::Unit | Type::Unknown => None,
+            ty => Some(ty),
+        };
+        let id = FunctionId(self.functions.len());
+        self.functions.push(None);
+        self.signatures.insert(
+            function.identifier.span.literal.clone(),
+            FunctionSignature { id, return_type },
+        );
+    }
+
+    /// A value for `value`, creating a `unit` value if there is none.
+    fn materialize(&mut self, value: Option<ValueId>) -> ValueId {
+        match value {
+            Some(value) => value,
+            None => self
+                .builder
+                .emit_value(InstructionKind::Unit, Type::Unit, None),
+        }
+    }
+
+    fn deliver(&mut self, target: Target) {
+        if target == Target::Return {
+            let value = self.materialize(self.last_value);
+            self.builder.terminate(Terminator::Return(value));
+        }
+    }
+
+    fn expression(&mut self, expression: &ASTExpression) -> ValueId {
+        self.visit_expression(expression);
+        let value = self.last_value.take();
+        self.materialize(value)
+    }
+
+    /// Lowers an expression whose value is used, which fails at runtime if it has none.
+    fn value(&mut self, expression: &ASTExpression) -> ValueId {
+        let value = self.expression(expression);
+        if Self::may_be_empty(expression) {
+            self.builder.emit(
+                InstructionKind::ExpectValue(value),
+                None,
+                Some(expression.span()),
+            );
+        }
+        value
+    }
+
+    fn may_be_empty(expression: &ASTExpression) -> bool {
+        match &expression.kind {
+            ASTExpressionKind::Call(_) | ASTExpressionKind::If(_) | ASTExpressionKind::Error(_) => {
+                true
+            }
+            ASTExpressionKind::Parenthesized(parenthesized) => {
+                Self::may_be_empty(&parenthesized.expression)
+            }
+            _ => false,
+        }
+    }
+
+    fn block(&mut self, block: &ASTBlockStatement, target: Target) {
+        self.variables.enter_scope();
+        if block.statements.is_empty() {
+            self.last_value = None;
+            self.deliver(target);
+        }
+        for (index, statement) in block.statements.iter().enumerate() {
+            self.target = if index + 1 == block.statements.len() {
+                target
+            } else {
+                Target::Discard
+            };
+            self.visit_statement(statement);
+        }
+        self.variables.exit_scope();
+    }
+
+    /// Lowers the `else` part of an `if`, adding its value to `incoming` for an if expression.
+    fn lower_else_branch(
+        &mut self,
+        if_statement: &ASTIfStatement,
+        target: Target,
+        incoming: &mut Vec<(BlockId, ValueId)>,
+    ) {
+        match &if_statement.else_branch {
+            Some(else_branch) => match &else_branch.else_statement.kind {
+                ASTStatementKind::Block(block) => self.block(block, target),
+                _ => {
+                    self.target = target;
+                    self.visit_statement(&else_branch.else_statement);
+                }
+            },
+            None => {
+                self.last_value = None;
+                self.deliver(target);
+            }
+        }
+        if let Target::Value(_) = target {
+            let value = self.materialize(self.last_value);
+            incoming.push((self.builder.current, value));
+        }
+    }
+
+    fn read(&mut self, identifier: &str) -> ValueId {
+        match self.variables.lookup(identifier) {
+            Some(Variable::Global(global, ty)) => {
+                let (global, ty) = (*global, *ty);
+                self.builder
+                    .emit_value(InstructionKind::LoadGlobal(global), ty, None)
+            }
+            Some(Variable::Local(variable)) => {
+                let variable = *variable;
+                let ty = self.variable_types[variable];
+                let current = self.builder.current;
+                self.builder.read_variable(variable, ty, current)
+            }
+            None => unreachable!("'{}' was not resolved by the symbol checker", identifier),
+        }
+    }
+
+    fn write(&mut self, variable: Variable, value: ValueId) {
+        match variable {
+            Variable::Global(global, _) => {
+                self.builder
+                    .emit(InstructionKind::StoreGlobal(global, value), None, None);
+            }
+            Variable::Local(variable) => {
+                let current = self.builder.current;
+                self.builder.write_variable(variable, current, value);
+            }
+        }
+    }
+
+    /// The type of the value `operator` produces from `left` and `right`, which the operator
+    /// checks at runtime. Arithmetic takes two ints or two floats, and `+` also two strings.
+    fn binary_type(&self, operator: ASTBinaryOperatorKind, left: ValueId, right: ValueId) -> Type {
+        let operands = (
+            self.builder.value_types[left.0],
+            self.builder.value_types[right.0],
+        );
+        match operator {
+            ASTBinaryOperatorKind::Plus
+            | ASTBinaryOperatorKind::Minus
+            | ASTBinaryOperatorKind::Multiply
+            | ASTBinaryOperatorKind::Divide => match operands {
+                (Type::String, _) | (_, Type::String)
+                    if operator == ASTBinaryOperatorKind::Plus =>
+                {
+                    Type::String
+                }
+                (Type::Float, _) | (_, Type::Float) => Type::Float,
+                (Type::Unknown, Type::Unknown) => Type::Unknown,
+                _ => Type::Integer,
+            },
+            _ => Type::Boolean,
+        }
+    }
+
+    fn declare_local(&mut self, identifier: &str, ty: Type) -> Variable {
+        self.variable_types.push(ty);
+        let variable = Variable::Local(self.variable_types.len() - 1);
+        self.variables.declare(identifier.to_string(), variable);
+        variable
+    }
+}
+
+impl ASTVisitor for IrBuilder<'_> {
+    fn visit_statement(&mut self, statement: &ASTStatement) {
+        let target = mem::replace(&mut self.target, Target::Discard);
+        match &statement.kind {
+            // They hand their value to the target from each branch.
+            ASTStatementKind::Block(_) | ASTStatementKind::If(_) => {
+                self.target = target;
+                self.do_visit_statement(statement);
+            }
+            ASTStatementKind::Expression(expression) => {
+                self.last_value = Some(self.expression(expression));
+                self.deliver(target);
+            }
+            ASTStatementKind::LetStatement(_) => {
+                self.do_visit_statement(statement);
+                self.deliver(target);
+            }
+            _ => {
+                self.do_visit_statement(statement);
+                self.last_value = None;
+                self.deliver(target);
+            }
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &ASTExpression) {
+        match &expression.kind {
+            ASTExpressionKind::If(if_expression) => {
+                self.target = Target::Value(self.types.type_of(expression));
+                self.visit_if_statement(if_expression);
+            }
+            _ => self.do_visit_expression(expression),
+        }
+        if let Some(expected) = self.types.runtime_checks.get(&expression.id).copied() {
+            let value = self.materialize(self.last_value);
+            let kind = InstructionKind::ExpectType(value, expected);
+            let checked = self
+                .builder
+                .emit_value(kind, expected, Some(expression.span()));
+            self.last_value = Some(checked);
+        }
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        let value = self.value(&let_statement.initializer);
+        let ty = match &let_statement.type_annotation {
+            Some(annotation) => {
+                Type::from_name(&annotation.type_name.span.literal).unwrap_or(Type::Unknown)
+            }
+            None => self.types.type_of(&let_statement.initializer),
+        };
+        let identifier = &let_statement.identifier.span.literal;
+        let variable = if self.variables.is_global() && self.shared.contains(identifier) {
+            // Redeclaring a global makes a new one, so functions keep the one they saw. The
+            // shadowed ones are told apart by a suffix: `g`, then `g.1`, `g.2` and so on.
+            let shadowed = self
+                .globals
+                .iter()
+                .filter(|global| global.split('.').next() == Some(identifier))
+                .count();
+            self.globals.push(match shadowed {
+                0 => identifier.clone(),
+                _ => format!("{}.{}", identifier, shadowed),
+            });
+            let global = GlobalId(self.globals.len() - 1);
+            let variable = Variable::Global(global, ty);
+            self.variables.declare(identifier.clone(), variable);
+            variable
+        } else {
+            self.declare_local(identifier, ty)
+        };
+        self.write(variable, value);
+        // Like in the evaluator, the value of a `let` is the value it binds.
+        self.last_value = Some(value);
+    }
+
+    fn visit_block_statement(&mut self, block: &ASTBlockStatement) {
+        let target = mem::replace(&mut self.target, Target::Discard);
+        self.block(block, target);
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        let target = mem::replace(&mut self.target, Target::Discard);
+        let condition = self.expression(&if_statement.condition);
+        let then_block = self.builder.new_block();
+        let merge_block = self.builder.new_block();
+        // Without an `else`, a discarded `if` can go straight to the code that follows it.
+        let skips_else = if_statement.else_branch.is_none() && target == Target::Discard;
+        let else_block = if skips_else {
+            merge_block
+        } else {
+            self.builder.new_block()
+        };
+        self.builder.terminate(Terminator::Branch {
+            condition,
+            then_block,
+            else_block,
+            span: if_statement.condition.span(),
+        });
+        self.builder.seal(then_block);
+
+        let mut incoming = Vec::new();
+        self.builder.current = then_block;
+        self.block(&if_statement.then_branch, target);
+        if let Target::Value(_) = target {
+            let value = self.materialize(self.last_value);
+            incoming.push((self.builder.current, value));
+        }
+        self.builder.terminate(Terminator::Jump(merge_block));
+
+        if !skips_else {
+            self.builder.seal(else_block);
+            self.builder.current = else_block;
+            self.lower_else_branch(if_statement, target, &mut incoming);
+            self.builder.terminate(Terminator::Jump(merge_block));
+        }
+
+        self.builder.seal(merge_block);
+        self.builder.current = merge_block;
+        self.last_value = match target {
+            Target::Value(ty) => {
+                let phi = self.builder.new_phi(merge_block, ty);
+                let phis = &mut self.builder.blocks[merge_block.0].phis;
+                phis.last_mut().unwrap().incoming = incoming;
+                Some(phi)
+            }
+            _ => None,
+        };
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        let header = self.builder.new_block();
+        self.builder.terminate(Terminator::Jump(header));
+        self.builder.current = header;
+        let condition = self.expression(&while_statement.condition);
+        let body = self.builder.new_block();
+        let exit = self.builder.new_block();
+        self.builder.terminate(Terminator::Branch {
+            condition,
+            then_block: body,
+            else_block: exit,
+            span: while_statement.condition.span(),
+        });
+        self.builder.seal(body);
+
+        self.builder.loops.push(Loop {
+            continue_block: header,
+            break_block: exit,
+        });
+        self.builder.current = body;
+        self.block(&while_statement.body, Target::Discard);
+        self.builder.terminate(Terminator::Jump(header));
+        self.builder.loops.pop();
+
+        self.builder.seal(header);
+        self.builder.seal(exit);
+        self.builder.current = exit;
+    }
+
+    fn visit_break_statement(&mut self, break_statement: &ASTBreakStatement) {
+        let target = self.builder.loops.last().unwrap().break_block;
+        self.builder.terminate(Terminator::Jump(target));
+    }
+
+    fn visit_continue_statement(&mut self, continue_statement: &ASTContinueStatement) {
+        let target = self.builder.loops.last().unwrap().continue_block;
+        self.builder.terminate(Terminator::Jump(target));
+    }
+
+    fn visit_function_declaration(&mut self, function: &Rc<ASTFunctionDeclaration>) {
+        let signature = &self.signatures[&function.identifier.span.literal];
+        let (id, return_type) = (signature.id, signature.return_type);
+        let name = function.identifier.span.literal.clone();
+        let outer = mem::replace(&mut self.builder, FunctionBuilder::new(name, return_type));
+        self.variables.enter_frame();
+        let parameter_types = &self
+            .types
+            .function_type(&function.identifier.span.literal)
+            .parameters;
+        for (parameter, &ty) in function.parameters.iter().zip(parameter_types) {
+            let value = self.builder.new_value(ty);
+            let identifier = &parameter.identifier.span.literal;
+            self.builder.parameters.push(Parameter {
+                name: identifier.clone(),
+                span: parameter.identifier.span.clone(),
+                value,
+            });
+            let variable = self.declare_local(identifier, ty);
+            self.write(variable, value);
+        }
+        self.block(&function.body, Target::Return);
+        self.variables.exit_frame();
+        let builder = mem::replace(&mut self.builder, outer);
+        self.functions[id.0] = Some(builder.finish());
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        let value = match &return_statement.return_value {
+            Some(return_value) => self.expression(return_value),
+            None => self.materialize(None),
+        };
+        self.builder.terminate(Terminator::Return(value));
+    }
+
+    fn visit_call_expression(&mut self, call: &ASTCallExpression) {
+        let arguments = call
+            .arguments
+            .iter()
+            .map(|argument| self.value(argument))
+            .collect();
+        let name = &call.identifier.span.literal;
+        let ty = self.types.function_type(name).return_type;
+        let kind = InstructionKind::Call(self.signatures[name].id, arguments);
+        self.last_value = Some(self.builder.emit_value(kind, ty, Some(call.span())));
+    }
+
+    fn visit_assignment_expression(&mut self, assignment: &ASTAssignmentExpression) {
+        let identifier = &assignment.identifier.span.literal;
+        // The value is evaluated before the variable is read, as in the evaluator.
+        let mut value = self.value(&assignment.value);
+        if let Some(operator) = assignment.operator.binary_operator() {
+            let current = self.read(identifier);
+            let ty = self.binary_type(operator.kind, current, value);
+            value = self.builder.emit_value(
+                InstructionKind::Binary(operator.kind, current, value),
+                ty,
+                Some(operator.token.span.clone()),
+            );
+        }
+        let variable = *self.variables.lookup(identifier).unwrap();
+        self.write(variable, value);
+        self.last_value = Some(value);
+    }
+
+    fn visit_binary_expression(&mut self, binary: &ASTBinaryExpression) {
+        let left = self.value(&binary.left);
+        let span = Some(binary.operator.token.span.clone());
+        // `&&` and `||` skip their right operand when the left one decides the result.
+        let decisive = match binary.operator.kind {
+            ASTBinaryOperatorKind::LogicalAnd => false,
+            ASTBinaryOperatorKind::LogicalOr => true,
+            _ => {
+                let right = self.value(&binary.right);
+                let ty = self.binary_type(binary.operator.kind, left, right);
+                let kind = InstructionKind::Binary(binary.operator.kind, left, right);
+                self.last_value = Some(self.builder.emit_value(kind, ty, span));
+                return;
+            }
+        };
+        let left_block = self.builder.current;
+        let right_block = self.builder.new_block();
+        let merge_block = self.builder.new_block();
+        self.builder.terminate(Terminator::ShortCircuit {
+            value: left,
+            decisive,
+            decided: merge_block,
+            undecided: right_block,
+        });
+        self.builder.seal(right_block);
+        self.builder.current = right_block;
+        let right = self.value(&binary.right);
+        let kind = InstructionKind::Binary(binary.operator.kind, left, right);
+        let result = self.builder.emit_value(kind, Type::Boolean, span);
+        let right_block = self.builder.current;
+        self.builder.terminate(Terminator::Jump(merge_block));
+        self.builder.seal(merge_block);
+        self.builder.current = merge_block;
+        let phi = self.builder.new_phi(merge_block, Type::Boolean);
+        self.builder.blocks[merge_block.0]
+            .phis
+            .last_mut()
+            .unwrap()
+            .incoming = vec![(left_block, left), (right_block, result)];
+        self.last_value = Some(phi);
+    }
+
+    fn visit_unary_expression(&mut self, unary: &ASTUnaryExpression) {
+        let operand = self.value(&unary.operand);
+        let ty = match unary.operator.kind {
+            ASTUnaryOperatorKind::LogicalNot => Type::Boolean,
+            ASTUnaryOperatorKind::BitwiseNot => Type::Integer,
+            ASTUnaryOperatorKind::Minus => match self.builder.value_types[operand.0] {
+                ty @ (Type::Float | Type::Unknown) => ty,
+                _ => Type::Integer,
+            },
+        };
+        let kind = InstructionKind::Unary(unary.operator.kind, operand);
+        let span = Some(unary.operator.token.span.clone());
+        self.last_value = Some(self.builder.emit_value(kind, ty, span));
+    }
+
+    fn visit_variable_expression(&mut self, variable: &ASTVariableExpression) {
+        self.last_value = Some(self.read(&variable.identifier().span.literal));
+    }
+
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {
+        let kind = InstructionKind::Constant(Value::Integer(number.number));
+        self.last_value = Some(self.builder.emit_value(kind, Type::Integer, None));
+    }
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        let kind = InstructionKind::Constant(Value::Float(float.number));
+        self.last_value = Some(self.builder.emit_value(kind, Type::Float, None));
+    }
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
+        let kind = InstructionKind::Constant(Value::Boolean(boolean.value));
+        self.last_value = Some(self.builder.emit_value(kind, Type::Boolean, None));
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        let kind = InstructionKind::Constant(Value::Str(string.value.clone()));
+        self.last_value = Some(self.builder.emit_value(kind, Type::String, None));
+    }
+
+    fn visit_parenthesized_expression(&mut self, parenthesized: &ASTParenthesizedExpression) {
+        self.visit_expression(&parenthesized.expression);
+    }
+
+    fn visit_cast_expression(&mut self, cast: &ASTCastExpression) {
+        let operand = self.value(&cast.expression);
+        let target = Type::from_name(&cast.type_name.span.literal).unwrap_or(Type::Unknown);
+        // Casting a value to its own type leaves it as it is.
+        if self.builder.value_types[operand.0] == target {
+            self.last_value = Some(operand);
+            return;
+        }
+        let kind = InstructionKind::Cast(operand, target);
+        let span = Some(cast.as_keyword.span.clone());
+        self.last_value = Some(self.builder.emit_value(kind, target, span));
+    }
+
+    fn visit_error(&mut self, span: &TextSpan) {
+        unreachable!("syntax errors are reported before lowering");
+    }
+}
@@ -0,0 +1,369 @@
+use std::fmt::{Display, Formatter};
+
+use crate::ast::{
+    evaluator::Value, lexer::TextSpan, types::Type, ASTBinaryOperatorKind, ASTUnaryOperatorKind,
+};
+
+pub mod lowering;
+pub mod verifier;
+
+/// A value defined exactly once, by a parameter, an instruction or a phi.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlobalId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FunctionId(pub usize);
+
+/// The top-level code is the function at index `MAIN_FUNCTION`.
+pub const MAIN_FUNCTION: FunctionId = FunctionId(0);
+
+/// Top-level `let` bindings that functions read or assign are kept in globals. All other
+/// variables are SSA values.
+#[derive(Debug)]
+pub struct Program {
+    pub globals: Vec<String>,
+    pub functions: Vec<Function>,
+}
+
+#[derive(Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub span: TextSpan,
+    pub value: ValueId,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    /// The declared return type, if any.
+    pub return_type: Option<Type>,
+    /// The static type of each value. Values of type `Type::Unit` hold no value at runtime;
+    /// those of type `Type::Unknown` may hold anything.
+    pub value_types: Vec<Type>,
+    /// The entry block comes first.
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl Function {
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id.0]
+    }
+
+    pub fn type_of(&self, value: ValueId) -> Type {
+        self.value_types[value.0]
+    }
+
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (index, block) in self.blocks.iter().enumerate() {
+            for successor in block.terminator.successors() {
+                predecessors[successor.0].push(BlockId(index));
+            }
+        }
+        predecessors
+    }
+}
+
+#[derive(Debug)]
+pub struct BasicBlock {
+    pub phis: Vec<Phi>,
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+/// Picks the value coming from the predecessor that control arrived from.
+#[derive(Debug)]
+pub struct Phi {
+    pub result: ValueId,
+    pub incoming: Vec<(BlockId, ValueId)>,
+}
+
+/// A three-address instruction, with the span to blame when it fails.
+#[derive(Debug)]
+pub struct Instruction {
+    pub result: Option<ValueId>,
+    pub kind: InstructionKind,
+    pub span: Option<TextSpan>,
+}
+
+#[derive(Debug)]
+pub enum InstructionKind {
+    Constant(Value),
+    /// The absence of a value, as produced by statements such as `while`.
+    Unit,
+    Unary(ASTUnaryOperatorKind, ValueId),
+    Binary(ASTBinaryOperatorKind, ValueId, ValueId),
+    /// Fails if the operand holds no value.
+    ExpectValue(ValueId),
+    /// Passes on the operand, an untyped value, failing if it is not of the given type.
+    ExpectType(ValueId, Type),
+    /// Converts the operand for an `as` cast, failing if it is not a number.
+    Cast(ValueId, Type),
+    LoadGlobal(GlobalId),
+    StoreGlobal(GlobalId, ValueId),
+    Call(FunctionId, Vec<ValueId>),
+}
+
+impl InstructionKind {
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            InstructionKind::Constant(_)
+            | InstructionKind::Unit
+            | InstructionKind::LoadGlobal(_) => Vec::new(),
+            InstructionKind::Unary(_, operand)
+            | InstructionKind::ExpectValue(operand)
+            | InstructionKind::ExpectType(operand, _)
+            | InstructionKind::Cast(operand, _)
+            | InstructionKind::StoreGlobal(_, operand) => vec![*operand],
+            InstructionKind::Binary(_, left, right) => vec![*left, *right],
+            InstructionKind::Call(_, arguments) => arguments.clone(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            InstructionKind::Constant(_)
+            | InstructionKind::Unit
+            | InstructionKind::LoadGlobal(_) => Vec::new(),
+            InstructionKind::Unary(_, operand)
+            | InstructionKind::ExpectValue(operand)
+            | InstructionKind::ExpectType(operand, _)
+            | InstructionKind::Cast(operand, _)
+            | InstructionKind::StoreGlobal(_, operand) => vec![operand],
+            InstructionKind::Binary(_, left, right) => vec![left, right],
+            InstructionKind::Call(_, arguments) => arguments.iter_mut().collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Fails unless the condition is a boolean.
+    Branch {
+        condition: ValueId,
+        then_block: BlockId,
+        else_block: BlockId,
+        span: TextSpan,
+    },
+    /// Goes to `decided` if the value is the boolean `decisive` and to `undecided` otherwise,
+    /// whatever its type. `&&` and `||` skip their right operand this way.
+    ShortCircuit {
+        value: ValueId,
+        decisive: bool,
+        decided: BlockId,
+        undecided: BlockId,
+    },
+    Return(ValueId),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => vec![*then_block, *else_block],
+            Terminator::ShortCircuit {
+                decided, undecided, ..
+            } => vec![*decided, *undecided],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => vec![then_block, else_block],
+            Terminator::ShortCircuit {
+                decided, undecided, ..
+            } => vec![decided, undecided],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Terminator::Jump(_) => Vec::new(),
+            Terminator::Branch { condition, .. } => vec![*condition],
+            Terminator::ShortCircuit { value, .. } => vec![*value],
+            Terminator::Return(value) => vec![*value],
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Terminator::Jump(_) => Vec::new(),
+            Terminator::Branch { condition, .. } => vec![condition],
+            Terminator::ShortCircuit { value, .. } => vec![value],
+            Terminator::Return(value) => vec![value],
+        }
+    }
+}
+
+impl Display for ValueId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl Display for BlockId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+fn unary_mnemonic(operator: ASTUnaryOperatorKind) -> &'static str {
+    match operator {
+        ASTUnaryOperatorKind::Minus => "neg",
+        ASTUnaryOperatorKind::LogicalNot => "not",
+        ASTUnaryOperatorKind::BitwiseNot => "bitnot",
+    }
+}
+
+fn binary_mnemonic(operator: ASTBinaryOperatorKind) -> &'static str {
+    match operator {
+        ASTBinaryOperatorKind::Plus => "add",
+        ASTBinaryOperatorKind::Minus => "sub",
+        ASTBinaryOperatorKind::Multiply => "mul",
+        ASTBinaryOperatorKind::Divide => "div",
+        ASTBinaryOperatorKind::Equals => "eq",
+        ASTBinaryOperatorKind::NotEquals => "ne",
+        ASTBinaryOperatorKind::LessThan => "lt",
+        ASTBinaryOperatorKind::LessThanOrEqual => "le",
+        ASTBinaryOperatorKind::GreaterThan => "gt",
+        ASTBinaryOperatorKind::GreaterThanOrEqual => "ge",
+        ASTBinaryOperatorKind::LogicalAnd => "and",
+        ASTBinaryOperatorKind::LogicalOr => "or",
+    }
+}
+
+impl Program {
+    fn fmt_instruction(
+        &self,
+        f: &mut Formatter<'_>,
+        function: &Function,
+        instruction: &Instruction,
+    ) -> std::fmt::Result {
+        write!(f, "    ")?;
+        if let Some(result) = instruction.result {
+            write!(f, "{}: {} = ", result, function.type_of(result))?;
+        }
+        match &instruction.kind {
+            InstructionKind::Constant(value) => write!(f, "const {}", value),
+            InstructionKind::Unit => write!(f, "unit"),
+            InstructionKind::Unary(operator, operand) => {
+                write!(f, "{} {}", unary_mnemonic(*operator), operand)
+            }
+            InstructionKind::Binary(operator, left, right) => {
+                write!(f, "{} {}, {}", binary_mnemonic(*operator), left, right)
+            }
+            InstructionKind::ExpectValue(operand) => write!(f, "expect_value {}", operand),
+            InstructionKind::ExpectType(operand, ty) => {
+                write!(f, "expect_type {}, {}", operand, ty)
+            }
+            InstructionKind::Cast(operand, target) => write!(f, "cast {} to {}", operand, target),
+            InstructionKind::LoadGlobal(global) => write!(f, "load @{}", self.globals[global.0]),
+            InstructionKind::StoreGlobal(global, value) => {
+                write!(f, "store @{}, {}", self.globals[global.0], value)
+            }
+            InstructionKind::Call(callee, arguments) => {
+                let arguments: Vec<String> = arguments
+                    .iter()
+                    .map(|argument| argument.to_string())
+                    .collect();
+                write!(
+                    f,
+                    "call {}({})",
+                    self.functions[callee.0].name,
+                    arguments.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for global in &self.globals {
+            writeln!(f, "global @{}", global)?;
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 || !self.globals.is_empty() {
+                writeln!(f)?;
+            }
+            let parameters: Vec<String> = function
+                .parameters
+                .iter()
+                .map(|parameter| {
+                    format!("{}: {}", parameter.value, function.type_of(parameter.value))
+                })
+                .collect();
+            write!(f, "fn {}({})", function.name, parameters.join(", "))?;
+            if let Some(return_type) = function.return_type {
+                write!(f, " -> {}", return_type)?;
+            }
+            writeln!(f, " {{")?;
+            for (index, block) in function.blocks.iter().enumerate() {
+                writeln!(f, "{}:", BlockId(index))?;
+                for phi in &block.phis {
+                    let incoming: Vec<String> = phi
+                        .incoming
+                        .iter()
+                        .map(|(block, value)| format!("[{}: {}]", block, value))
+                        .collect();
+                    writeln!(
+                        f,
+                        "    {}: {} = phi {}",
+                        phi.result,
+                        function.type_of(phi.result),
+                        incoming.join(", ")
+                    )?;
+                }
+                for instruction in &block.instructions {
+                    self.fmt_instruction(f, function, instruction)?;
+                    writeln!(f)?;
+                }
+                match &block.terminator {
+                    Terminator::Jump(target) => writeln!(f, "    jump {}", target)?,
+                    Terminator::Branch {
+                        condition,
+                        then_block,
+                        else_block,
+                        ..
+                    } => writeln!(
+                        f,
+                        "    branch {}, {}, {}",
+                        condition, then_block, else_block
+                    )?,
+                    Terminator::ShortCircuit {
+                        value,
+                        decisive,
+                        decided,
+                        undecided,
+                    } => writeln!(
+                        f,
+                        "    short_circuit {} is {}, {}, {}",
+                        value, decisive, decided, undecided
+                    )?,
+                    Terminator::Return(value) => writeln!(f, "    return {}", value)?,
+                }
+            }
+            writeln!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
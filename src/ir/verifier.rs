@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+use crate::ir::{BlockId, Function, Program, ValueId};
+
+/// Checks the invariants the backends rely on: every block is reachable and terminated,
+/// every value is defined exactly once, definitions dominate their uses, and phis have one
+/// incoming value per predecessor. Returns every violation found.
+pub fn verify(program: &Program) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    for function in &program.functions {
+        FunctionVerifier::new(function, &mut errors).verify();
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Where a value is defined: its block and its position in it. Parameters and phis come
+/// before the first instruction.
+#[derive(Clone, Copy)]
+struct Definition {
+    block: BlockId,
+    position: usize,
+}
+
+struct FunctionVerifier<'a> {
+    function: &'a Function,
+    errors: &'a mut Vec<String>,
+    predecessors: Vec<Vec<BlockId>>,
+    definitions: HashMap<ValueId, Definition>,
+    /// The immediate dominator of each reachable block; the entry block dominates itself.
+    dominators: Vec<Option<BlockId>>,
+}
+
+impl<'a> FunctionVerifier<'a> {
+    fn new(function: &'a Function, errors: &'a mut Vec<String>) -> Self {
+        Self {
+            function,
+            errors,
+            predecessors: function.predecessors(),
+            definitions: HashMap::new(),
+            dominators: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors
+            .push(format!("in function '{}': {}", self.function.name, message));
+    }
+
+    fn verify(&mut self) {
+        if self.function.blocks.is_empty() {
+            self.error("the function has no entry block".to_string());
+            return;
+        }
+        let block_count = self.function.blocks.len();
+        for (index, block) in self.function.blocks.iter().enumerate() {
+            for successor in block.terminator.successors() {
+                if successor.0 >= block_count {
+                    self.error(format!(
+                        "{} jumps to missing block {}",
+                        BlockId(index),
+                        successor
+                    ));
+                    return;
+                }
+            }
+        }
+        self.collect_definitions();
+        self.compute_dominators();
+        for index in 0..block_count {
+            if self.dominators[index].is_none() {
+                self.error(format!("{} is unreachable", BlockId(index)));
+            }
+        }
+        self.check_uses();
+    }
+
+    fn define(&mut self, value: ValueId, definition: Definition) {
+        if value.0 >= self.function.value_types.len() {
+            self.error(format!("{} has no type", value));
+        }
+        if self.definitions.insert(value, definition).is_some() {
+            self.error(format!("{} is defined more than once", value));
+        }
+    }
+
+    fn collect_definitions(&mut self) {
+        let function = self.function;
+        for parameter in &function.parameters {
+            let definition = Definition {
+                block: BlockId(0),
+                position: 0,
+            };
+            self.define(parameter.value, definition);
+        }
+        for (index, block) in function.blocks.iter().enumerate() {
+            for phi in &block.phis {
+                let definition = Definition {
+                    block: BlockId(index),
+                    position: 0,
+                };
+                self.define(phi.result, definition);
+            }
+            for (position, instruction) in block.instructions.iter().enumerate() {
+                if let Some(result) = instruction.result {
+                    let definition = Definition {
+                        block: BlockId(index),
+                        position: position + 1,
+                    };
+                    self.define(result, definition);
+                }
+            }
+        }
+    }
+
+    /// Cooper, Harvey and Kennedy, "A Simple, Fast Dominance Algorithm".
+    fn compute_dominators(&mut self) {
+        let order = self.reverse_postorder();
+        let mut rank = vec![usize::MAX; self.function.blocks.len()];
+        for (position, block) in order.iter().enumerate() {
+            rank[block.0] = position;
+        }
+        let mut dominators = vec![None; self.function.blocks.len()];
+        dominators[0] = Some(BlockId(0));
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in order.iter().skip(1) {
+                let mut new_dominator: Option<BlockId> = None;
+                for predecessor in &self.predecessors[block.0] {
+                    if dominators[predecessor.0].is_none() {
+                        continue;
+                    }
+                    new_dominator = Some(match new_dominator {
+                        None => *predecessor,
+                        Some(other) => Self::intersect(&dominators, &rank, *predecessor, other),
+                    });
+                }
+                if new_dominator.is_some() && dominators[block.0] != new_dominator {
+                    dominators[block.0] = new_dominator;
+                    changed = true;
+                }
+            }
+        }
+        self.dominators = dominators;
+    }
+
+    fn intersect(
+        dominators: &[Option<BlockId>],
+        rank: &[usize],
+        mut left: BlockId,
+        mut right: BlockId,
+    ) -> BlockId {
+        while left != right {
+            while rank[left.0] > rank[right.0] {
+                left = dominators[left.0].unwrap();
+            }
+            while rank[right.0] > rank[left.0] {
+                right = dominators[right.0].unwrap();
+            }
+        }
+        left
+    }
+
+    fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.function.blocks.len()];
+        let mut postorder = Vec::new();
+        // Each entry holds a block and the index of the next successor to visit.
+        let mut stack = vec![(BlockId(0), 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            let successors = self.function.block(block).terminator.successors();
+            match successors.get(next) {
+                Some(successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[successor.0] {
+                        visited[successor.0] = true;
+                        stack.push((*successor, 0));
+                    }
+                }
+                None => postorder.push(block),
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+
+    fn dominates(&self, dominator: BlockId, mut block: BlockId) -> bool {
+        loop {
+            if block == dominator {
+                return true;
+            }
+            match self.dominators[block.0] {
+                Some(parent) if parent != block => block = parent,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Checks that `value` is available at `position` in `block`.
+    fn check_use(&mut self, value: ValueId, block: BlockId, position: usize) {
+        let Some(definition) = self.definitions.get(&value).copied() else {
+            self.error(format!("{} is used in {} but never defined", value, block));
+            return;
+        };
+        let available = if definition.block == block {
+            definition.position < position
+        } else {
+            self.dominates(definition.block, block)
+        };
+        if !available {
+            self.error(format!(
+                "{} is used in {} where its definition does not dominate the use",
+                value, block
+            ));
+        }
+    }
+
+    fn check_uses(&mut self) {
+        let function = self.function;
+        for (index, block) in function.blocks.iter().enumerate() {
+            let id = BlockId(index);
+            if self.dominators[index].is_none() {
+                continue;
+            }
+            for phi in &block.phis {
+                let mut expected = self.predecessors[index].clone();
+                for (predecessor, value) in &phi.incoming {
+                    match expected.iter().position(|block| block == predecessor) {
+                        Some(position) => {
+                            expected.remove(position);
+                        }
+                        None => self.error(format!(
+                            "phi {} in {} has an incoming value from {}, which is not a \
+                             predecessor or is listed twice",
+                            phi.result, id, predecessor
+                        )),
+                    }
+                    // Phi operands are read at the end of the predecessor.
+                    if predecessor.0 < function.blocks.len() {
+                        let end = function.block(*predecessor).instructions.len() + 1;
+                        self.check_use(*value, *predecessor, end);
+                    }
+                }
+                for predecessor in expected {
+                    self.error(format!(
+                        "phi {} in {} has no incoming value from {}",
+                        phi.result, id, predecessor
+                    ));
+                }
+            }
+            for (position, instruction) in block.instructions.iter().enumerate() {
+                for operand in instruction.kind.operands() {
+                    self.check_use(operand, id, position + 1);
+                }
+            }
+            for operand in block.terminator.operands() {
+                self.check_use(operand, id, block.instructions.len() + 1);
+            }
+        }
+    }
+}
@@ -1,25 +1,47 @@
 pub struct SourceText {
+    name: String,
     text: String,
+    /// Where each line starts.
+    line_starts: Vec<usize>,
 }
 
 impl SourceText {
-    pub fn new(text: String) -> Self {
-        SourceText { text }
+    pub fn new(name: String, text: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        SourceText {
+            name,
+            text,
+            line_starts,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
     }
 
     pub fn line_index(&self, position: usize) -> usize {
-        self.text[..position].lines().count() - 1
+        self.line_starts.partition_point(|start| *start <= position) - 1
     }
 
     pub fn get_line(&self, index: usize) -> &str {
-        self.text.lines().nth(index).unwrap()
+        let Some(start) = self.line_starts.get(index) else {
+            return "";
+        };
+        let line = &self.text[*start..];
+        let line = line.split('\n').next().unwrap_or("");
+        line.strip_suffix('\r').unwrap_or(line)
     }
 
     pub fn line_start(&self, index: usize) -> usize {
-        self.text
-            .lines()
-            .take(index)
-            .map(|line| line.len() + 1)
-            .sum()
+        self.line_starts
+            .get(index)
+            .copied()
+            .unwrap_or(self.text.len())
     }
 }
@@ -0,0 +1,218 @@
+use crate::ast::evaluator::{
+    apply_binary_operator, apply_cast, apply_unary_operator, ArithmeticMode, CallFrame,
+    RuntimeError, RuntimeErrorKind, Value, MAX_CALL_DEPTH,
+};
+use crate::ast::lexer::TextSpan;
+use crate::codegen::bytecode::{Instruction, Program, MAIN_FUNCTION};
+
+struct Frame {
+    function: usize,
+    ip: usize,
+    /// Index of the frame's first local in `VM::locals`.
+    base: usize,
+    call_site: Option<TextSpan>,
+}
+
+/// A stack machine that runs the bytecode produced by `BytecodeCompiler`.
+pub struct VM<'a> {
+    program: &'a Program,
+    arithmetic: ArithmeticMode,
+    stack: Vec<Option<Value>>,
+    locals: Vec<Option<Value>>,
+    globals: Vec<Option<Value>>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> VM<'a> {
+    pub fn new(program: &'a Program, arithmetic: ArithmeticMode) -> Self {
+        Self {
+            program,
+            arithmetic,
+            stack: Vec::new(),
+            locals: Vec::new(),
+            globals: vec![None; program.global_count],
+            frames: Vec::new(),
+        }
+    }
+
+    /// Runs the program and returns the value of its last top-level statement.
+    pub fn run(&mut self) -> Result<Option<Value>, RuntimeError> {
+        let program = self.program;
+        self.push_frame(MAIN_FUNCTION, None);
+        // The state of the innermost frame lives in locals while it runs and is only written
+        // back to `self.frames` around calls.
+        let mut function = MAIN_FUNCTION;
+        let mut code = &program.functions[function].chunk.code[..];
+        let mut ip = 0;
+        let mut base = 0;
+
+        loop {
+            let instruction = code[ip];
+            ip += 1;
+
+            match instruction {
+                Instruction::Constant(index) => self
+                    .stack
+                    .push(Some(program.constants[index as usize].clone())),
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::Unit => self.stack.push(None),
+                Instruction::LoadLocal(slot) => {
+                    let value = self.locals[base + slot as usize].clone();
+                    self.stack.push(value);
+                }
+                Instruction::StoreLocal(slot) => {
+                    self.locals[base + slot as usize] = self.pop();
+                }
+                Instruction::LoadGlobal(slot) => {
+                    self.stack.push(self.globals[slot as usize].clone());
+                }
+                Instruction::StoreGlobal(slot) => {
+                    self.globals[slot as usize] = self.pop();
+                }
+                Instruction::ExpectValue => {
+                    if self.stack.last().unwrap().is_none() {
+                        return Err(self.error(RuntimeErrorKind::MissingValue, function, ip - 1));
+                    }
+                }
+                Instruction::ExpectType(expected) => match self.stack.last().unwrap() {
+                    Some(found) if found.ty() == expected => {}
+                    Some(found) => {
+                        let found = found.clone();
+                        let kind = RuntimeErrorKind::TypeMismatch { expected, found };
+                        return Err(self.error(kind, function, ip - 1));
+                    }
+                    None => {
+                        return Err(self.error(RuntimeErrorKind::MissingValue, function, ip - 1))
+                    }
+                },
+                Instruction::Cast(target) => match self.pop() {
+                    Some(value) => match apply_cast(value, target, self.arithmetic) {
+                        Ok(value) => self.stack.push(Some(value)),
+                        Err(kind) => return Err(self.error(kind, function, ip - 1)),
+                    },
+                    None => {
+                        return Err(self.error(RuntimeErrorKind::MissingValue, function, ip - 1))
+                    }
+                },
+                Instruction::Unary(operator) => {
+                    let operand = self.pop().unwrap();
+                    let symbol = Self::symbol(program, function, ip - 1);
+                    match apply_unary_operator(operator, symbol, operand, self.arithmetic) {
+                        Ok(value) => self.stack.push(Some(value)),
+                        Err(kind) => return Err(self.error(kind, function, ip - 1)),
+                    }
+                }
+                Instruction::Binary(operator) => {
+                    let right = self.pop().unwrap();
+                    let left = self.pop().unwrap();
+                    let symbol = Self::symbol(program, function, ip - 1);
+                    match apply_binary_operator(operator, symbol, left, right, self.arithmetic) {
+                        Ok(value) => self.stack.push(Some(value)),
+                        Err(kind) => return Err(self.error(kind, function, ip - 1)),
+                    }
+                }
+                Instruction::Jump(target) => ip = target as usize,
+                Instruction::JumpIfFalse(target) => match self.pop() {
+                    Some(Value::Boolean(true)) => {}
+                    Some(Value::Boolean(false)) => ip = target as usize,
+                    Some(found) => {
+                        let kind = RuntimeErrorKind::NonBooleanCondition { found };
+                        return Err(self.error(kind, function, ip - 1));
+                    }
+                    None => {
+                        return Err(self.error(RuntimeErrorKind::MissingValue, function, ip - 1))
+                    }
+                },
+                Instruction::JumpIfBoolean(decisive, target) => {
+                    if self.pop() == Some(Value::Boolean(decisive)) {
+                        ip = target as usize;
+                    }
+                }
+                Instruction::Call(callee) => {
+                    let callee = callee as usize;
+                    if self.frames.len() > MAX_CALL_DEPTH {
+                        let name = program.functions[callee].name.clone();
+                        let kind = RuntimeErrorKind::StackOverflow { function: name };
+                        return Err(self.error(kind, function, ip - 1));
+                    }
+                    let frame = self.frames.last_mut().unwrap();
+                    frame.ip = ip;
+                    let call_site = program.functions[function].chunk.spans[ip - 1].clone();
+                    self.push_frame(callee, call_site);
+
+                    function = callee;
+                    code = &program.functions[function].chunk.code[..];
+                    ip = 0;
+                    base = self.frames.last().unwrap().base;
+                }
+                Instruction::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.locals.truncate(frame.base);
+                    let Some(caller) = self.frames.last_mut() else {
+                        return Ok(result);
+                    };
+                    self.stack.push(result);
+
+                    function = caller.function;
+                    code = &program.functions[function].chunk.code[..];
+                    ip = caller.ip;
+                    base = caller.base;
+                }
+            }
+        }
+    }
+
+    /// Enters `function`, moving its arguments from the stack into its first locals.
+    fn push_frame(&mut self, function: usize, call_site: Option<TextSpan>) {
+        let base = self.locals.len();
+        let function_ref = &self.program.functions[function];
+        self.locals.resize(base + function_ref.local_count, None);
+        let argument_count = function_ref.parameter_count;
+        let arguments = self.stack.len() - argument_count;
+        for (offset, argument) in self.stack.drain(arguments..).enumerate() {
+            self.locals[base + offset] = argument;
+        }
+        self.frames.push(Frame {
+            function,
+            ip: 0,
+            base,
+            call_site,
+        });
+    }
+
+    fn pop(&mut self) -> Option<Value> {
+        self.stack.pop().unwrap()
+    }
+
+    /// How the operator at `ip` is written in the source, for error messages.
+    fn symbol(program: &Program, function: usize, ip: usize) -> &str {
+        program.functions[function].chunk.spans[ip]
+            .as_ref()
+            .map(|span| span.literal.as_str())
+            .unwrap_or("")
+    }
+
+    /// Builds the error for the instruction at `ip` of `function`, which is the innermost frame.
+    fn error(&self, kind: RuntimeErrorKind, function: usize, ip: usize) -> RuntimeError {
+        let stack_trace = self
+            .frames
+            .iter()
+            .filter_map(|frame| {
+                Some(CallFrame {
+                    function: self.program.functions[frame.function].name.clone(),
+                    call_site: frame.call_site.clone()?,
+                })
+            })
+            .collect();
+        RuntimeError {
+            kind,
+            span: self.program.functions[function].chunk.spans[ip]
+                .clone()
+                .unwrap(),
+            stack_trace,
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Runs every program of `tests/corpus` with both backends of `run`, in both arithmetic modes,
+//! and checks that the virtual machine prints and exits exactly like the tree-walking evaluator.
+//! The programs are also compiled to C and to native executables, which have to print the same
+//! output and exit with the same status as the evaluator, unless they use floats or strings.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// The exit status for programs rejected before they reach a backend.
+const SEMANTIC_ERRORS: i32 = 3;
+
+/// The corpus programs that use floats or strings, which only `run` supports.
+const RUN_ONLY_PROGRAMS: &[&str] = &[
+    "float_cast_overflow",
+    "floats",
+    "mixed_operands",
+    "nan_cast",
+    "strings",
+];
+
+/// The corpus programs the C translation rejects because the type of a parameter or returned
+/// value is not known statically. Every other program has to translate.
+const UNTYPED_PROGRAMS: &[&str] = &[
+    "checked_argument",
+    "checked_assignment",
+    "checked_return",
+    "inferred_mixed_calls",
+    "missing_operand",
+    "missing_return_value",
+    "short_circuit",
+    "stack_overflow",
+    "untyped_comparison",
+];
+
+fn corpus() -> Vec<PathBuf> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut paths: Vec<PathBuf> = fs::read_dir(&directory)
+        .expect("the corpus directory exists")
+        .map(|entry| entry.expect("the corpus directory is readable").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn run(path: &Path, options: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust-compiler"))
+        .arg("run")
+        .args(options)
+        .arg(path)
+        .output()
+        .expect("the compiler runs")
+}
+
+fn build(path: &Path, options: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust-compiler"))
+        .arg("build")
+        .args(options)
+        .arg(path)
+        .output()
+        .expect("the compiler runs")
+}
+
+/// Whether a C compiler is installed, which the C translation and the linker both need.
+fn has_c_compiler() -> bool {
+    let found = Command::new("cc").arg("--version").output().is_ok();
+    if !found {
+        eprintln!("skipping: no C compiler found as `cc`");
+    }
+    found
+}
+
+/// Where a test writes the executable it builds from `path`.
+fn executable(path: &Path, backend: &str, arithmetic: Option<&str>) -> PathBuf {
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    let mode = if arithmetic.is_some() {
+        "wrapping"
+    } else {
+        "checked"
+    };
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}-{}-{}", stem, backend, mode))
+}
+
+fn execute(executable: &Path) -> Output {
+    Command::new(executable)
+        .output()
+        .expect("the executable runs")
+}
+
+/// Compares what an executable printed with what the evaluator printed for the same program.
+/// Compiled programs do not report warnings, so standard error is not compared.
+fn compare_with_evaluator(
+    path: &Path,
+    arithmetic: Option<&str>,
+    compiled: &Output,
+    mismatches: &mut Vec<String>,
+) {
+    let options: Vec<&str> = arithmetic.into_iter().chain(["--backend=tree"]).collect();
+    let tree = run(path, &options);
+    if tree.status.code() != compiled.status.code() || tree.stdout != compiled.stdout {
+        mismatches.push(format!(
+            "{} {}\n--- tree\n{}\n--- compiled\n{}",
+            path.display(),
+            arithmetic.unwrap_or(""),
+            describe(&tree),
+            describe(compiled)
+        ));
+    }
+}
+
+fn is_listed(path: &Path, programs: &[&str]) -> bool {
+    programs.contains(&&*path.file_stem().unwrap().to_string_lossy())
+}
+
+fn describe(output: &Output) -> String {
+    format!(
+        "status: {:?}\nstdout:\n{}\nstderr:\n{}",
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+#[test]
+fn vm_agrees_with_the_evaluator_on_the_corpus() {
+    let paths = corpus();
+    assert!(!paths.is_empty(), "the corpus is empty");
+
+    let mut mismatches = Vec::new();
+    for path in &paths {
+        for arithmetic in [None, Some("--wrapping")] {
+            let options = |backend| arithmetic.into_iter().chain([backend]).collect::<Vec<_>>();
+            let tree = run(path, &options("--backend=tree"));
+            let vm = run(path, &options("--backend=vm"));
+            assert_ne!(
+                tree.status.code(),
+                Some(SEMANTIC_ERRORS),
+                "{} does not pass the checks, so it exercises neither backend:\n{}",
+                path.display(),
+                describe(&tree)
+            );
+            if tree.status.code() != vm.status.code()
+                || tree.stdout != vm.stdout
+                || tree.stderr != vm.stderr
+            {
+                mismatches.push(format!(
+                    "{} {}\n--- tree\n{}\n--- vm\n{}",
+                    path.display(),
+                    arithmetic.unwrap_or(""),
+                    describe(&tree),
+                    describe(&vm)
+                ));
+            }
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n\n"));
+}
+
+#[test]
+fn c_translations_agree_with_the_evaluator_on_the_corpus() {
+    if !has_c_compiler() {
+        return;
+    }
+    let mut mismatches = Vec::new();
+    for path in &corpus() {
+        let rejected = is_listed(path, RUN_ONLY_PROGRAMS) || is_listed(path, UNTYPED_PROGRAMS);
+        for arithmetic in [None, Some("--wrapping")] {
+            let options: Vec<&str> = arithmetic.into_iter().chain(["--emit=c"]).collect();
+            let translation = build(path, &options);
+            if rejected {
+                assert_eq!(
+                    translation.status.code(),
+                    Some(SEMANTIC_ERRORS),
+                    "{} is expected to be rejected:\n{}",
+                    path.display(),
+                    describe(&translation)
+                );
+                continue;
+            }
+            assert!(
+                translation.status.success(),
+                "{} does not translate to C:\n{}",
+                path.display(),
+                describe(&translation)
+            );
+            let executable = executable(path, "c", arithmetic);
+            let source = executable.with_extension("c");
+            fs::write(&source, &translation.stdout).expect("the translation can be written");
+            let compilation = Command::new("cc")
+                .args(["-std=c11", "-o"])
+                .arg(&executable)
+                .arg(&source)
+                .output()
+                .expect("the C compiler runs");
+            assert!(
+                compilation.status.success(),
+                "the translation of {} does not compile:\n{}",
+                path.display(),
+                describe(&compilation)
+            );
+            compare_with_evaluator(path, arithmetic, &execute(&executable), &mut mismatches);
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n\n"));
+}
+
+#[test]
+fn native_executables_agree_with_the_evaluator_on_the_corpus() {
+    if !has_c_compiler() {
+        return;
+    }
+    let mut mismatches = Vec::new();
+    for path in &corpus() {
+        for arithmetic in [None, Some("--wrapping")] {
+            let executable = executable(path, "native", arithmetic);
+            let output = executable.to_string_lossy().into_owned();
+            let options: Vec<&str> = arithmetic.into_iter().chain(["-o", &output]).collect();
+            let build = build(path, &options);
+            if is_listed(path, RUN_ONLY_PROGRAMS) {
+                assert_eq!(
+                    build.status.code(),
+                    Some(SEMANTIC_ERRORS),
+                    "{} is expected to be rejected:\n{}",
+                    path.display(),
+                    describe(&build)
+                );
+                continue;
+            }
+            assert!(
+                build.status.success(),
+                "{} does not build:\n{}",
+                path.display(),
+                describe(&build)
+            );
+            compare_with_evaluator(path, arithmetic, &execute(&executable), &mut mismatches);
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n\n"));
+}
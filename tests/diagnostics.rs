@@ -0,0 +1,206 @@
+//! Checks that each mistake in a program is reported once, without errors cascading from it,
+//! and with a message that says what is wrong.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The messages `check` reports for `source`, in order.
+fn diagnostics(source: &str) -> Vec<String> {
+    messages(&["check"], source)
+}
+
+/// The messages the compiler reports when given `source` with the arguments `command`.
+fn messages(command: &[&str], source: &str) -> Vec<String> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust-compiler"))
+        .args(command)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the compiler runs");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().expect("the compiler runs");
+    let text = String::from_utf8_lossy(&output.stdout).into_owned()
+        + &String::from_utf8_lossy(&output.stderr);
+    strip_colors(&text)
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("+-- "))
+        .map(str::to_string)
+        .collect()
+}
+
+fn strip_colors(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+fn assert_single_diagnostic(source: &str) -> String {
+    let mut diagnostics = diagnostics(source);
+    assert_eq!(diagnostics.len(), 1, "{:?} for:\n{}", diagnostics, source);
+    diagnostics.pop().unwrap()
+}
+
+#[test]
+fn missing_open_brace_is_reported_once() {
+    for source in [
+        "fn f()\n{ 1 }\nf()\n",
+        "while false\n{ }\n1\n",
+        "if true\n{ 1 }\n2\n",
+        "fn f() 1\nf()\n",
+        "while false 1\n2\n",
+        "let x = if true 1 else 2\nx\n",
+    ] {
+        assert_single_diagnostic(source);
+    }
+}
+
+#[test]
+fn block_on_the_line_after_its_header_is_reported_as_such() {
+    for source in [
+        "fn f() -> int\n{\n    1\n}\nf()\n",
+        "let c = false\nwhile c\n{ }\n",
+        "let c = true\nif c\n{ 1 }\n",
+    ] {
+        let diagnostic = assert_single_diagnostic(source);
+        assert_eq!(
+            diagnostic, "A block must start on the same line as the statement it belongs to",
+            "for:\n{}",
+            source
+        );
+    }
+    assert_eq!(
+        diagnostics("let c = true\nif c { 1 }\nelse\n{ 2 }\n"),
+        [] as [String; 0]
+    );
+}
+
+#[test]
+fn out_of_range_literals_are_reported() {
+    assert_eq!(
+        assert_single_diagnostic("99999999999999999999 + 1\n"),
+        "Integer literal '99999999999999999999' does not fit in <int>"
+    );
+    // Only a minus sign makes room for the magnitude of the smallest int.
+    for source in [
+        "9223372036854775808\n",
+        "-(9223372036854775808)\n",
+        "1 - 9223372036854775808\n",
+    ] {
+        assert_eq!(
+            assert_single_diagnostic(source),
+            "Integer literal '9223372036854775808' does not fit in <int>"
+        );
+    }
+    assert_eq!(diagnostics("-9223372036854775808\n"), [] as [String; 0]);
+    assert_eq!(
+        assert_single_diagnostic("let x = 1e400\nx\n"),
+        "Float literal '1e400' does not fit in <float>"
+    );
+    assert_eq!(
+        diagnostics("1e-400 + 1.7976931348623157e308\n"),
+        [] as [String; 0]
+    );
+}
+
+#[test]
+fn runtime_type_errors_name_the_types_of_the_values() {
+    for (source, expected) in [
+        (
+            "fn f(x) { x + 1 }\nf(2)\nf(true)\n",
+            "Operator '+' cannot be applied to <bool> (true) and <int> (1)",
+        ),
+        (
+            "fn f(x) { x + x }\nf(2)\nf(\"ab\")\nf(1.5) + f(\"ab\")\n",
+            "Operator '+' cannot be applied to <float> (3.0) and <string> (\"abab\")",
+        ),
+        (
+            "fn f(x) { if x { 1 } else { 2 } }\nf(true)\nf(3)\n",
+            "Expected a boolean condition, found <int> (3)",
+        ),
+        (
+            "fn f(x) { x }\nf(3)\nlet a: int = f(false)\na\n",
+            "Expected a value of type <int>, found <bool> (false)",
+        ),
+    ] {
+        for backend in ["--backend=tree", "--backend=vm"] {
+            let messages = messages(&["run", backend], source);
+            assert!(
+                messages.iter().any(|message| message == expected),
+                "{:?} with {} for:\n{}",
+                messages,
+                backend,
+                source
+            );
+        }
+    }
+}
+
+#[test]
+fn nested_function_is_reported_once() {
+    for (source, name) in [
+        (
+            "fn outer(n: int) -> int {\n    fn inner() -> int { n + 1 }\n    inner()\n}\nouter(1)\n",
+            "inner",
+        ),
+        (
+            "fn outer() -> int {\n    let x = inner()\n    fn inner() -> int { 1 }\n    x\n}\nouter()\n",
+            "inner",
+        ),
+        (
+            "fn outer() {\n    fn inner() { }\n}\nfn inner() { }\ninner()\n",
+            "inner",
+        ),
+        (
+            "let a = 1\nif a > 0 {\n    fn g() -> int { a }\n}\ng()\n",
+            "g",
+        ),
+    ] {
+        assert_eq!(
+            assert_single_diagnostic(source),
+            format!("Function '{}' must be declared at the top level", name),
+            "for:\n{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn out_of_scope_variables_are_told_apart_from_undeclared_ones() {
+    let out_of_scope = "Variable 't' is used outside of the scope that declares it";
+    let undeclared = "Undeclared variable 't'";
+    for (source, expected) in [
+        ("{ let t = 1 }\nt\n", out_of_scope),
+        (
+            "fn f() -> int {\n    { let t = 1 }\n    t\n}\nf()\n",
+            out_of_scope,
+        ),
+        // The scopes of other functions do not count.
+        (
+            "fn f() { { let t = 1 } }\nfn g() -> int { t }\ng()\n",
+            undeclared,
+        ),
+        ("{ let t = 1 }\nfn g() -> int { t }\ng()\n", undeclared),
+        ("fn f() { { let t = 1 } }\nt\n", undeclared),
+    ] {
+        assert_eq!(
+            assert_single_diagnostic(source),
+            expected,
+            "for:\n{}",
+            source
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! Checks the structure of the modules `build --emit=wat` prints, without running them: the text
+//! has to parse as a single `module` with the imports, exports and signatures the runtime expects.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// The exit status for programs rejected by the checks or by a backend.
+const SEMANTIC_ERRORS: i32 = 3;
+
+/// An S-expression of the WebAssembly text format. Strings keep their quotes and escapes.
+#[derive(Debug)]
+enum SExpression {
+    Atom(String),
+    List(Vec<SExpression>),
+}
+
+impl SExpression {
+    fn atom(&self) -> Option<&str> {
+        match self {
+            SExpression::Atom(atom) => Some(atom),
+            SExpression::List(_) => None,
+        }
+    }
+
+    fn items(&self) -> &[SExpression] {
+        match self {
+            SExpression::Atom(_) => &[],
+            SExpression::List(items) => items,
+        }
+    }
+
+    /// The keyword a list starts with, like `func` or `param`.
+    fn head(&self) -> Option<&str> {
+        self.items().first().and_then(SExpression::atom)
+    }
+
+    /// The lists directly inside this one that start with `head`.
+    fn fields<'a>(&'a self, head: &'a str) -> impl Iterator<Item = &'a SExpression> {
+        self.items()
+            .iter()
+            .filter(move |item| item.head() == Some(head))
+    }
+
+    /// The function with the given `$` name.
+    fn function(&self, name: &str) -> &SExpression {
+        self.fields("func")
+            .find(|function| function.items().get(1).and_then(SExpression::atom) == Some(name))
+            .unwrap_or_else(|| panic!("no function {} in the module", name))
+    }
+
+    /// The signature of a function as written, like `(param $x i64) (result i32)`. Block types
+    /// in the body, like the one of `if (result i64)`, are not part of it.
+    fn signature(&self) -> String {
+        self.items()
+            .iter()
+            .skip(2)
+            .take_while(|item| matches!(item.head(), Some("export" | "param" | "result")))
+            .filter(|item| item.head() != Some("export"))
+            .map(SExpression::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl std::fmt::Display for SExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SExpression::Atom(atom) => write!(f, "{}", atom),
+            SExpression::List(items) => {
+                let items: Vec<String> = items.iter().map(SExpression::to_string).collect();
+                write!(f, "({})", items.join(" "))
+            }
+        }
+    }
+}
+
+/// Parses every top-level S-expression of `text`, panicking on unbalanced parentheses or
+/// unterminated strings. Instructions outside parentheses become atoms of their function.
+fn parse(text: &str) -> Vec<SExpression> {
+    let mut stack: Vec<Vec<SExpression>> = vec![Vec::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => stack.push(Vec::new()),
+            ')' => {
+                let list = stack.pop().expect("balanced parentheses");
+                stack
+                    .last_mut()
+                    .unwrap_or_else(|| panic!("unmatched ')' in:\n{}", text))
+                    .push(SExpression::List(list));
+            }
+            ';' if chars.peek() == Some(&';') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                let mut string = String::from('"');
+                loop {
+                    let c = chars.next().expect("terminated strings");
+                    string.push(c);
+                    match c {
+                        '\\' => string.push(chars.next().expect("complete escapes")),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                stack.last_mut().unwrap().push(SExpression::Atom(string));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut atom = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || matches!(next, '(' | ')' | '"') {
+                        break;
+                    }
+                    atom.push(next);
+                    chars.next();
+                }
+                stack.last_mut().unwrap().push(SExpression::Atom(atom));
+            }
+        }
+    }
+    assert_eq!(stack.len(), 1, "unclosed '(' in:\n{}", text);
+    stack.pop().unwrap()
+}
+
+fn emit(source: &str, options: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust-compiler"))
+        .args(["build", "--emit=wat"])
+        .args(options)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the compiler runs");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    child.wait_with_output().expect("the compiler runs")
+}
+
+/// The module `source` compiles to.
+fn module(source: &str, options: &[&str]) -> SExpression {
+    let output = emit(source, options);
+    let text = String::from_utf8(output.stdout).expect("the module is UTF-8");
+    assert!(
+        output.status.success(),
+        "compiling failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut forms = parse(&text);
+    assert_eq!(forms.len(), 1, "expected a single module in:\n{}", text);
+    let module = forms.pop().unwrap();
+    assert_eq!(module.head(), Some("module"), "in:\n{}", text);
+    module
+}
+
+/// Every `$` identifier in `expression`.
+fn identifiers(expression: &SExpression) -> Vec<&str> {
+    match expression {
+        SExpression::Atom(atom) => atom.strip_prefix('$').into_iter().collect(),
+        SExpression::List(items) => items.iter().flat_map(identifiers).collect(),
+    }
+}
+
+/// The value of a global that is initialized with a constant, like `(i32.const 1)`.
+fn global_value<'a>(module: &'a SExpression, name: &str) -> &'a str {
+    let global = module
+        .fields("global")
+        .find(|global| global.items().get(1).and_then(SExpression::atom) == Some(name))
+        .unwrap_or_else(|| panic!("no global {} in the module", name));
+    let initializer = global.items().last().unwrap();
+    initializer.items()[1].atom().unwrap()
+}
+
+#[test]
+fn module_imports_the_runtime_and_exports_main_and_its_memory() {
+    let module = module("let a = 1\na + 2\n", &[]);
+
+    let imports: Vec<String> = module.fields("import").map(|i| i.to_string()).collect();
+    assert_eq!(
+        imports,
+        [r#"(import "rt" "fail" (func $rt_fail (param i32)))"#]
+    );
+    let memory = module.fields("memory").next().expect("a memory");
+    assert_eq!(memory.to_string(), r#"(memory (export "memory") 1)"#);
+
+    let exports: Vec<String> = module
+        .fields("func")
+        .filter_map(|function| function.fields("export").next())
+        .map(|export| export.to_string())
+        .collect();
+    assert_eq!(exports, [r#"(export "main")"#]);
+}
+
+#[test]
+fn main_returns_the_value_of_the_last_statement() {
+    let main = |source| module(source, &[]).function("$rt_main").signature();
+    assert_eq!(main("1 + 2\n"), "(result i64)");
+    assert_eq!(main("1 < 2\n"), "(result i32)");
+    assert_eq!(main("let mut i = 0\nwhile i < 3 { i += 1 }\n"), "");
+}
+
+#[test]
+fn integers_are_i64_and_booleans_i32() {
+    let module = module(
+        "fn pick(flag: bool, a: int, b: int) -> int { if flag { a } else { b } }\n\
+         fn positive(n: int) -> bool { n > 0 }\n\
+         fn reset() { }\n\
+         let flag = positive(pick(true, 1, 2))\n\
+         reset()\n\
+         flag\n",
+        &[],
+    );
+    assert_eq!(
+        module.function("$pick").signature(),
+        "(param $flag i32) (param $a i64) (param $b i64) (result i64)"
+    );
+    assert_eq!(
+        module.function("$positive").signature(),
+        "(param $n i64) (result i32)"
+    );
+    assert_eq!(module.function("$reset").signature(), "");
+    let global = module
+        .fields("global")
+        .find(|g| g.to_string().contains("$flag"));
+    assert_eq!(
+        global.expect("a global for `flag`").to_string(),
+        "(global $flag (mut i32) (i32.const 0))"
+    );
+}
+
+#[test]
+fn unannotated_functions_use_their_inferred_types() {
+    let module = module(
+        "fn id(x) { return x }\n\
+         fn negate(b) { !b }\n\
+         id(1) + 1\n\
+         negate(false)\n",
+        &[],
+    );
+    assert_eq!(
+        module.function("$id").signature(),
+        "(param $x i64) (result i64)"
+    );
+    assert_eq!(
+        module.function("$negate").signature(),
+        "(param $b i32) (result i32)"
+    );
+}
+
+#[test]
+fn non_ascii_identifiers_are_escaped() {
+    let module = module(
+        "fn café(größe: int) -> int { let maß = größe * 2; maß }\n\
+         let größe = café(1)\n\
+         größe\n",
+        &[],
+    );
+    // The characters the text format allows in identifiers.
+    let is_identifier_char = |c: char| c.is_ascii_graphic() && !"\"(),;[]{}".contains(c);
+    for identifier in identifiers(&module) {
+        assert!(
+            !identifier.is_empty() && identifier.chars().all(is_identifier_char),
+            "invalid identifier ${}",
+            identifier
+        );
+    }
+    let function = module.function("$caf%C3%A9");
+    assert_eq!(
+        function.signature(),
+        "(param $gr%C3%B6%C3%9Fe i64) (result i64)"
+    );
+    let locals: Vec<String> = function.fields("local").map(|l| l.to_string()).collect();
+    assert_eq!(locals, ["(local $ma%C3%9F i64)"]);
+    assert_eq!(global_value(&module, "$gr%C3%B6%C3%9Fe"), "0");
+}
+
+#[test]
+fn shadowed_variables_get_distinct_names() {
+    let module = module(
+        "let x = 1\n\
+         fn f(x: int) -> int { let x = x + 1; { let x = x * 2; x } }\n\
+         f(x)\n",
+        &[],
+    );
+    let f = module.function("$f");
+    let locals: Vec<String> = f.fields("local").map(|local| local.to_string()).collect();
+    assert_eq!(locals, ["(local $x2 i64)", "(local $x3 i64)"]);
+    assert_eq!(f.signature(), "(param $x i64) (result i64)");
+}
+
+#[test]
+fn runtime_errors_are_stored_in_the_data_segment() {
+    let module = module("fn div(a: int, b: int) -> int { a / b }\ndiv(1, 0)\n", &[]);
+    let data = module.fields("data").next().expect("a data segment");
+    let text = data.items().last().unwrap().atom().unwrap();
+    assert!(
+        text.starts_with('"') && text.ends_with("\\00\""),
+        "{}",
+        text
+    );
+    assert!(text.contains("Division by zero"), "{}", text);
+    assert!(text.contains("Integer overflow"), "{}", text);
+}
+
+#[test]
+fn wrapping_is_a_runtime_flag() {
+    assert_eq!(global_value(&module("1\n", &[]), "$rt_wrapping"), "0");
+    assert_eq!(
+        global_value(&module("1\n", &["--wrapping"]), "$rt_wrapping"),
+        "1"
+    );
+}
+
+#[test]
+fn unsupported_programs_are_rejected() {
+    for source in [
+        "1.5\n",
+        "let s = \"text\"\n",
+        // Folding does not make a string comparison supported.
+        "\"abc\" == \"abc\"\n",
+        "fn f(x) { x }\nf(1)\nf(true)\n",
+        // The return type of a function that never returns is not known.
+        "fn f(n) { return f(n + 1) }\nf(0)\n",
+        // `main` cannot return an int on one path and nothing on the other.
+        "let c = true\nif c { 1 }\n",
+    ] {
+        let output = emit(source, &[]);
+        assert_eq!(output.status.code(), Some(SEMANTIC_ERRORS), "{}", source);
+        assert!(output.stdout.is_empty(), "{}", source);
+    }
+}